@@ -0,0 +1,582 @@
+//! Built-in middleware ready to plug into `use_middleware`.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use tokio::sync::watch;
+
+use crate::{BytesSent, Next, RequestCtx, Response, ResponseBuilder, context::media_type};
+
+/// Reject body-carrying requests whose `Content-Type` doesn't match `expected`
+///
+/// Only `POST`, `PUT` and `PATCH` requests with a non-empty body are checked.
+/// The body is judged by `Content-Length` and `Transfer-Encoding` too, so uploads
+/// still on the connection, as for [`streaming`](crate::streaming) routes or
+/// `Expect: 100-continue`, are checked before anything is read.
+/// Parameters such as `charset` are ignored and the comparison is case-insensitive,
+/// so `application/json; charset=utf-8` satisfies `require_content_type("application/json")`.
+/// Mismatches are answered with `415 Unsupported Media Type`.
+pub fn require_content_type(
+    expected: &str,
+) -> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    let expected = expected.trim().to_ascii_lowercase();
+    move |ctx, next| {
+        let carries_body = matches!(ctx.request.method().as_str(), "POST" | "PUT" | "PATCH")
+            && (ctx.body.is_some()
+                || ctx
+                    .declared_content_length()
+                    .is_some_and(|length| length > 0)
+                || ctx
+                    .request
+                    .headers()
+                    .contains_key(hyper::header::TRANSFER_ENCODING));
+        let matches = ctx
+            .request
+            .headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| media_type(value) == expected)
+            .unwrap_or(false);
+
+        Box::pin(async move {
+            if carries_body && !matches {
+                return ResponseBuilder::new()
+                    .status(hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                    .content_type("text/plain; charset=utf-8")
+                    .body("415 Unsupported Media Type");
+            }
+            next(ctx).await
+        })
+    }
+}
+
+/// A buffered response that can be handed to every coalesced request
+#[derive(Clone)]
+struct SharedResponse {
+    status: hyper::StatusCode,
+    headers: hyper::HeaderMap,
+    body: Bytes,
+}
+
+impl SharedResponse {
+    fn to_response(&self) -> Response {
+        let mut response = ResponseBuilder::new()
+            .status(self.status)
+            .body(self.body.clone());
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+type InFlight = Arc<Mutex<HashMap<String, watch::Receiver<Option<SharedResponse>>>>>;
+
+/// Coalesce concurrent identical `GET` requests into a single handler run
+///
+/// While a `GET` for a given path and query is being handled, further requests
+/// for the same path and query wait for it and receive a copy of its response
+/// instead of running the handler again. The response body is buffered to be
+/// shared. Requests arriving after the first one finishes run the handler anew,
+/// so this isn't a cache. Other methods pass straight through, as do requests
+/// carrying `Authorization` or `Cookie`, whose responses may be per-user.
+pub fn single_flight()
+-> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    let in_flight: InFlight = Arc::default();
+    move |ctx, next| {
+        let in_flight = in_flight.clone();
+        Box::pin(async move {
+            let headers = ctx.request.headers();
+            let credentialed = headers.contains_key(hyper::header::AUTHORIZATION)
+                || headers.contains_key(hyper::header::COOKIE);
+            if ctx.request.method() != hyper::Method::GET || credentialed {
+                return next(ctx).await;
+            }
+            let key = ctx
+                .uri()
+                .path_and_query()
+                .map_or("/", |pq| pq.as_str())
+                .to_string();
+
+            let leader = {
+                let mut in_flight = in_flight.lock().unwrap();
+                match in_flight.get(&key) {
+                    // A receiver whose sender is gone belongs to a dropped leader
+                    Some(waiting) if waiting.has_changed().is_ok() => Err(waiting.clone()),
+                    _ => {
+                        let (sender, receiver) = watch::channel(None);
+                        in_flight.insert(key.clone(), receiver);
+                        Ok(sender)
+                    }
+                }
+            };
+
+            match leader {
+                Ok(sender) => {
+                    let response = next(ctx).await;
+                    let (parts, body) = response.into_parts();
+                    let shared = match body.collect().await {
+                        Ok(body) => SharedResponse {
+                            status: parts.status,
+                            headers: parts.headers,
+                            body: body.to_bytes(),
+                        },
+                        Err(_) => SharedResponse {
+                            status: hyper::StatusCode::INTERNAL_SERVER_ERROR,
+                            headers: hyper::HeaderMap::new(),
+                            body: Bytes::from_static(b"500 Internal Server Error"),
+                        },
+                    };
+                    in_flight.lock().unwrap().remove(&key);
+                    let _ = sender.send(Some(shared.clone()));
+                    shared.to_response()
+                }
+                Err(mut waiting) => {
+                    let shared = waiting
+                        .wait_for(Option::is_some)
+                        .await
+                        .ok()
+                        .and_then(|shared| shared.clone());
+                    match shared {
+                        Some(shared) => shared.to_response(),
+                        // The leading request was dropped before finishing; run our own
+                        None => next(ctx).await,
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Set `Content-Type: application/json` on untyped responses whose body is JSON
+///
+/// Only responses without a `Content-Type` and with a non-empty body are
+/// inspected; their body is buffered and checked with `serde_json`. Anything
+/// that doesn't parse as JSON is passed on unchanged.
+pub fn default_json_content_type()
+-> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    |ctx, next| {
+        Box::pin(async move {
+            let response = next(ctx).await;
+            if response.headers().contains_key(hyper::header::CONTENT_TYPE) {
+                return response;
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let Ok(body) = body.collect().await.map(|body| body.to_bytes()) else {
+                return ResponseBuilder::internal_error();
+            };
+            if !body.is_empty() && serde_json::from_slice::<serde::de::IgnoredAny>(&body).is_ok() {
+                parts.headers.insert(
+                    hyper::header::CONTENT_TYPE,
+                    hyper::header::HeaderValue::from_static("application/json"),
+                );
+            }
+
+            let body = http_body_util::Full::new(body)
+                .map_err(|never| match never {})
+                .boxed();
+            Response::from_parts(parts, body)
+        })
+    }
+}
+
+/// Reject requests whose path has more than `max` segments with `414 URI Too Long`
+///
+/// Segments are the non-empty parts between slashes, so `/a/b/c` and `/a//b/c/`
+/// both have three. The rejection is built with
+/// [`ResponseBuilder::error_for`] and neither later middleware nor the handler
+/// runs. The engine's own route lookup happens before any middleware, so pair
+/// this with [`Engine::max_uri_length`](crate::Engine::max_uri_length) to bound
+/// that as well.
+pub fn max_path_depth(
+    max: usize,
+) -> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    move |ctx, next| {
+        Box::pin(async move {
+            let depth = ctx
+                .path()
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .count();
+            if depth > max {
+                return ResponseBuilder::error_for(&ctx, hyper::StatusCode::URI_TOO_LONG);
+            }
+            next(ctx).await
+        })
+    }
+}
+
+/// How [`normalize_path`] treats a request whose path isn't canonical
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalizePath {
+    /// Route the request as if it had been sent to the canonical path
+    Rewrite,
+    /// Answer `308 Permanent Redirect` to the canonical path
+    Redirect,
+}
+
+/// The path with runs of `/` collapsed and, if asked, the trailing `/` removed
+fn canonical_path(path: &str, strip_trailing_slash: bool) -> String {
+    let mut canonical = String::with_capacity(path.len());
+    for c in path.chars() {
+        if !(c == '/' && canonical.ends_with('/')) {
+            canonical.push(c);
+        }
+    }
+    if strip_trailing_slash && canonical.len() > 1 && canonical.ends_with('/') {
+        canonical.pop();
+    }
+    canonical
+}
+
+/// Collapse duplicate slashes (`/api//users`) and optionally a trailing slash
+///
+/// Depending on `mode` the request is rewritten in place or redirected, with its
+/// query string kept. With `strip_trailing_slash`, `/users/` becomes `/users`;
+/// the root path `/` is left alone. Canonical paths pass straight through.
+pub fn normalize_path(
+    mode: NormalizePath,
+    strip_trailing_slash: bool,
+) -> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    move |mut ctx, next| {
+        Box::pin(async move {
+            let canonical = canonical_path(ctx.path(), strip_trailing_slash);
+            if canonical == ctx.path() {
+                return next(ctx).await;
+            }
+            match mode {
+                NormalizePath::Rewrite => {
+                    crate::router::set_request_path(&mut ctx, &canonical);
+                    next(ctx).await
+                }
+                NormalizePath::Redirect => {
+                    let location = match ctx.request.uri().query() {
+                        Some(query) => format!("{canonical}?{query}"),
+                        None => canonical,
+                    };
+                    ResponseBuilder::new()
+                        .status(hyper::StatusCode::PERMANENT_REDIRECT)
+                        .header("Location", location)
+                        .empty_body()
+                }
+            }
+        })
+    }
+}
+
+/// Print one line per request to stdout, see [`logger_with`]
+pub fn logger()
+-> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    logger_with(|line| println!("{line}"))
+}
+
+/// Hand one line per request to `sink`, after the response is produced
+///
+/// Lines look like `GET /users/7 200 1.3ms bytes=512 user_id=7 tenant=acme`:
+/// method, path, status, time to the response and bytes of body sent, followed
+/// by the fields handlers and later middleware added with
+/// [`RequestCtx::log_field`]. Values with spaces or quotes are quoted.
+///
+/// Under the engine the line is written once the body is sent, see
+/// [`BytesSent`]; a chain run without the engine logs right away without `bytes=`.
+pub fn logger_with<F>(
+    sink: F,
+) -> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+where
+    F: Fn(&str) + Send + Sync + 'static,
+{
+    let sink = Arc::new(sink);
+    move |ctx, next| {
+        let sink = sink.clone();
+        Box::pin(async move {
+            let method = ctx.request.method().clone();
+            let path = ctx.path().to_string();
+            let log = ctx.log_context().clone();
+            let started = Instant::now();
+            let response = next(ctx).await;
+
+            let head = format!(
+                "{method} {path} {} {:.1}ms",
+                response.status().as_u16(),
+                started.elapsed().as_secs_f64() * 1000.0
+            );
+            let sent = log.get::<BytesSent>();
+            let write = move |bytes: Option<u64>| {
+                let mut line = head;
+                if let Some(bytes) = bytes {
+                    line.push_str(&format!(" bytes={bytes}"));
+                }
+                for (key, value) in log.fields() {
+                    if value.is_empty() || value.contains([' ', '"', '=']) {
+                        line.push_str(&format!(" {key}={value:?}"));
+                    } else {
+                        line.push_str(&format!(" {key}={value}"));
+                    }
+                }
+                sink(&line);
+            };
+            match sent {
+                Some(sent) => sent.on_finish(move |bytes| write(Some(bytes))),
+                None => write(None),
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IntoNext, IntoResponse};
+
+    fn json_post(content_type: &str) -> RequestCtx {
+        let (parts, _) = hyper::Request::builder()
+            .method("POST")
+            .uri("/users")
+            .header("Content-Type", content_type)
+            .body(())
+            .unwrap()
+            .into_parts();
+        RequestCtx::from_parts(parts, Bytes::from_static(b"{}"))
+    }
+
+    fn endpoint() -> Next {
+        (|_ctx: RequestCtx| async { ResponseBuilder::new().body("ok") }).into_next()
+    }
+
+    #[tokio::test]
+    async fn test_require_content_type_passes_matching_type() {
+        let middleware = require_content_type("application/json");
+        let response = middleware(json_post("application/json; charset=utf-8"), endpoint()).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_require_content_type_rejects_mismatch() {
+        let middleware = require_content_type("application/json");
+        let response = middleware(json_post("text/plain"), endpoint()).await;
+        assert_eq!(response.status(), hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_require_content_type_checks_unread_bodies() {
+        let middleware = require_content_type("application/json");
+        let unread = |request: hyper::http::request::Builder| {
+            let body = http_body_util::Empty::<Bytes>::new()
+                .map_err(|never| match never {})
+                .boxed();
+            RequestCtx::streaming(request.method("POST").uri("/upload").body(body).unwrap())
+        };
+
+        // A streaming route with a chunked upload
+        let chunked = unread(
+            hyper::Request::builder()
+                .header("Content-Type", "text/plain")
+                .header("Transfer-Encoding", "chunked"),
+        );
+        let response = middleware(chunked, endpoint()).await;
+        assert_eq!(response.status(), hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // A client waiting for 100 Continue before it sends the body
+        let expecting = unread(
+            hyper::Request::builder()
+                .header("Content-Type", "text/plain")
+                .header("Content-Length", "512")
+                .header("Expect", "100-continue"),
+        );
+        let response = middleware(expecting, endpoint()).await;
+        assert_eq!(response.status(), hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let empty = unread(hyper::Request::builder().header("Content-Length", "0"));
+        let response = middleware(empty, endpoint()).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_runs_handler_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let endpoint = {
+            let runs = runs.clone();
+            (move |_ctx: RequestCtx| {
+                let runs = runs.clone();
+                async move {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    ResponseBuilder::new()
+                        .header("X-Run", "1")
+                        .body("expensive")
+                }
+            })
+            .into_next()
+        };
+
+        let middleware = Arc::new(single_flight());
+        let requests = (0..10).map(|_| {
+            let middleware = middleware.clone();
+            let endpoint = endpoint.clone();
+            tokio::spawn(async move {
+                let ctx = RequestCtx::new(
+                    hyper::Request::get("/report?year=2024").body("").unwrap(),
+                    Default::default(),
+                );
+                middleware(ctx, endpoint).await
+            })
+        });
+
+        for request in requests.collect::<Vec<_>>() {
+            let response = request.await.unwrap();
+            assert_eq!(response.headers()["x-run"], "1");
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, "expensive");
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_keeps_credentialed_requests_apart() {
+        let endpoint = (|ctx: RequestCtx| async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            let user = ctx
+                .header("authorization")
+                .unwrap_or("anonymous")
+                .to_string();
+            ResponseBuilder::new().body(user)
+        })
+        .into_next();
+
+        let middleware = Arc::new(single_flight());
+        let requests = ["Bearer alice", "Bearer bob"].map(|credential| {
+            let middleware = middleware.clone();
+            let endpoint = endpoint.clone();
+            tokio::spawn(async move {
+                let request = hyper::Request::get("/me").header("Authorization", credential);
+                let ctx = RequestCtx::new(request.body("").unwrap(), Default::default());
+                let response = middleware(ctx, endpoint).await;
+                response.into_body().collect().await.unwrap().to_bytes()
+            })
+        });
+
+        let [alice, bob] = requests;
+        assert_eq!(alice.await.unwrap(), "Bearer alice");
+        assert_eq!(bob.await.unwrap(), "Bearer bob");
+    }
+
+    #[tokio::test]
+    async fn test_default_json_content_type() {
+        let untyped = |body: &'static str| {
+            (move |_ctx: RequestCtx| async move { ResponseBuilder::new().body(body) }).into_next()
+        };
+        let middleware = default_json_content_type();
+
+        let response = middleware(json_post("application/json"), untyped(r#"{"id": 1}"#)).await;
+        assert_eq!(response.headers()["content-type"], "application/json");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"{"id": 1}"#);
+
+        let response = middleware(json_post("application/json"), untyped("plain text")).await;
+        assert!(!response.headers().contains_key("content-type"));
+
+        // Existing content types are left alone
+        let response = middleware(json_post("application/json"), endpoint()).await;
+        assert!(!response.headers().contains_key("content-type"));
+        let typed = (|_ctx: RequestCtx| async { "[1, 2]".into_response() }).into_next();
+        let response = middleware(json_post("application/json"), typed).await;
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/plain; charset=utf-8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_path_depth() {
+        let middleware = max_path_depth(3);
+        let get = |path: &str| {
+            RequestCtx::new(
+                hyper::Request::get(path).body("").unwrap(),
+                Default::default(),
+            )
+        };
+
+        let response = middleware(get("/a/b/c"), endpoint()).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        let response = middleware(get("/a//b/c/"), endpoint()).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+
+        let response = middleware(get("/a/b/c/d"), endpoint()).await;
+        assert_eq!(response.status(), hyper::StatusCode::URI_TOO_LONG);
+    }
+
+    #[tokio::test]
+    async fn test_normalize_path_rewrite_and_redirect() {
+        let echo_path = || {
+            (|ctx: RequestCtx| async move { ResponseBuilder::new().body(ctx.uri().to_string()) })
+                .into_next()
+        };
+        let get = |uri: &str| {
+            RequestCtx::new(
+                hyper::Request::get(uri).body("").unwrap(),
+                Default::default(),
+            )
+        };
+        let body =
+            async |response: Response| response.into_body().collect().await.unwrap().to_bytes();
+
+        let rewrite = normalize_path(NormalizePath::Rewrite, false);
+        let response = rewrite(get("/api//users///7?full=1"), echo_path()).await;
+        assert_eq!(body(response).await, "/api/users/7?full=1");
+        let response = rewrite(get("/api/users/"), echo_path()).await;
+        assert_eq!(body(response).await, "/api/users/");
+
+        let rewrite = normalize_path(NormalizePath::Rewrite, true);
+        let response = rewrite(get("/api//users/"), echo_path()).await;
+        assert_eq!(body(response).await, "/api/users");
+        let response = rewrite(get("/"), echo_path()).await;
+        assert_eq!(body(response).await, "/");
+
+        let redirect = normalize_path(NormalizePath::Redirect, true);
+        let response = redirect(get("/api//users/?page=2"), echo_path()).await;
+        assert_eq!(response.status(), hyper::StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(response.headers()["location"], "/api/users?page=2");
+        let response = redirect(get("/api/users"), echo_path()).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_logger_emits_log_fields() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let middleware = logger_with({
+            let lines = lines.clone();
+            move |line: &str| lines.lock().unwrap().push(line.to_string())
+        });
+        let endpoint = (|ctx: RequestCtx| async move {
+            ctx.log_field("user_id", 42);
+            ctx.log_field("tenant", "acme corp");
+            ctx.log_field("user_id", 7);
+            ResponseBuilder::new().body("ok")
+        })
+        .into_next();
+
+        let request = hyper::Request::get("/users/7").body("").unwrap();
+        let response = middleware(RequestCtx::new(request, Default::default()), endpoint).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+
+        let lines = lines.lock().unwrap();
+        let (request, fields) = lines[0].split_once("ms ").unwrap();
+        assert!(request.starts_with("GET /users/7 200 "), "{request}");
+        assert_eq!(fields, r#"user_id=7 tenant="acme corp""#);
+    }
+}
@@ -0,0 +1,160 @@
+//! `Cache-Control` header parsing.
+
+use crate::RequestCtx;
+
+/// The directives of a `Cache-Control` header (RFC 9111, 5.2)
+///
+/// Read from a request with [`RequestCtx::cache_control`] or from a response
+/// with [`ResponseExt::cache_control`](crate::ResponseExt::cache_control).
+/// Directives that aren't present are `false` or `None`; unknown ones are
+/// ignored. Ages are in seconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub no_transform: bool,
+    pub private: bool,
+    pub public: bool,
+    pub must_revalidate: bool,
+    pub proxy_revalidate: bool,
+    pub immutable: bool,
+    pub only_if_cached: bool,
+    pub max_age: Option<u64>,
+    pub s_maxage: Option<u64>,
+    /// `max-stale` without a value accepts responses of any staleness, `u64::MAX`
+    pub max_stale: Option<u64>,
+    pub min_fresh: Option<u64>,
+    pub stale_while_revalidate: Option<u64>,
+    pub stale_if_error: Option<u64>,
+}
+
+impl CacheControl {
+    /// The directives from every `Cache-Control` header in `headers`
+    pub fn from_headers(headers: &hyper::HeaderMap) -> Self {
+        let mut cache_control = CacheControl::default();
+        for value in headers.get_all(hyper::header::CACHE_CONTROL) {
+            if let Ok(value) = value.to_str() {
+                cache_control.merge(value);
+            }
+        }
+        cache_control
+    }
+
+    /// Whether a shared cache may store the response these directives came with
+    ///
+    /// That rules out `no-store` and `private`; `no-cache` responses can be
+    /// stored but must be revalidated before each use.
+    pub fn is_storable(&self) -> bool {
+        !self.no_store && !self.private
+    }
+
+    /// Add the directives of one header value, keeping earlier ages
+    fn merge(&mut self, value: &str) {
+        for directive in value.split(',') {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name, Some(argument.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            let seconds = argument.and_then(|argument| argument.parse::<u64>().ok());
+            let name = name.trim().to_ascii_lowercase();
+            let flag = match name.as_str() {
+                "no-store" => Some(&mut self.no_store),
+                // `no-cache="Set-Cookie"` and `private="..."` limit the directive
+                // to some fields; they're treated as applying to all of them
+                "no-cache" => Some(&mut self.no_cache),
+                "private" => Some(&mut self.private),
+                "no-transform" => Some(&mut self.no_transform),
+                "public" => Some(&mut self.public),
+                "must-revalidate" => Some(&mut self.must_revalidate),
+                "proxy-revalidate" => Some(&mut self.proxy_revalidate),
+                "immutable" => Some(&mut self.immutable),
+                "only-if-cached" => Some(&mut self.only_if_cached),
+                _ => None,
+            };
+            if let Some(flag) = flag {
+                *flag = true;
+                continue;
+            }
+
+            let (age, seconds) = match name.as_str() {
+                "max-age" => (&mut self.max_age, seconds),
+                "s-maxage" => (&mut self.s_maxage, seconds),
+                "max-stale" if argument.is_none() => (&mut self.max_stale, Some(u64::MAX)),
+                "max-stale" => (&mut self.max_stale, seconds),
+                "min-fresh" => (&mut self.min_fresh, seconds),
+                "stale-while-revalidate" => (&mut self.stale_while_revalidate, seconds),
+                "stale-if-error" => (&mut self.stale_if_error, seconds),
+                _ => continue,
+            };
+            if age.is_none() {
+                *age = seconds;
+            }
+        }
+    }
+}
+
+/// Parse a single `Cache-Control` header value
+pub fn parse_cache_control(value: &str) -> CacheControl {
+    let mut cache_control = CacheControl::default();
+    cache_control.merge(value);
+    cache_control
+}
+
+impl RequestCtx {
+    /// The directives of the request's `Cache-Control` headers
+    ///
+    /// Empty when the request has none. Clients send e.g. `no-cache` on a hard
+    /// reload to ask for a response that isn't served from cache.
+    pub fn cache_control(&self) -> CacheControl {
+        CacheControl::from_headers(self.request.headers())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ResponseBuilder, ResponseExt};
+
+    #[test]
+    fn test_no_cache_with_max_age() {
+        let parsed = parse_cache_control("no-cache, max-age=60");
+        assert_eq!(
+            parsed,
+            CacheControl {
+                no_cache: true,
+                max_age: Some(60),
+                ..Default::default()
+            }
+        );
+        assert!(parsed.is_storable());
+
+        let parsed = parse_cache_control(r#"Private="Set-Cookie", MAX-AGE="30", max-age=90"#);
+        assert!(parsed.private);
+        assert!(!parsed.is_storable());
+        assert_eq!(parsed.max_age, Some(30));
+    }
+
+    #[test]
+    fn test_no_store_on_request_and_response() {
+        let request = hyper::Request::get("/")
+            .header("Cache-Control", "no-store")
+            .header("Cache-Control", "max-stale")
+            .body("")
+            .unwrap();
+        let parsed = RequestCtx::new(request, Default::default()).cache_control();
+        assert!(parsed.no_store);
+        assert!(!parsed.no_cache);
+        assert_eq!(parsed.max_stale, Some(u64::MAX));
+        assert!(!parsed.is_storable());
+
+        let response = ResponseBuilder::new()
+            .header("Cache-Control", "public, max-age=3600, s-maxage=600")
+            .body("cached");
+        let parsed = response.cache_control();
+        assert!(parsed.public && parsed.is_storable());
+        assert_eq!((parsed.max_age, parsed.s_maxage), (Some(3600), Some(600)));
+
+        let response = ResponseBuilder::new().body("plain");
+        assert_eq!(response.cache_control(), CacheControl::default());
+    }
+}
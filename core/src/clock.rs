@@ -0,0 +1,109 @@
+//! The time source for time-dependent request handling.
+//!
+//! Expiry checks read the current time through [`RequestCtx::now`], which asks
+//! the engine's [`Clock`]. That's the system clock unless replaced with
+//! [`Engine::with_clock`](crate::Engine::with_clock), so tests can step a
+//! [`ManualClock`] past an expiry instead of sleeping.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::RequestCtx;
+
+/// A source of the current wall-clock time
+pub trait Clock: Send + Sync + 'static {
+    /// The current time
+    fn now(&self) -> SystemTime;
+}
+
+/// A clock that only moves when told to, for tests
+///
+/// Clones share the same time, so a test can keep one and give the other
+/// to [`Engine::with_clock`](crate::Engine::with_clock).
+#[derive(Clone)]
+pub struct ManualClock(Arc<Mutex<SystemTime>>);
+
+impl ManualClock {
+    /// A clock standing at `start`
+    pub fn new(start: SystemTime) -> Self {
+        Self(Arc::new(Mutex::new(start)))
+    }
+
+    /// Move the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+
+    /// Move the clock to `to`, which may be in its past
+    pub fn set(&self, to: SystemTime) {
+        *self.0.lock().unwrap() = to;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+impl RequestCtx {
+    /// The current time by the engine's [`Clock`]
+    ///
+    /// The system time when the request isn't handled by an engine.
+    pub fn now(&self) -> SystemTime {
+        match self.request.extensions().get::<Arc<dyn Clock>>() {
+            Some(clock) => clock.now(),
+            None => SystemTime::now(),
+        }
+    }
+
+    /// [`RequestCtx::now`] as whole seconds since the Unix epoch
+    pub(crate) fn unix_now(&self) -> u64 {
+        self.now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Engine, ResponseBuilder, ResponseExt, TestClient};
+
+    const SECRET: &[u8] = b"clock test secret";
+
+    #[tokio::test]
+    async fn test_manual_clock_expires_signed_cookie() {
+        let clock = ManualClock::new(SystemTime::now());
+        let mut app = Engine::new();
+        app.with_clock(clock.clone())
+            .get("/login", |_| async {
+                let mut response = ResponseBuilder::new().body("welcome");
+                response.set_signed_cookie("user", "alice", SECRET, Duration::from_secs(60));
+                response
+            })
+            .get("/me", |ctx: RequestCtx| async move {
+                ctx.signed_cookie("user", SECRET)
+                    .unwrap_or_else(|| "anonymous".to_string())
+            });
+        let client = TestClient::new(app);
+
+        let login = client.get("/login").await;
+        let cookie = login.header("set-cookie").unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_string();
+        let me = |cookie: String| {
+            hyper::Request::get("/me")
+                .header("Cookie", cookie)
+                .body("")
+                .unwrap()
+        };
+
+        assert_eq!(client.oneshot(me(cookie.clone())).await.text(), "alice");
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(client.oneshot(me(cookie.clone())).await.text(), "alice");
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(client.oneshot(me(cookie)).await.text(), "anonymous");
+    }
+}
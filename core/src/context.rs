@@ -1,33 +1,616 @@
 //! Context for handling HTTP requests in a web application.
+//!
+//! A [`RequestCtx`] is what every handler and middleware receives. Its public
+//! fields are part of the stable API:
+//!
+//! - `request`: the request head (method, URI, headers, extensions) without a body
+//! - `params`: route parameters captured by the router, e.g. `id` for `/users/:id`
+//! - `body`: the buffered request body, `None` when the request had no body or was
+//!   routed to a [`streaming`](crate::streaming) handler
+//!
+//! Everything else is reached through methods so it can evolve without breaking handlers.
+//! Handlers can be unit-tested without a server by building a context directly:
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//! use ree::RequestCtx;
+//!
+//! let request = hyper::Request::get("/users/7").body("").unwrap();
+//! let params = HashMap::from([("id".to_string(), "7".to_string())]);
+//! let ctx = RequestCtx::new(request, params);
+//!
+//! assert_eq!(ctx.path(), "/users/7");
+//! assert_eq!(ctx.get_param("id").map(String::as_str), Some("7"));
+//! ```
 
-use http_body_util::BodyExt;
+use std::{
+    path::Path,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use http_body_util::{BodyExt, combinators::BoxBody};
 use hyper::body::Bytes;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    error::{BodyRejection, JsonErrorFormatter, JsonRejection, Validate},
+    query::{self, DuplicateKeys, QueryError},
+};
+
+/// A request body that hasn't been read yet
+pub(crate) type BodyStream = BoxBody<Bytes, hyper::Error>;
+
+/// Errors produced while reading or deserializing a request body
+#[derive(Debug)]
+pub enum BodyError {
+    /// The request has no body
+    Missing,
+    /// The body's content type isn't supported by the parser
+    UnsupportedMediaType(String),
+    /// The body isn't valid JSON for the target type
+    Json(serde_json::Error),
+    /// The body isn't a valid urlencoded form for the target type
+    Form(QueryError),
+    /// The body is larger than the allowed number of bytes
+    TooLarge(u64),
+    /// Reading the body from the connection failed
+    Read(hyper::Error),
+    /// Writing the body somewhere failed
+    Io(std::io::Error),
+    /// The body isn't valid `multipart/form-data`
+    Multipart(multer::Error),
+    /// A line of a JSON Lines body isn't valid JSON for the target type
+    JsonLine {
+        /// 1-based number of the offending line
+        line: usize,
+        error: serde_json::Error,
+    },
+}
+
+impl std::fmt::Display for BodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyError::Missing => write!(f, "Request body is required"),
+            BodyError::UnsupportedMediaType(content_type) => {
+                write!(f, "Unsupported content type: {content_type}")
+            }
+            BodyError::Json(err) => write!(f, "Invalid JSON body: {err}"),
+            BodyError::Form(err) => write!(f, "Invalid form body: {err}"),
+            BodyError::TooLarge(limit) => write!(f, "Request body exceeds {limit} bytes"),
+            BodyError::Read(err) => write!(f, "Failed to read request body: {err}"),
+            BodyError::Io(err) => write!(f, "Failed to store request body: {err}"),
+            BodyError::Multipart(err) => write!(f, "Invalid multipart body: {err}"),
+            BodyError::JsonLine { line, error } => {
+                write!(f, "Invalid JSON on line {line}: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BodyError {}
+
+/// Why [`RequestCtx::param`] couldn't produce a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamError {
+    /// The matched route has no parameter of this name
+    Missing(String),
+    /// The parameter is there but doesn't parse as the requested type
+    Invalid {
+        name: String,
+        value: String,
+        /// The parser's error message
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for ParamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamError::Missing(name) => write!(f, "Missing route parameter `{name}`"),
+            ParamError::Invalid {
+                name,
+                value,
+                reason,
+            } => write!(f, "Invalid route parameter `{name}` ({value:?}): {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+impl crate::IntoResponse for ParamError {
+    fn into_response(self) -> crate::Response {
+        crate::IntoResponse::into_response(crate::Rejection::bad_request(self.to_string()))
+    }
+}
+
+/// Strip parameters from a media type, e.g. `Text/HTML; charset=utf-8` -> `text/html`
+pub(crate) fn media_type(value: &str) -> String {
+    value
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Typed values a handler records for middleware running after it
+///
+/// The context is shared: middleware can clone it from [`RequestCtx::log_context`]
+/// before calling `next`, and the router also attaches it to the response
+/// extensions so it can be read back from the returned [`crate::Response`].
+#[derive(Clone, Default, Debug)]
+pub struct LogContext {
+    values: Arc<Mutex<hyper::http::Extensions>>,
+}
 
-pub type HayperRequest = hyper::Request<hyper::body::Incoming>;
+impl LogContext {
+    /// Record a value, replacing any previous value of the same type
+    pub fn insert<T: Clone + Send + Sync + 'static>(&self, value: T) -> Option<T> {
+        self.values.lock().unwrap().insert(value)
+    }
+
+    /// Read back a recorded value
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.values.lock().unwrap().get::<T>().cloned()
+    }
 
+    /// Record a key/value field for log lines, replacing an earlier value for `key`
+    pub fn field(&self, key: &str, value: impl ToString) {
+        let mut values = self.values.lock().unwrap();
+        let fields = &mut values.get_or_insert_default::<LogFields>().0;
+        let value = value.to_string();
+        match fields.iter_mut().find(|(existing, _)| existing == key) {
+            Some((_, existing)) => *existing = value,
+            None => fields.push((key.to_string(), value)),
+        }
+    }
+
+    /// The recorded log fields, in the order their keys were first recorded
+    pub fn fields(&self) -> Vec<(String, String)> {
+        self.get::<LogFields>()
+            .map(|fields| fields.0)
+            .unwrap_or_default()
+    }
+}
+
+/// Fields recorded with [`LogContext::field`]
+#[derive(Clone, Default)]
+struct LogFields(Vec<(String, String)>);
+
+/// A value memoized by [`RequestCtx::get_or_compute`], kept apart from other extensions
+#[derive(Clone)]
+struct Memoized<T>(T);
+
+/// Per-request context handed to handlers and middleware
 pub struct RequestCtx {
     pub request: hyper::Request<()>, // Request without body
     pub params: std::collections::HashMap<String, String>,
     pub body: Option<Bytes>, // Pre-read body
+    log: LogContext,
+    pub(crate) duplicate_keys: DuplicateKeys,
+    /// Builds the body of [`JsonRejection`] responses, `None` for the default
+    pub(crate) json_error_formatter: Option<JsonErrorFormatter>,
+    pub(crate) case_insensitive_params: bool,
+    /// Whether framework errors are sent as RFC 7807 problem details
+    pub(crate) problem_details: bool,
+    /// Whether the path falls under one of the engine's API prefixes
+    pub(crate) api_request: bool,
+    /// Body still on the connection, for streaming handlers
+    stream: Option<BodyStream>,
+    /// Body bytes read so far, also counted once the stream is handed out
+    consumed: Arc<AtomicU64>,
+    /// Largest body the engine buffers, `None` for no limit
+    pub(crate) max_body_size: Option<u64>,
+    /// Pattern of the route the request was matched to
+    pub(crate) route_pattern: Option<String>,
+    /// `Cookie` header parsed on first use
+    cookies: OnceLock<std::collections::HashMap<String, String>>,
+    /// Trace context parsed or started on first use
+    pub(crate) trace: OnceLock<crate::TraceContext>,
 }
 
 impl RequestCtx {
-    /// Create a new RequestCtx from a hyper request
-    pub async fn new(request: HayperRequest) -> Result<Self, hyper::Error> {
+    /// Create a RequestCtx from a request with an in-memory body and route parameters
+    pub fn new<B: Into<Bytes>>(
+        request: hyper::Request<B>,
+        params: std::collections::HashMap<String, String>,
+    ) -> Self {
         let (parts, body) = request.into_parts();
-        let body_bytes = body.collect().await?.to_bytes();
+        let mut ctx = Self::from_parts(parts, body.into());
+        ctx.params = params;
+        ctx
+    }
 
-        Ok(RequestCtx {
+    /// Create a RequestCtx from a request received from `remote_addr`, reading its body
+    ///
+    /// At most `max_body_size` bytes are buffered; a larger body, or a
+    /// `Content-Length` announcing one, fails with [`BodyError::TooLarge`]
+    /// before the rest is read. The engine itself leaves the body on the
+    /// connection until the matched handler needs it, see
+    /// [`Engine::max_body_size`](crate::Engine::max_body_size).
+    pub async fn from_request<B>(
+        request: hyper::Request<B>,
+        remote_addr: std::net::SocketAddr,
+        max_body_size: u64,
+    ) -> Result<Self, BodyError>
+    where
+        B: hyper::body::Body<Data = Bytes, Error = hyper::Error> + Send + Sync + 'static,
+    {
+        let mut ctx = Self::from_connection(request.map(BodyExt::boxed), remote_addr);
+        ctx.buffer_body(Some(max_body_size)).await?;
+        Ok(ctx)
+    }
+
+    /// Create a RequestCtx for a request as it arrives, leaving its body unread
+    pub(crate) fn from_connection(
+        request: hyper::Request<BodyStream>,
+        remote_addr: std::net::SocketAddr,
+    ) -> Self {
+        let mut ctx = Self::streaming(request);
+        ctx.request.extensions_mut().insert(remote_addr);
+        ctx
+    }
+
+    /// Create a RequestCtx from request parts and an already-read body
+    pub(crate) fn from_parts(parts: hyper::http::request::Parts, body: Bytes) -> Self {
+        RequestCtx {
             request: hyper::Request::from_parts(parts, ()),
             params: std::collections::HashMap::new(),
-            body: if body_bytes.is_empty() {
-                None
-            } else {
-                Some(body_bytes)
-            },
+            log: LogContext::default(),
+            duplicate_keys: DuplicateKeys::default(),
+            json_error_formatter: None,
+            case_insensitive_params: false,
+            problem_details: false,
+            api_request: false,
+            route_pattern: None,
+            cookies: OnceLock::new(),
+            trace: OnceLock::new(),
+            consumed: Arc::new(AtomicU64::new(body.len() as u64)),
+            body: if body.is_empty() { None } else { Some(body) },
+            stream: None,
+            max_body_size: None,
+        }
+    }
+
+    /// Create a RequestCtx whose body is left unread until [`RequestCtx::buffer_body`]
+    pub(crate) fn streaming(request: hyper::Request<BodyStream>) -> Self {
+        let (parts, body) = request.into_parts();
+        let mut ctx = Self::from_parts(parts, Bytes::new());
+        let consumed = ctx.consumed.clone();
+        ctx.stream = Some(
+            body.map_frame(move |frame| {
+                if let Some(data) = frame.data_ref() {
+                    consumed.fetch_add(data.len() as u64, Ordering::Relaxed);
+                }
+                frame
+            })
+            .boxed(),
+        );
+        ctx
+    }
+
+    /// The body length announced in `Content-Length`, before any of it is read
+    ///
+    /// `None` without the header, e.g. for chunked uploads, or when it isn't a
+    /// number.
+    pub fn declared_content_length(&self) -> Option<u64> {
+        self.header(hyper::header::CONTENT_LENGTH.as_str())
+            .and_then(|length| length.trim().parse().ok())
+    }
+
+    /// Body bytes read from the connection so far
+    ///
+    /// The whole body once the engine has buffered it for a handler; in a
+    /// [`streaming`](crate::streaming) handler it grows as the body is read.
+    /// For a context created with an in-memory body, that body's length.
+    pub fn consumed_body_len(&self) -> u64 {
+        self.consumed.load(Ordering::Relaxed)
+    }
+
+    /// Read a still-pending body into `body`, failing past `max_bytes`
+    ///
+    /// A `Content-Length` over the limit is refused before anything is read.
+    pub(crate) async fn buffer_body(&mut self, max_bytes: Option<u64>) -> Result<(), BodyError> {
+        let Some(mut stream) = self.stream.take() else {
+            return Ok(());
+        };
+        let max_bytes = max_bytes.unwrap_or(u64::MAX);
+        if self
+            .declared_content_length()
+            .is_some_and(|length| length > max_bytes)
+        {
+            return Err(BodyError::TooLarge(max_bytes));
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(frame) = stream.frame().await {
+            if let Ok(data) = frame.map_err(BodyError::Read)?.into_data() {
+                if (bytes.len() + data.len()) as u64 > max_bytes {
+                    return Err(BodyError::TooLarge(max_bytes));
+                }
+                bytes.extend_from_slice(&data);
+            }
+        }
+        self.body = (!bytes.is_empty()).then(|| Bytes::from(bytes));
+        Ok(())
+    }
+
+    /// Buffer a still-pending body for a handler, answering read failures
+    ///
+    /// Over the engine's body size limit is `413 Payload Too Large`, any other
+    /// read error `400 Bad Request`.
+    pub(crate) async fn read_pending_body(&mut self) -> Result<(), crate::Response> {
+        match self.buffer_body(self.max_body_size).await {
+            Ok(()) => Ok(()),
+            Err(BodyError::TooLarge(_)) => Err(crate::ResponseBuilder::error_for(
+                self,
+                hyper::StatusCode::PAYLOAD_TOO_LARGE,
+            )),
+            Err(err) => {
+                eprintln!("Failed to read request body: {err}");
+                Err(crate::ResponseBuilder::bad_request())
+            }
+        }
+    }
+
+    /// Whether the client sent `Expect: 100-continue` and waits before uploading
+    ///
+    /// The engine sends `100 Continue` only once the body is read: right before a
+    /// buffered handler runs, or on the first read in a
+    /// [`streaming`](crate::streaming) handler. Unmatched routes, middleware that
+    /// answers early and handlers that never read the body spare the upload.
+    pub fn expects_continue(&self) -> bool {
+        self.header_has_token(hyper::header::EXPECT, "100-continue")
+    }
+
+    /// The request method
+    pub fn method(&self) -> &hyper::Method {
+        self.request.method()
+    }
+
+    /// Whether this is a `HEAD` request, whose response body is never sent
+    pub fn is_head(&self) -> bool {
+        self.request.method() == hyper::Method::HEAD
+    }
+
+    /// The full request URI
+    pub fn uri(&self) -> &hyper::Uri {
+        self.request.uri()
+    }
+
+    /// The request path, without the query string
+    pub fn path(&self) -> &str {
+        self.request.uri().path()
+    }
+
+    /// All request headers
+    pub fn headers(&self) -> &hyper::HeaderMap {
+        self.request.headers()
+    }
+
+    /// A single header value, if present and valid UTF-8
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    /// The pattern of the matched route, e.g. `/users/:id`
+    ///
+    /// Set once the engine has routed the request; `None` before that and
+    /// for requests no route matches.
+    pub fn route_pattern(&self) -> Option<&str> {
+        self.route_pattern.as_deref()
+    }
+
+    /// Whether the client should get JSON rather than HTML
+    ///
+    /// True for paths under an [`Engine::api_prefix`](crate::Engine::api_prefix),
+    /// or when `Accept` ranks a JSON type (`application/json`, `*/*+json`)
+    /// above `text/html`. Wildcards like `*/*` don't count for either, so
+    /// browsers and bare clients get HTML.
+    pub fn wants_json(&self) -> bool {
+        self.api_request || self.accept_prefers_json()
+    }
+
+    /// Whether the request was made by script (`fetch` or `XMLHttpRequest`)
+    ///
+    /// True when `X-Requested-With` is `XMLHttpRequest`, as set by jQuery and
+    /// similar libraries, or when `Accept` ranks JSON above HTML like
+    /// [`RequestCtx::wants_json`]. Handy for answering partial JSON to script
+    /// and full pages to browser navigation.
+    pub fn is_ajax(&self) -> bool {
+        self.header("x-requested-with")
+            .is_some_and(|value| value.eq_ignore_ascii_case("XMLHttpRequest"))
+            || self.accept_prefers_json()
+    }
+
+    /// Whether `Accept` ranks a JSON type above `text/html`
+    fn accept_prefers_json(&self) -> bool {
+        let (mut json, mut html) = (0.0f32, 0.0f32);
+        for (media_type, quality) in self.accepted_types() {
+            if media_type == "application/json" || media_type.ends_with("+json") {
+                json = json.max(quality);
+            } else if media_type == "text/html" {
+                html = html.max(quality);
+            }
+        }
+        json > html
+    }
+
+    /// The media ranges in `Accept`, lowercased, with their `q` weights (default 1)
+    pub(crate) fn accepted_types(&self) -> Vec<(String, f32)> {
+        self.header_tokens(hyper::header::ACCEPT)
+            .map(|range| {
+                let mut params = range.split(';');
+                let media_type = params.next().unwrap_or("").trim().to_ascii_lowercase();
+                let quality = params
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (media_type, quality)
+            })
+            .collect()
+    }
+
+    /// All cookies sent with the request, by name
+    ///
+    /// Every `Cookie` header is parsed on first call and the map is cached, so
+    /// later calls don't parse again. Values are percent-decoded and stripped of
+    /// surrounding quotes; a value that doesn't decode is kept as sent. When a
+    /// name appears twice the first value wins, as browsers send the most
+    /// specific cookie first.
+    pub fn cookies(&self) -> &std::collections::HashMap<String, String> {
+        self.cookies.get_or_init(|| {
+            let mut cookies = std::collections::HashMap::new();
+            let pairs = self
+                .headers()
+                .get_all(hyper::header::COOKIE)
+                .into_iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(';'));
+            for pair in pairs {
+                let Some((name, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let name = name.trim();
+                if name.is_empty() {
+                    continue;
+                }
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                let value =
+                    crate::files::percent_decode(value).unwrap_or_else(|| value.to_string());
+                cookies.entry(name.to_string()).or_insert(value);
+            }
+            cookies
         })
     }
 
+    /// The value of the cookie `name`, see [`RequestCtx::cookies`]
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies().get(name).map(String::as_str)
+    }
+
+    /// The payload of a cookie set with
+    /// [`ResponseExt::set_signed_cookie`](crate::ResponseExt::set_signed_cookie)
+    ///
+    /// Returns `None` when the cookie is missing, was altered, was signed with
+    /// another secret or has expired by [`RequestCtx::now`].
+    pub fn signed_cookie(&self, name: &str, secret: &[u8]) -> Option<String> {
+        crate::cookie::verify(name, self.cookie(name)?, secret, self.unix_now())
+    }
+
+    /// Whether this is a WebSocket upgrade request
+    ///
+    /// Checks for a `GET` with `Connection: Upgrade`, `Upgrade: websocket`
+    /// and a `Sec-WebSocket-Key`; it doesn't validate the key itself.
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.method() == hyper::Method::GET
+            && self.header_has_token(hyper::header::CONNECTION, "upgrade")
+            && self.header_has_token(hyper::header::UPGRADE, "websocket")
+            && self
+                .headers()
+                .contains_key(hyper::header::SEC_WEBSOCKET_KEY)
+    }
+
+    /// Subprotocols offered in `Sec-WebSocket-Protocol`, in client preference order
+    pub fn websocket_protocols(&self) -> Vec<String> {
+        self.header_tokens(hyper::header::SEC_WEBSOCKET_PROTOCOL)
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Comma-separated tokens across every value of a header
+    fn header_tokens(&self, name: hyper::header::HeaderName) -> impl Iterator<Item = &str> {
+        self.headers()
+            .get_all(name)
+            .into_iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+    }
+
+    fn header_has_token(&self, name: hyper::header::HeaderName, token: &str) -> bool {
+        self.header_tokens(name)
+            .any(|value| value.eq_ignore_ascii_case(token))
+    }
+
+    /// Values recorded for logging middleware that runs after the handler
+    pub fn log_context(&self) -> &LogContext {
+        &self.log
+    }
+
+    /// The request's value of type `T`, computed by `compute` on first use
+    ///
+    /// Later calls for the same `T`, from middleware or the handler, return the
+    /// cached value without running their closure, so derived data such as a
+    /// parsed auth token is worked out once per request.
+    pub fn get_or_compute<T, F>(&mut self, compute: F) -> &T
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce() -> T,
+    {
+        &self
+            .request
+            .extensions_mut()
+            .get_or_insert_with(|| Memoized(compute()))
+            .0
+    }
+
+    /// Add `key=value` to the request's log line, e.g. `ctx.log_field("user_id", 42)`
+    ///
+    /// Read by the [`logger`](crate::logger) middleware after the handler ran.
+    pub fn log_field(&self, key: &str, value: impl ToString) {
+        self.log.field(key, value);
+    }
+
+    /// A route parameter parsed as a `T`, e.g. `ctx.param::<u64>("id")` for `/users/:id`
+    ///
+    /// Fails with [`ParamError::Missing`] when the route has no such parameter
+    /// and [`ParamError::Invalid`] when the value doesn't parse; returned from a
+    /// handler with `?` either is answered with `400 Bad Request`.
+    pub fn param<T: std::str::FromStr>(&self, name: &str) -> Result<T, ParamError>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let value = self
+            .param_str(name)
+            .ok_or_else(|| ParamError::Missing(name.to_string()))?;
+        value.parse().map_err(|err: T::Err| ParamError::Invalid {
+            name: name.to_string(),
+            value: value.to_string(),
+            reason: err.to_string(),
+        })
+    }
+
+    /// A route parameter by name, as captured
+    ///
+    /// Names match exactly unless the engine enabled
+    /// [`Engine::case_insensitive_params`](crate::Engine::case_insensitive_params),
+    /// in which case `param_str("ID")` also finds a `:id` parameter.
+    pub fn param_str(&self, name: &str) -> Option<&str> {
+        if let Some(value) = self.params.get(name) {
+            return Some(value);
+        }
+        if !self.case_insensitive_params {
+            return None;
+        }
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
     pub fn get_param(&self, key: &str) -> Option<&String> {
         self.params.get(key)
     }
@@ -52,6 +635,20 @@ impl RequestCtx {
         self.body.as_ref()
     }
 
+    /// Replace the request body, e.g. after decrypting or normalizing it
+    ///
+    /// Any body still pending on the connection is dropped, and a
+    /// `Content-Length` header is updated to the new length.
+    pub fn set_body(&mut self, body: impl Into<Bytes>) {
+        let body = body.into();
+        let headers = self.request.headers_mut();
+        if headers.contains_key(hyper::header::CONTENT_LENGTH) {
+            headers.insert(hyper::header::CONTENT_LENGTH, body.len().into());
+        }
+        self.stream = None;
+        self.body = Some(body);
+    }
+
     /// Get the request body as a UTF-8 string
     pub fn body_string(&self) -> Result<Option<String>, std::string::FromUtf8Error> {
         match &self.body {
@@ -61,18 +658,25 @@ impl RequestCtx {
     }
 
     /// Parse JSON from the request body
+    ///
+    /// Invalid JSON fails with a [`JsonRejection`], answered with `400` when
+    /// returned from a handler.
     pub fn body_json<T>(&self) -> Result<Option<T>, Box<dyn std::error::Error + Send + Sync>>
     where
         T: serde::de::DeserializeOwned,
     {
         match self.body_string()? {
-            Some(body_str) => Ok(Some(serde_json::from_str(&body_str)?)),
+            Some(body_str) => serde_json::from_str(&body_str)
+                .map(Some)
+                .map_err(|err| JsonRejection::new(err, self.json_error_formatter.as_ref()).into()),
             None => Ok(None),
         }
     }
 
     /// Parse JSON from the request body, returning an error if body is missing
     /// Use this when the request body is required
+    ///
+    /// Invalid JSON fails with a [`JsonRejection`], see [`RequestCtx::body_json`].
     pub fn json<T>(&self) -> Result<T, Box<dyn std::error::Error + Send + Sync>>
     where
         T: serde::de::DeserializeOwned,
@@ -82,4 +686,834 @@ impl RequestCtx {
             None => Err("Request body is required".into()),
         }
     }
+
+    /// Parse a required JSON body and check it with its [`Validate`] impl
+    ///
+    /// Invalid fields fail with [`ValidationErrors`](crate::ValidationErrors),
+    /// answered with `422 Unprocessable Entity` when returned from a handler.
+    pub fn json_validated<T>(&self) -> Result<T, Box<dyn std::error::Error + Send + Sync>>
+    where
+        T: serde::de::DeserializeOwned + Validate,
+    {
+        let value: T = self.json()?;
+        value.validate()?;
+        Ok(value)
+    }
+
+    /// Deserialize the body by its `Content-Type` and check it with its [`Validate`] impl
+    ///
+    /// JSON and urlencoded forms are understood, as with [`RequestCtx::body_as`];
+    /// other types, CBOR included, are refused. Failures come back as a
+    /// [`BodyRejection`], which answers `400` for a missing or malformed body,
+    /// `415` for other types and `422` for failed validation when returned from
+    /// a handler with `?`.
+    pub fn validated_body<T>(&self) -> Result<T, BodyRejection>
+    where
+        T: serde::de::DeserializeOwned + Validate,
+    {
+        let value: T = self.body_as().map_err(BodyRejection::Body)?;
+        value.validate().map_err(BodyRejection::Validation)?;
+        Ok(value)
+    }
+
+    /// A query string parameter by name, percent-decoded
+    ///
+    /// `None` when the request has no query string or the key isn't in it. A
+    /// repeated key gives its first value under [`DuplicateKeys::FirstWins`]
+    /// and its last otherwise; [`RequestCtx::query_all`] returns every one.
+    pub fn query(&self, name: &str) -> Option<String> {
+        let mut values = self.query_all(name);
+        match self.duplicate_keys {
+            DuplicateKeys::FirstWins if !values.is_empty() => Some(values.swap_remove(0)),
+            _ => values.pop(),
+        }
+    }
+
+    /// Every value of a query string parameter, in the order sent
+    pub fn query_all(&self, name: &str) -> Vec<String> {
+        let query = self.request.uri().query().unwrap_or("");
+        form_urlencoded::parse(query.as_bytes())
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .collect()
+    }
+
+    /// A query string parameter parsed as a `T`, e.g. `ctx.query_parsed::<u32>("page")`
+    ///
+    /// `None` when the parameter is missing or doesn't parse. Use
+    /// [`RequestCtx::query_as`] to report which parameter was wrong.
+    pub fn query_parsed<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        self.query(name)?.parse().ok()
+    }
+
+    /// Deserialize the query string into `T`
+    ///
+    /// Repeated keys follow the engine's [`DuplicateKeys`] policy
+    /// (last-wins unless configured otherwise). A missing query string
+    /// decodes like an empty one.
+    pub fn query_as<T>(&self) -> Result<T, QueryError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.query_as_with(self.duplicate_keys)
+    }
+
+    /// Deserialize the query string into `T` with an explicit repeated-key policy
+    pub fn query_as_with<T>(&self, policy: DuplicateKeys) -> Result<T, QueryError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let query = self.request.uri().query().unwrap_or("");
+        query::from_urlencoded(query.as_bytes(), policy)
+    }
+
+    /// Deserialize an urlencoded form body into `T`
+    pub fn form<T>(&self) -> Result<T, BodyError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.form_with(self.duplicate_keys)
+    }
+
+    /// Deserialize an urlencoded form body into `T` with an explicit repeated-key policy
+    pub fn form_with<T>(&self, policy: DuplicateKeys) -> Result<T, BodyError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let body = self.body.as_ref().ok_or(BodyError::Missing)?;
+        query::from_urlencoded(body, policy).map_err(BodyError::Form)
+    }
+
+    /// Take the body as a stream, whether it's still pending or was buffered
+    ///
+    /// Returns `None` once the body has been consumed, or if there was none.
+    pub(crate) fn take_body_stream(&mut self) -> Option<BodyStream> {
+        if let Some(stream) = self.stream.take() {
+            return Some(stream);
+        }
+        let body = self.body.take()?;
+        Some(
+            http_body_util::Full::new(body)
+                .map_err(|never| match never {})
+                .boxed(),
+        )
+    }
+
+    /// Write the request body to `path`, returning the number of bytes written
+    ///
+    /// In a [`streaming`](crate::streaming) handler the body goes from the
+    /// connection to disk chunk by chunk; otherwise the buffered body is written.
+    /// A body larger than `max_bytes` fails with [`BodyError::TooLarge`], and the
+    /// partially written file is removed on any error.
+    pub async fn save_body_to_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        max_bytes: u64,
+    ) -> Result<u64, BodyError> {
+        if self.stream.is_none() && self.body.is_none() {
+            return Err(BodyError::Missing);
+        }
+
+        let path = path.as_ref();
+        let mut file = tokio::fs::File::create(path).await.map_err(BodyError::Io)?;
+        let result = self.write_body(&mut file, max_bytes).await;
+        if result.is_err() {
+            drop(file);
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        result
+    }
+
+    async fn write_body(
+        &mut self,
+        file: &mut tokio::fs::File,
+        max_bytes: u64,
+    ) -> Result<u64, BodyError> {
+        let mut written = 0u64;
+        let mut write = async |chunk: &[u8]| {
+            written += chunk.len() as u64;
+            if written > max_bytes {
+                return Err(BodyError::TooLarge(max_bytes));
+            }
+            file.write_all(chunk).await.map_err(BodyError::Io)
+        };
+
+        if let Some(mut stream) = self.stream.take() {
+            while let Some(frame) = stream.frame().await {
+                if let Ok(data) = frame.map_err(BodyError::Read)?.into_data() {
+                    write(&data).await?;
+                }
+            }
+        } else if let Some(body) = &self.body {
+            write(body).await?;
+        }
+
+        file.flush().await.map_err(BodyError::Io)?;
+        Ok(written)
+    }
+
+    /// Deserialize a JSON Lines (`application/x-ndjson`) body into one `T` per line
+    ///
+    /// Blank lines are skipped. The first line that doesn't parse fails the whole
+    /// body with [`BodyError::JsonLine`] carrying its 1-based line number. Use
+    /// [`RequestCtx::json_lines_stream`] to handle lines as they arrive instead.
+    pub fn json_lines<T>(&self) -> Result<Vec<T>, BodyError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.require_json_lines()?;
+        let body = self.body.as_ref().ok_or(BodyError::Missing)?;
+        body.split(|&byte| byte == b'\n')
+            .enumerate()
+            .filter(|(_, line)| !line.trim_ascii().is_empty())
+            .map(|(index, line)| parse_json_line(index + 1, line))
+            .collect()
+    }
+
+    /// Read a JSON Lines body one `T` at a time, see [`JsonLines`]
+    ///
+    /// In a [`streaming`](crate::streaming) handler lines are parsed as they come
+    /// off the connection. Reading more than `max_bytes` of body ends the stream
+    /// with [`BodyError::TooLarge`].
+    pub fn json_lines_stream<T>(&mut self, max_bytes: u64) -> Result<JsonLines<T>, BodyError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.require_json_lines()?;
+        let stream = self.take_body_stream().ok_or(BodyError::Missing)?;
+        Ok(JsonLines {
+            stream: Some(stream),
+            buffer: Vec::new(),
+            line: 0,
+            read: 0,
+            max_bytes,
+            _item: std::marker::PhantomData,
+        })
+    }
+
+    fn require_json_lines(&self) -> Result<(), BodyError> {
+        let content_type = self
+            .header(hyper::header::CONTENT_TYPE.as_str())
+            .unwrap_or("");
+        match media_type(content_type).as_str() {
+            "application/x-ndjson" | "application/jsonl" => Ok(()),
+            _ => Err(BodyError::UnsupportedMediaType(content_type.to_string())),
+        }
+    }
+
+    /// Deserialize the body as JSON or an urlencoded form, based on `Content-Type`
+    pub fn body_as<T>(&self) -> Result<T, BodyError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let content_type = self
+            .request
+            .headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        let media_type = media_type(content_type);
+        let body = self.body.as_ref().ok_or(BodyError::Missing)?;
+
+        match media_type.as_str() {
+            "application/json" => serde_json::from_slice(body).map_err(BodyError::Json),
+            "application/x-www-form-urlencoded" => {
+                query::from_urlencoded(body, self.duplicate_keys).map_err(BodyError::Form)
+            }
+            _ => Err(BodyError::UnsupportedMediaType(content_type.to_string())),
+        }
+    }
+}
+
+fn parse_json_line<T: serde::de::DeserializeOwned>(
+    line: usize,
+    bytes: &[u8],
+) -> Result<T, BodyError> {
+    serde_json::from_slice(bytes).map_err(|error| BodyError::JsonLine { line, error })
+}
+
+/// Values parsed line by line from a JSON Lines body, see [`RequestCtx::json_lines_stream`]
+pub struct JsonLines<T> {
+    /// `None` once the body has been read to the end
+    stream: Option<BodyStream>,
+    /// Bytes read but not yet split into lines
+    buffer: Vec<u8>,
+    /// Number of lines taken from `buffer` so far
+    line: usize,
+    read: u64,
+    max_bytes: u64,
+    _item: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: serde::de::DeserializeOwned> JsonLines<T> {
+    /// The next value, or `None` at the end of the body
+    ///
+    /// Blank lines are skipped. After an error the remaining lines can still be read.
+    pub async fn next(&mut self) -> Option<Result<T, BodyError>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                self.line += 1;
+                if !line.trim_ascii().is_empty() {
+                    return Some(parse_json_line(self.line, &line));
+                }
+                continue;
+            }
+
+            let Some(stream) = self.stream.as_mut() else {
+                // A last line without a trailing newline
+                if self.buffer.trim_ascii().is_empty() {
+                    return None;
+                }
+                self.line += 1;
+                let line = std::mem::take(&mut self.buffer);
+                return Some(parse_json_line(self.line, &line));
+            };
+            match stream.frame().await {
+                Some(Ok(frame)) => {
+                    if let Ok(data) = frame.into_data() {
+                        self.read += data.len() as u64;
+                        if self.read > self.max_bytes {
+                            self.stream = None;
+                            self.buffer.clear();
+                            return Some(Err(BodyError::TooLarge(self.max_bytes)));
+                        }
+                        self.buffer.extend_from_slice(&data);
+                    }
+                }
+                Some(Err(err)) => {
+                    self.stream = None;
+                    self.buffer.clear();
+                    return Some(Err(BodyError::Read(err)));
+                }
+                None => self.stream = None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Login {
+        user: String,
+        remember: bool,
+    }
+
+    fn post(content_type: &str, body: &'static str) -> RequestCtx {
+        let (parts, _) = hyper::Request::builder()
+            .method("POST")
+            .uri("/login")
+            .header("Content-Type", content_type)
+            .body(())
+            .unwrap()
+            .into_parts();
+        RequestCtx::from_parts(parts, Bytes::from_static(body.as_bytes()))
+    }
+
+    #[test]
+    fn test_body_as_json_and_form() {
+        let expected = Login {
+            user: "alice".to_string(),
+            remember: true,
+        };
+
+        let ctx = post("application/json", r#"{"user":"alice","remember":true}"#);
+        assert_eq!(ctx.body_as::<Login>().unwrap(), expected);
+
+        let ctx = post(
+            "application/x-www-form-urlencoded; charset=utf-8",
+            "user=alice&remember=true",
+        );
+        assert_eq!(ctx.body_as::<Login>().unwrap(), expected);
+    }
+
+    impl Validate for Login {
+        fn validate(&self) -> Result<(), crate::ValidationErrors> {
+            let mut errors = crate::ValidationErrors::new();
+            if self.user.is_empty() {
+                errors.add("user", "required", "user must not be empty");
+            }
+            errors.into_result()
+        }
+    }
+
+    #[test]
+    fn test_validated_body() {
+        let expected = Login {
+            user: "alice".to_string(),
+            remember: true,
+        };
+        let ctx = post("application/json", r#"{"user":"alice","remember":true}"#);
+        assert_eq!(ctx.validated_body::<Login>().unwrap(), expected);
+        let ctx = post(
+            "application/x-www-form-urlencoded",
+            "user=alice&remember=true",
+        );
+        assert_eq!(ctx.validated_body::<Login>().unwrap(), expected);
+
+        let cases = [
+            (post("application/json", r#"{"user":"#), 400),
+            (post("application/json", ""), 400),
+            (post("application/cbor", "\u{a1}"), 415),
+            (post("text/plain", "alice"), 415),
+            (
+                post("application/json", r#"{"user":"","remember":false}"#),
+                422,
+            ),
+        ];
+        for (ctx, status) in cases {
+            let rejection = ctx.validated_body::<Login>().unwrap_err();
+            assert_eq!(rejection.status(), status, "{rejection}");
+            // Returned from a handler with `?` the status carries through
+            let response = crate::IntoResponse::into_response(Err::<&str, _>(rejection));
+            assert_eq!(response.status(), status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_for_handler_unit_tests() {
+        use crate::Handler;
+
+        let handler = |ctx: RequestCtx| async move {
+            let id = ctx.get_param("id").cloned().unwrap_or_default();
+            let agent = ctx.header("User-Agent").unwrap_or("unknown").to_string();
+            format!("{} {} {id} {agent}", ctx.method(), ctx.path())
+        };
+
+        let request = hyper::Request::builder()
+            .method("PUT")
+            .uri("/users/7?verbose=1")
+            .header("User-Agent", "tests")
+            .body(r#"{"name":"bob"}"#)
+            .unwrap();
+        let params = std::collections::HashMap::from([("id".to_string(), "7".to_string())]);
+        let ctx = RequestCtx::new(request, params);
+
+        assert_eq!(ctx.uri().query(), Some("verbose=1"));
+        assert_eq!(ctx.headers().len(), 1);
+        assert_eq!(
+            ctx.json::<serde_json::Value>().unwrap()["name"],
+            serde_json::json!("bob")
+        );
+
+        let response = handler.handle(ctx).await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "PUT /users/7 7 tests");
+    }
+
+    #[test]
+    fn test_new_without_body() {
+        let request = hyper::Request::get("/").body(Bytes::new()).unwrap();
+        let ctx = RequestCtx::new(request, Default::default());
+        assert!(ctx.body_bytes().is_none());
+        assert!(ctx.params.is_empty());
+    }
+
+    #[test]
+    fn test_query_and_form_duplicate_policy() {
+        #[derive(serde::Deserialize)]
+        struct Filter {
+            x: Vec<u32>,
+        }
+
+        let mut ctx = post("application/x-www-form-urlencoded", "x=1&x=2");
+        ctx.request = hyper::Request::get("/items?x=3&x=4").body(()).unwrap();
+
+        assert_eq!(ctx.query_as::<Filter>().unwrap().x, vec![4]);
+        assert_eq!(ctx.form::<Filter>().unwrap().x, vec![2]);
+
+        ctx.duplicate_keys = DuplicateKeys::Collect;
+        assert_eq!(ctx.query_as::<Filter>().unwrap().x, vec![3, 4]);
+        assert_eq!(ctx.form::<Filter>().unwrap().x, vec![1, 2]);
+        assert_eq!(
+            ctx.query_as_with::<Filter>(DuplicateKeys::FirstWins)
+                .unwrap()
+                .x,
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn test_query_params() {
+        let get = |uri: &str| {
+            RequestCtx::new(
+                hyper::Request::get(uri).body("").unwrap(),
+                Default::default(),
+            )
+        };
+
+        let mut ctx = get("/search?q=hello%20world&page=2&tag=a&tag=b&empty=");
+        assert_eq!(ctx.query("q").as_deref(), Some("hello world"));
+        assert_eq!(ctx.query_parsed::<u32>("page"), Some(2));
+        assert_eq!(ctx.query_parsed::<u32>("q"), None);
+        assert_eq!(ctx.query("tag").as_deref(), Some("b"));
+        assert_eq!(ctx.query_all("tag"), vec!["a", "b"]);
+        assert_eq!(ctx.query("empty").as_deref(), Some(""));
+        assert_eq!(ctx.query("limit"), None);
+        assert_eq!(ctx.query_parsed::<u32>("limit"), None);
+        ctx.duplicate_keys = DuplicateKeys::FirstWins;
+        assert_eq!(ctx.query("tag").as_deref(), Some("a"));
+
+        for uri in ["/search", "/search?"] {
+            let ctx = get(uri);
+            assert_eq!(ctx.query("q"), None);
+            assert!(ctx.query_all("q").is_empty());
+        }
+    }
+
+    #[test]
+    fn test_body_as_unsupported_type() {
+        let ctx = post("text/xml", "<login/>");
+        assert!(matches!(
+            ctx.body_as::<Login>(),
+            Err(BodyError::UnsupportedMediaType(_))
+        ));
+    }
+
+    #[test]
+    fn test_websocket_upgrade_request() {
+        let request = hyper::Request::get("/ws")
+            .header("Connection", "keep-alive, Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .header("Sec-WebSocket-Protocol", "chat, superchat")
+            .body("")
+            .unwrap();
+        let ctx = RequestCtx::new(request, Default::default());
+
+        assert!(ctx.is_websocket_upgrade());
+        assert_eq!(ctx.websocket_protocols(), ["chat", "superchat"]);
+    }
+
+    #[test]
+    fn test_plain_request_is_not_websocket_upgrade() {
+        let ctx = RequestCtx::new(
+            hyper::Request::get("/ws").body("").unwrap(),
+            Default::default(),
+        );
+        assert!(!ctx.is_websocket_upgrade());
+        assert!(ctx.websocket_protocols().is_empty());
+
+        // Upgrade headers without a key aren't enough
+        let request = hyper::Request::get("/ws")
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "websocket")
+            .body("")
+            .unwrap();
+        assert!(!RequestCtx::new(request, Default::default()).is_websocket_upgrade());
+    }
+
+    fn streaming_post(body: &'static str) -> RequestCtx {
+        let body = http_body_util::Full::new(Bytes::from_static(body.as_bytes()))
+            .map_err(|never| match never {})
+            .boxed();
+        RequestCtx::streaming(hyper::Request::post("/upload").body(body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_declared_and_consumed_body_len() {
+        let mut ctx = streaming_post("hello world");
+        assert_eq!(ctx.declared_content_length(), None);
+        ctx.request
+            .headers_mut()
+            .insert("content-length", "11".parse().unwrap());
+        assert_eq!(ctx.declared_content_length(), Some(11));
+        assert_eq!(ctx.consumed_body_len(), 0);
+
+        ctx.buffer_body(None).await.unwrap();
+        assert_eq!(ctx.body_bytes().unwrap(), "hello world");
+        assert_eq!(ctx.consumed_body_len(), 11);
+
+        // Counted when read from a stream handed out, too
+        let mut ctx = streaming_post("a,b,c");
+        let mut stream = ctx.take_body_stream().unwrap();
+        while stream.frame().await.is_some() {}
+        assert_eq!(ctx.consumed_body_len(), 5);
+
+        let ctx = post("text/plain", "in memory");
+        assert_eq!(ctx.consumed_body_len(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_from_request_reads_capped_body() {
+        let remote_addr: std::net::SocketAddr = "10.0.0.7:4321".parse().unwrap();
+        let request = |body: &'static str| {
+            hyper::Request::post("/users")
+                .header("Content-Type", "application/json")
+                .body(
+                    http_body_util::Full::new(Bytes::from_static(body.as_bytes()))
+                        .map_err(|never| match never {}),
+                )
+                .unwrap()
+        };
+
+        let ctx = RequestCtx::from_request(request(r#"{"name":"alice"}"#), remote_addr, 1024)
+            .await
+            .unwrap();
+        assert_eq!(ctx.method(), hyper::Method::POST);
+        assert_eq!(ctx.path(), "/users");
+        assert!(ctx.params.is_empty());
+        assert_eq!(ctx.remote_addr(), Some(remote_addr));
+        let user: serde_json::Value = ctx.json().unwrap();
+        assert_eq!(user["name"], "alice");
+
+        let result = RequestCtx::from_request(request(r#"{"name":"bob"}"#), remote_addr, 8).await;
+        assert!(matches!(result, Err(BodyError::TooLarge(8))));
+    }
+
+    #[tokio::test]
+    async fn test_save_body_to_file() {
+        let path = std::env::temp_dir().join(format!("ree-upload-{}.bin", std::process::id()));
+
+        let mut ctx = streaming_post("file contents");
+        assert!(ctx.body.is_none());
+        assert_eq!(ctx.save_body_to_file(&path, 1024).await.unwrap(), 13);
+        assert_eq!(std::fs::read(&path).unwrap(), b"file contents");
+
+        // The stream is consumed by the first save
+        assert!(matches!(
+            ctx.save_body_to_file(&path, 1024).await,
+            Err(BodyError::Missing)
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        let mut ctx = post("application/octet-stream", "buffered");
+        assert_eq!(ctx.save_body_to_file(&path, 1024).await.unwrap(), 8);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_save_body_to_file_over_limit() {
+        let path = std::env::temp_dir().join(format!("ree-oversize-{}.bin", std::process::id()));
+
+        let mut ctx = streaming_post("way too large");
+        assert!(matches!(
+            ctx.save_body_to_file(&path, 4).await,
+            Err(BodyError::TooLarge(4))
+        ));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_typed_param() {
+        let params = std::collections::HashMap::from([
+            ("id".to_string(), "42".to_string()),
+            ("slug".to_string(), "forty-two".to_string()),
+        ]);
+        let ctx = RequestCtx::new(hyper::Request::get("/users/42").body("").unwrap(), params);
+
+        assert_eq!(ctx.param::<u64>("id"), Ok(42));
+        assert_eq!(ctx.param::<String>("slug").as_deref(), Ok("forty-two"));
+
+        let err = ctx.param::<u64>("slug").unwrap_err();
+        assert!(matches!(
+            &err,
+            ParamError::Invalid { name, value, .. } if name == "slug" && value == "forty-two"
+        ));
+        let response = crate::IntoResponse::into_response(err);
+        assert_eq!(response.status(), hyper::StatusCode::BAD_REQUEST);
+
+        assert_eq!(
+            ctx.param::<u64>("page"),
+            Err(ParamError::Missing("page".to_string()))
+        );
+        assert_eq!(ctx.param_str("page"), None);
+    }
+
+    #[test]
+    fn test_param_case_insensitive_only_when_enabled() {
+        let params = std::collections::HashMap::from([("id".to_string(), "42".to_string())]);
+        let mut ctx = RequestCtx::new(hyper::Request::get("/users/42").body("").unwrap(), params);
+
+        assert_eq!(ctx.param_str("id"), Some("42"));
+        assert_eq!(ctx.param_str("ID"), None);
+
+        ctx.case_insensitive_params = true;
+        assert_eq!(ctx.param_str("ID"), Some("42"));
+        assert_eq!(ctx.param_str("name"), None);
+    }
+
+    #[test]
+    fn test_wants_json() {
+        let accepting = |accept: &str| {
+            let request = hyper::Request::get("/users")
+                .header("Accept", accept)
+                .body("");
+            RequestCtx::new(request.unwrap(), Default::default())
+        };
+
+        assert!(accepting("application/json").wants_json());
+        assert!(accepting("application/problem+json, text/html;q=0.5").wants_json());
+        assert!(
+            !accepting("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
+                .wants_json()
+        );
+        assert!(!accepting("*/*").wants_json());
+
+        let mut ctx = accepting("text/html");
+        ctx.api_request = true;
+        assert!(ctx.wants_json());
+    }
+
+    #[test]
+    fn test_is_ajax() {
+        let request = |headers: &[(&str, &str)]| {
+            let mut request = hyper::Request::get("/cart");
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            RequestCtx::new(request.body("").unwrap(), Default::default())
+        };
+
+        assert!(request(&[("X-Requested-With", "XMLHttpRequest")]).is_ajax());
+        assert!(request(&[("Accept", "application/json")]).is_ajax());
+        let browser = request(&[(
+            "Accept",
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+        )]);
+        assert!(!browser.is_ajax());
+        assert!(!request(&[]).is_ajax());
+    }
+
+    #[test]
+    fn test_cookies() {
+        let request = hyper::Request::get("/")
+            .header(
+                "Cookie",
+                "session=abc123; token=a=b=c;theme=%22dark%20blue%22",
+            )
+            .header("Cookie", "session=shadowed; quoted=\"x\"")
+            .body("")
+            .unwrap();
+        let ctx = RequestCtx::new(request, Default::default());
+
+        let cookies = ctx.cookies();
+        assert_eq!(cookies.len(), 4);
+        assert_eq!(cookies["session"], "abc123");
+        assert_eq!(cookies["token"], "a=b=c");
+        assert_eq!(cookies["theme"], "\"dark blue\"");
+        assert_eq!(ctx.cookie("quoted"), Some("x"));
+        assert_eq!(ctx.cookie("missing"), None);
+        // Parsed once and cached
+        assert!(std::ptr::eq(cookies, ctx.cookies()));
+    }
+
+    #[test]
+    fn test_signed_cookie() {
+        use crate::ResponseExt;
+
+        let secret = b"server secret";
+        let mut response = crate::ResponseBuilder::no_content();
+        response.set_signed_cookie("user", "alice", secret, std::time::Duration::from_secs(60));
+        let set_cookie = response.header_str("set-cookie").unwrap();
+        let (cookie, attributes) = set_cookie.split_once(';').unwrap();
+        assert!(attributes.contains("HttpOnly"));
+
+        let with_cookie = |cookie: &str| {
+            let request = hyper::Request::get("/").header("Cookie", cookie).body("");
+            RequestCtx::new(request.unwrap(), Default::default())
+        };
+        let ctx = with_cookie(cookie);
+        assert_eq!(ctx.signed_cookie("user", secret).as_deref(), Some("alice"));
+        assert_eq!(ctx.signed_cookie("user", b"wrong secret"), None);
+
+        let tampered = cookie.replacen("user=", "user=Ym9i", 1);
+        assert_eq!(with_cookie(&tampered).signed_cookie("user", secret), None);
+    }
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Event {
+        id: u32,
+        kind: String,
+    }
+
+    const EVENTS: &str = concat!(
+        r#"{"id": 1, "kind": "open"}"#,
+        "\n",
+        r#"{"id": 2, "kind": "edit"}"#,
+        "\n\n",
+        r#"{"id": 3, "kind": "close"}"#,
+        "\n",
+    );
+
+    #[test]
+    fn test_json_lines() {
+        let events: Vec<Event> = post("application/x-ndjson", EVENTS).json_lines().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[2],
+            Event {
+                id: 3,
+                kind: "close".to_string()
+            }
+        );
+
+        let body = "{\"id\": 1, \"kind\": \"open\"}\n{\"id\": 2}\n{\"id\": 3, \"kind\": \"x\"}";
+        let err = post("application/x-ndjson", body)
+            .json_lines::<Event>()
+            .unwrap_err();
+        assert!(matches!(err, BodyError::JsonLine { line: 2, .. }), "{err}");
+
+        assert!(matches!(
+            post("application/json", EVENTS).json_lines::<Event>(),
+            Err(BodyError::UnsupportedMediaType(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_json_lines_stream() {
+        let body = http_body_util::Full::new(Bytes::from_static(EVENTS.as_bytes()))
+            .map_err(|never| match never {})
+            .boxed();
+        let request = hyper::Request::post("/events")
+            .header("Content-Type", "application/x-ndjson")
+            .body(body)
+            .unwrap();
+        let mut ctx = RequestCtx::streaming(request);
+
+        let mut lines = ctx.json_lines_stream::<Event>(1024).unwrap();
+        let mut ids = Vec::new();
+        while let Some(event) = lines.next().await {
+            ids.push(event.unwrap().id);
+        }
+        assert_eq!(ids, [1, 2, 3]);
+
+        let mut ctx = post("application/x-ndjson", EVENTS);
+        let mut lines = ctx.json_lines_stream::<Event>(16).unwrap();
+        assert!(matches!(
+            lines.next().await,
+            Some(Err(BodyError::TooLarge(16)))
+        ));
+        assert!(lines.next().await.is_none());
+    }
+
+    #[test]
+    fn test_get_or_compute_runs_once() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct User(String);
+
+        let mut ctx = RequestCtx::new(
+            hyper::Request::get("/").body("").unwrap(),
+            Default::default(),
+        );
+        let mut runs = 0;
+        let first = ctx
+            .get_or_compute(|| {
+                runs += 1;
+                User("ada".to_string())
+            })
+            .clone();
+        let second = ctx.get_or_compute(|| {
+            runs += 1;
+            User("grace".to_string())
+        });
+        assert_eq!(first, User("ada".to_string()));
+        assert_eq!(second, &first);
+        assert_eq!(runs, 1);
+        // Keyed by type: another type gets its own value
+        assert_eq!(*ctx.get_or_compute(|| 7u32), 7);
+    }
 }
@@ -0,0 +1,113 @@
+//! Signed cookies for tamper-evident client-side data.
+//!
+//! A signed value is `payload.expires.signature`: the base64url payload, the
+//! expiry as Unix seconds and an HMAC-SHA256 over the cookie name, payload and
+//! expiry. Binding the name stops a valid value from being replayed under
+//! another cookie. The payload is only signed, not encrypted, so clients can
+//! read it.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(secret: &[u8], name: &str, payload: &str, expires: u64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC takes any key size");
+    mac.update(name.as_bytes());
+    mac.update(b"=");
+    mac.update(payload.as_bytes());
+    mac.update(b".");
+    mac.update(expires.to_string().as_bytes());
+    mac
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Sign `value` for the cookie `name`, valid for `max_age`
+pub(crate) fn sign(name: &str, value: &str, secret: &[u8], max_age: Duration) -> String {
+    let payload = URL_SAFE_NO_PAD.encode(value);
+    let expires = now().saturating_add(max_age.as_secs());
+    let signature = mac(secret, name, &payload, expires).finalize().into_bytes();
+    format!("{payload}.{expires}.{}", URL_SAFE_NO_PAD.encode(signature))
+}
+
+/// The value inside a cookie made by [`sign`], if the signature holds and it
+/// hasn't expired by `now`, in seconds since the Unix epoch
+pub(crate) fn verify(name: &str, signed: &str, secret: &[u8], now: u64) -> Option<String> {
+    let mut parts = signed.splitn(3, '.');
+    let (payload, expires, signature) = (parts.next()?, parts.next()?, parts.next()?);
+    let expires: u64 = expires.parse().ok()?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+    mac(secret, name, payload, expires)
+        .verify_slice(&signature)
+        .ok()?;
+    if expires <= now {
+        return None;
+    }
+    String::from_utf8(URL_SAFE_NO_PAD.decode(payload).ok()?).ok()
+}
+
+/// A `Set-Cookie` value with the attributes used for framework-set cookies
+///
+/// Without `max_age` the browser drops the cookie when it closes.
+pub(crate) fn set_cookie_header(name: &str, value: &str, max_age: Option<Duration>) -> String {
+    match max_age {
+        Some(max_age) => format!(
+            "{name}={value}; Max-Age={}; Path=/; HttpOnly; SameSite=Lax",
+            max_age.as_secs()
+        ),
+        None => format!("{name}={value}; Path=/; HttpOnly; SameSite=Lax"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"correct horse battery staple";
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signed = sign("user", "alice; role=admin", SECRET, HOUR);
+        assert_eq!(
+            verify("user", &signed, SECRET, now()).as_deref(),
+            Some("alice; role=admin")
+        );
+        // Signed for a different cookie name
+        assert_eq!(verify("admin", &signed, SECRET, now()), None);
+    }
+
+    #[test]
+    fn test_verify_rejects_tampering() {
+        let signed = sign("user", "alice", SECRET, HOUR);
+        let (_, rest) = signed.split_once('.').unwrap();
+        let forged = format!("{}.{rest}", URL_SAFE_NO_PAD.encode("mallory"));
+        assert_eq!(verify("user", &forged, SECRET, now()), None);
+
+        // Pushing the expiry out breaks the signature too
+        let mut parts: Vec<&str> = signed.split('.').collect();
+        let later = (now() + 10 * 3600).to_string();
+        parts[1] = &later;
+        assert_eq!(verify("user", &parts.join("."), SECRET, now()), None);
+
+        assert_eq!(verify("user", "garbage", SECRET, now()), None);
+    }
+
+    #[test]
+    fn test_verify_rejects_wrong_secret_and_expired() {
+        let signed = sign("user", "alice", SECRET, HOUR);
+        assert_eq!(verify("user", &signed, b"another secret", now()), None);
+
+        let expired = sign("user", "alice", SECRET, Duration::ZERO);
+        assert_eq!(verify("user", &expired, SECRET, now()), None);
+    }
+}
@@ -0,0 +1,354 @@
+//! Cross-origin resource sharing.
+//!
+//! A [`Cors`] policy is middleware, so it can be applied to the whole engine
+//! with [`Engine::cors`](crate::Engine::cors) or to a single group with
+//! [`RouterGroup::cors`](crate::RouterGroup::cors), e.g. letting any origin
+//! read `/public` while `/api` only answers its own frontend. When policies
+//! are layered, the innermost one decides alone, for preflight requests as
+//! well as for the actual ones: a group's policy replaces the engine's.
+
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use hyper::{
+    HeaderMap, Method, StatusCode,
+    header::{self, HeaderValue},
+};
+
+use crate::{Next, RequestCtx, Response, ResponseBuilder};
+
+/// Marks a response whose CORS headers a [`Cors`] policy has decided on
+#[derive(Clone, Copy)]
+struct Decided;
+
+/// A CORS policy, applied as middleware
+///
+/// `Cors::new()` allows any origin with the common methods and the
+/// `Content-Type` and `Authorization` headers; [`Cors::allow_origin`]
+/// restricts it to a list.
+///
+/// ```rust
+/// use ree::{Cors, Engine};
+///
+/// let mut app = Engine::new();
+/// app.group("/public").cors(Cors::new()).get("/feed", |_| async { "feed" });
+/// app.group("/api")
+///     .cors(Cors::new().allow_origin("https://app.example.com").allow_credentials(true))
+///     .get("/me", |_| async { "me" });
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cors {
+    /// Allowed origins, empty for any
+    origins: Vec<String>,
+    methods: Vec<String>,
+    headers: Vec<String>,
+    expose_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cors {
+    /// A policy allowing any origin
+    pub fn new() -> Self {
+        Self {
+            origins: Vec::new(),
+            methods: ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            headers: vec!["Content-Type".to_string(), "Authorization".to_string()],
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allow requests from `origin`, e.g. `https://app.example.com`
+    ///
+    /// Can be called several times; once it has been, other origins get no
+    /// CORS headers. `"*"` keeps allowing any origin.
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        if origin != "*" {
+            self.origins.push(origin.trim_end_matches('/').to_string());
+        }
+        self
+    }
+
+    /// Methods allowed in preflighted requests
+    pub fn allow_methods(mut self, methods: &[&str]) -> Self {
+        self.methods = methods.iter().map(|method| method.to_string()).collect();
+        self
+    }
+
+    /// Request headers allowed in preflighted requests
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Response headers scripts may read beyond the CORS-safelisted ones
+    pub fn expose_headers(mut self, headers: &[&str]) -> Self {
+        self.expose_headers = headers.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Let requests carry cookies and `Authorization`
+    ///
+    /// Only for origins listed with [`Cors::allow_origin`], whose value is then
+    /// echoed since browsers reject `*` for credentialed requests. Allowing
+    /// credentials from any origin would let every website read responses with
+    /// the user's cookies, so [`Cors::middleware`] panics on such a policy.
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.credentials = allow;
+        self
+    }
+
+    /// How long browsers may cache a preflight response
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// The `Access-Control-Allow-Origin` value for a request from `origin`
+    fn allowed_origin(&self, origin: &str) -> Option<HeaderValue> {
+        if self.origins.is_empty() {
+            return Some(HeaderValue::from_static("*"));
+        }
+        if self.origins.iter().any(|allowed| allowed == origin) {
+            return HeaderValue::from_str(origin).ok();
+        }
+        None
+    }
+
+    /// Whether responses differ by `Origin`, which caches then need to know
+    fn varies_by_origin(&self) -> bool {
+        !self.origins.is_empty() || self.credentials
+    }
+
+    /// Add the headers sent on every CORS response
+    fn apply(&self, headers: &mut HeaderMap, origin: HeaderValue) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    /// Mark `response` as handled by this policy, with `Vary` if needed
+    fn decided(&self, response: &mut Response) {
+        response.extensions_mut().insert(Decided);
+        if self.varies_by_origin() {
+            response
+                .headers_mut()
+                .append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+    }
+
+    /// The `204` answering a preflight request
+    fn preflight(&self, origin: HeaderValue) -> Response {
+        let mut response = ResponseBuilder::new()
+            .status(StatusCode::NO_CONTENT)
+            .header(
+                header::ACCESS_CONTROL_ALLOW_METHODS.as_str(),
+                self.methods.join(", "),
+            )
+            .header(
+                header::ACCESS_CONTROL_ALLOW_HEADERS.as_str(),
+                self.headers.join(", "),
+            )
+            .empty_body();
+        if let Some(max_age) = self.max_age {
+            response
+                .headers_mut()
+                .insert(header::ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+        self.apply(response.headers_mut(), origin);
+        response
+    }
+
+    /// The policy as middleware
+    ///
+    /// Preflight requests, `OPTIONS` with `Origin` and
+    /// `Access-Control-Request-Method`, from an allowed origin are answered with
+    /// `204 No Content`. Other requests from an allowed origin get
+    /// `Access-Control-Allow-Origin` on their response. Requests from other
+    /// origins pass through untouched. Nothing is changed when a policy further
+    /// in has already handled the request. Unless any origin is allowed, every
+    /// response, including those to other origins or without `Origin`, gets
+    /// `Vary: Origin` so shared caches keep the variants apart.
+    ///
+    /// # Panics
+    ///
+    /// If credentials are allowed without an [`Cors::allow_origin`] allowlist.
+    pub fn middleware(
+        self,
+    ) -> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>>
+    + Send
+    + Sync
+    + 'static {
+        assert!(
+            !self.credentials || !self.origins.is_empty(),
+            "Cors::allow_credentials needs an allow_origin allowlist"
+        );
+        let cors = Arc::new(self);
+        move |ctx, next| {
+            let cors = cors.clone();
+            Box::pin(async move {
+                let origin = ctx.header(header::ORIGIN.as_str()).map(str::to_string);
+                let preflight = origin.is_some()
+                    && ctx.method() == Method::OPTIONS
+                    && ctx
+                        .headers()
+                        .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+                let mut response = next(ctx).await;
+                if response.extensions().get::<Decided>().is_some() {
+                    return response;
+                }
+                let Some(allowed) = origin.and_then(|origin| cors.allowed_origin(&origin)) else {
+                    cors.decided(&mut response);
+                    return response;
+                };
+                if preflight {
+                    let mut response = cors.preflight(allowed);
+                    cors.decided(&mut response);
+                    return response;
+                }
+                cors.decided(&mut response);
+                if !cors.expose_headers.is_empty()
+                    && let Ok(value) = HeaderValue::from_str(&cors.expose_headers.join(", "))
+                {
+                    response
+                        .headers_mut()
+                        .insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, value);
+                }
+                cors.apply(response.headers_mut(), allowed);
+                response
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Engine, TestClient};
+
+    fn app() -> TestClient {
+        let mut app = Engine::new();
+        app.cors(Cors::new().allow_origin("https://admin.example.com"));
+        app.group("/public")
+            .cors(Cors::new())
+            .get("/feed", |_| async { "feed" });
+        app.group("/api")
+            .cors(
+                Cors::new()
+                    .allow_origin("https://app.example.com")
+                    .allow_methods(&["GET", "POST"])
+                    .allow_credentials(true),
+            )
+            .get("/me", |_| async { "me" });
+        app.get("/health", |_| async { "ok" });
+        TestClient::new(app)
+    }
+
+    fn from(method: &str, path: &str, origin: &str) -> hyper::Request<&'static str> {
+        let mut request = hyper::Request::builder()
+            .method(method)
+            .uri(path)
+            .header("Origin", origin);
+        if method == "OPTIONS" {
+            request = request.header("Access-Control-Request-Method", "POST");
+        }
+        request.body("").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_group_policies_differ() {
+        let client = app();
+
+        let feed = client
+            .oneshot(from("GET", "/public/feed", "https://evil.example"))
+            .await;
+        assert_eq!(feed.header("access-control-allow-origin"), Some("*"));
+
+        let me = client
+            .oneshot(from("GET", "/api/me", "https://app.example.com"))
+            .await;
+        assert_eq!(me.text(), "me");
+        assert_eq!(
+            me.header("access-control-allow-origin"),
+            Some("https://app.example.com")
+        );
+        assert_eq!(me.header("access-control-allow-credentials"), Some("true"));
+        assert_eq!(me.header("vary"), Some("Origin"));
+
+        let me = client
+            .oneshot(from("GET", "/api/me", "https://evil.example"))
+            .await;
+        assert_eq!(me.header("access-control-allow-origin"), None);
+        assert_eq!(me.header("vary"), Some("Origin"));
+        let me = client
+            .oneshot(hyper::Request::get("/api/me").body("").unwrap())
+            .await;
+        assert_eq!(me.header("vary"), Some("Origin"));
+
+        // Any origin gets the same `*`, so nothing varies
+        assert_eq!(feed.header("vary"), None);
+
+        // The engine-wide policy covers everything else
+        let health = client
+            .oneshot(from("GET", "/health", "https://admin.example.com"))
+            .await;
+        assert_eq!(
+            health.header("access-control-allow-origin"),
+            Some("https://admin.example.com")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "allow_origin allowlist")]
+    fn test_credentials_need_an_allowlist() {
+        let _ = Cors::new().allow_credentials(true).middleware();
+    }
+
+    #[tokio::test]
+    async fn test_preflight_uses_group_policy() {
+        let client = app();
+
+        let preflight = client
+            .oneshot(from("OPTIONS", "/api/me", "https://app.example.com"))
+            .await;
+        assert_eq!(preflight.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            preflight.header("access-control-allow-origin"),
+            Some("https://app.example.com")
+        );
+        assert_eq!(
+            preflight.header("access-control-allow-methods"),
+            Some("GET, POST")
+        );
+
+        let preflight = client
+            .oneshot(from("OPTIONS", "/public/feed", "https://app.example.com"))
+            .await;
+        assert_eq!(preflight.status(), StatusCode::NO_CONTENT);
+        assert_eq!(preflight.header("access-control-allow-origin"), Some("*"));
+
+        // The engine's policy allows this origin, but `/api` has its own
+        for origin in ["https://admin.example.com", "https://evil.example"] {
+            let preflight = client.oneshot(from("OPTIONS", "/api/me", origin)).await;
+            assert_eq!(preflight.status(), StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(preflight.header("access-control-allow-origin"), None);
+        }
+        let preflight = client
+            .oneshot(from("OPTIONS", "/health", "https://admin.example.com"))
+            .await;
+        assert_eq!(preflight.status(), StatusCode::NO_CONTENT);
+    }
+}
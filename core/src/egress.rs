@@ -0,0 +1,155 @@
+//! Response bytes sent per request, for egress metrics.
+//!
+//! The engine wraps every response body in a counter and records a
+//! [`BytesSent`] in the request's [`LogContext`](crate::LogContext). The count
+//! grows as body frames are handed to the connection, so it's only final once
+//! the body is done; [`BytesSent::on_finish`] runs code at that point, which is
+//! how [`logger`](crate::logger) reports `bytes=` after the response is sent.
+
+use std::{
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+};
+
+use http_body_util::BodyExt;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+
+use crate::Response;
+
+/// Callback waiting for the final count
+type OnFinish = Box<dyn FnOnce(u64) + Send>;
+
+/// Response body bytes sent for one request, read from its `LogContext`
+#[derive(Clone)]
+pub struct BytesSent(Arc<SentInner>);
+
+struct SentInner {
+    count: AtomicU64,
+    /// Callbacks for [`BytesSent::on_finish`], `None` once the body is done
+    pending: Mutex<Option<Vec<OnFinish>>>,
+}
+
+impl BytesSent {
+    /// A count for a body that hasn't started yet
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(SentInner {
+            count: AtomicU64::new(0),
+            pending: Mutex::new(Some(Vec::new())),
+        }))
+    }
+
+    /// Body bytes handed to the connection so far
+    pub fn get(&self) -> u64 {
+        self.0.count.load(Ordering::Relaxed)
+    }
+
+    /// Run `callback` with the final count once the body is sent or dropped
+    ///
+    /// A body dropped early, e.g. because the client went away, finishes with
+    /// the bytes sent up to then. Runs right away if the body is already done.
+    pub fn on_finish(&self, callback: impl FnOnce(u64) + Send + 'static) {
+        let mut pending = self.0.pending.lock().unwrap();
+        match pending.as_mut() {
+            Some(callbacks) => callbacks.push(Box::new(callback)),
+            None => {
+                drop(pending);
+                callback(self.get());
+            }
+        }
+    }
+
+    /// Mark the body as done and run the waiting callbacks
+    fn finish(&self) {
+        let callbacks = self.0.pending.lock().unwrap().take();
+        let count = self.get();
+        for callback in callbacks.into_iter().flatten() {
+            callback(count);
+        }
+    }
+}
+
+/// A response body counting the data it yields into a [`BytesSent`]
+struct CountingBody {
+    inner: crate::context::BodyStream,
+    sent: BytesSent,
+}
+
+impl Body for CountingBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, hyper::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.sent
+                        .0
+                        .count
+                        .fetch_add(data.len() as u64, Ordering::Relaxed);
+                }
+                if self.inner.is_end_stream() {
+                    self.sent.finish();
+                }
+            }
+            Poll::Ready(_) => self.sent.finish(),
+            Poll::Pending => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CountingBody {
+    fn drop(&mut self) {
+        self.sent.finish();
+    }
+}
+
+/// Count the body of `response` into `sent` as it's sent
+pub(crate) fn count_body(response: Response, sent: BytesSent) -> Response {
+    response.map(|inner| CountingBody { inner, sent }.boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Engine, TestClient, logger_with};
+
+    #[tokio::test]
+    async fn test_logger_reports_bytes_sent() {
+        let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = lines.clone();
+
+        let mut app = Engine::new();
+        app.use_middleware(logger_with(move |line| {
+            sink.lock().unwrap().push(line.to_string())
+        }));
+        app.get("/report", |_| async { "x".repeat(1500) });
+        let client = TestClient::new(app);
+
+        let response = client.get("/report").await;
+        assert_eq!(response.body().len(), 1500);
+        let request = hyper::Request::head("/report").body("").unwrap();
+        client.oneshot(request).await;
+
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" bytes=1500"), "{}", lines[0]);
+        // HEAD responses drop their body before it's sent
+        assert!(lines[1].ends_with(" bytes=0"), "{}", lines[1]);
+    }
+}
@@ -1,46 +1,186 @@
 //! Main HTTP engine and router group implementations.
 
 use std::{
-    collections::HashMap, convert::Infallible, future::Future, net::SocketAddr, pin::Pin, sync::Arc,
+    borrow::Cow,
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
 
-use hyper::{server::conn::http1, service::service_fn};
-use hyper_util::{rt::TokioIo, server::graceful::GracefulShutdown};
+use http_body_util::BodyExt;
+use hyper::{HeaderMap, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use tokio::io::AsyncWriteExt;
+use tracing::Instrument;
 
 use crate::{
-    Handler, Middleware, Next, RequestCtx, Response, Router, execute_chain, middleware::IntoNext,
-    response::IntoResponse,
+    BytesSent, Clock, Handler, IntoResponse, Middleware, Next, RequestCtx, Response,
+    ResponseBuilder, Router, TrailingSlash,
+    egress::count_body,
+    error::{ErrorMapper, ErrorMappers, JsonErrorFormatter},
+    execute_chain,
+    files::{ServeDir, StaticAsset, read_manifest, spa_fallback},
+    inject::Services,
+    middleware::IntoNext,
+    panic::{CatchUnwind, ErrorContext, panic_message},
+    query::DuplicateKeys,
+    swagger::RouteDoc,
 };
 
+/// Name listed by `middleware_names` for middleware registered without one
+const ANONYMOUS_MIDDLEWARE: &str = "<anonymous>";
+
 /// A group of routes with shared prefix and middleware
 pub struct RouterGroup {
     prefix: String,
     router: Router,
     middlewares: Vec<Middleware>,
+    middleware_names: Vec<String>,
+    default_headers: HeaderMap,
+    strip_prefix: bool,
+    /// Groups nested with [`RouterGroup::group`]
+    subgroups: Vec<RouterGroup>,
 }
 
 impl RouterGroup {
-    fn new(prefix: String) -> Self {
+    /// A group not yet attached to an engine, see [`Engine::mount_group`]
+    ///
+    /// Building a group on its own keeps `app` free for other routes meanwhile,
+    /// and lets a module hand back its routes from a plain function.
+    pub fn new(prefix: &str) -> Self {
         Self {
-            prefix,
+            prefix: prefix.to_string(),
             router: Router::new(),
             middlewares: Vec::new(),
+            middleware_names: Vec::new(),
+            default_headers: HeaderMap::new(),
+            strip_prefix: false,
+            subgroups: Vec::new(),
+        }
+    }
+
+    /// Create a group nested under this one, serving `prefix` + `sub_prefix`
+    ///
+    /// Requests to the nested group run this group's middleware ahead of its
+    /// own, including middleware added here later, and get this group's default
+    /// headers unless it sets them itself. Routing between the two follows the
+    /// longest-prefix rule of [`Engine::group`]; a nested group already created
+    /// at the same sub-prefix is replaced.
+    ///
+    /// ```rust
+    /// use ree::{Engine, RequestCtx};
+    ///
+    /// let mut app = Engine::new();
+    /// let api = app.group("/api");
+    /// api.use_middleware(|ctx: RequestCtx, next| async move { next(ctx).await });
+    /// api.group("/v1").get("/users", |_| async { "v1 users" });
+    /// ```
+    pub fn group(&mut self, sub_prefix: &str) -> &mut RouterGroup {
+        let prefix = format!("{}{}", self.prefix, sub_prefix);
+        self.subgroups.retain(|group| group.prefix != prefix);
+        self.subgroups.push(RouterGroup::new(&prefix));
+        self.subgroups.last_mut().unwrap()
+    }
+
+    /// This group followed by every group nested under it, depth first
+    fn with_subgroups(&self) -> Vec<&RouterGroup> {
+        let mut groups = vec![self];
+        for group in &self.subgroups {
+            groups.extend(group.with_subgroups());
+        }
+        groups
+    }
+
+    /// Split off the nested groups, each carrying its parents' middleware
+    ///
+    /// Parent middleware goes ahead of the group's own, so it runs first.
+    fn flatten(mut self) -> Vec<RouterGroup> {
+        let subgroups = std::mem::take(&mut self.subgroups);
+        let mut groups = Vec::with_capacity(subgroups.len() + 1);
+        for mut group in subgroups {
+            let middlewares = std::mem::take(&mut group.middlewares);
+            let names = std::mem::take(&mut group.middleware_names);
+            group.middlewares = self
+                .middlewares
+                .iter()
+                .cloned()
+                .chain(middlewares)
+                .collect();
+            group.middleware_names = self.middleware_names.iter().cloned().chain(names).collect();
+            for name in self.default_headers.keys() {
+                if !group.default_headers.contains_key(name) {
+                    for value in self.default_headers.get_all(name) {
+                        group.default_headers.append(name.clone(), value.clone());
+                    }
+                }
+            }
+            groups.extend(group.flatten());
         }
+        groups.insert(0, self);
+        groups
     }
 
     /// Add a route to this group
+    ///
+    /// Panics on a duplicate or conflicting route, see [`Router::add_route`].
     pub fn add_route(&mut self, method: &str, pattern: &str, handler: impl Handler) {
         let handler = Box::new(handler);
         let full_pattern = format!("{}{}", self.prefix, pattern);
         self.router.add_route(method, &full_pattern, handler);
     }
 
+    /// Add a route to this group with OpenAPI metadata
+    pub fn add_documented_route(
+        &mut self,
+        method: &str,
+        pattern: &str,
+        doc: RouteDoc,
+        handler: impl Handler,
+    ) {
+        let full_pattern = format!("{}{}", self.prefix, pattern);
+        self.router.set_doc(method, &full_pattern, doc);
+        self.add_route(method, pattern, handler);
+    }
+
+    /// Add a documented GET route to this group
+    pub fn get_documented(
+        &mut self,
+        path: &str,
+        doc: RouteDoc,
+        handler: impl Handler,
+    ) -> &mut Self {
+        self.add_documented_route("GET", path, doc, handler);
+        self
+    }
+
     /// Add a GET route to this group
     pub fn get(&mut self, path: &str, handler: impl Handler) -> &mut Self {
         self.add_route("GET", path, handler);
         self
     }
 
+    /// Add a GET route to this group running at most `max` requests at once
+    ///
+    /// See [`limit_concurrency`](crate::limit_concurrency); requests over the
+    /// limit get `503 Service Unavailable`.
+    pub fn get_with_concurrency(
+        &mut self,
+        path: &str,
+        max: usize,
+        handler: impl Handler,
+    ) -> &mut Self {
+        self.add_route("GET", path, crate::limit_concurrency(handler, max));
+        self
+    }
+
     /// Add a POST route to this group
     pub fn post(&mut self, path: &str, handler: impl Handler) -> &mut Self {
         self.add_route("POST", path, handler);
@@ -59,23 +199,232 @@ impl RouterGroup {
         self
     }
 
+    /// Add a PATCH route to this group
+    pub fn patch(&mut self, path: &str, handler: impl Handler) -> &mut Self {
+        self.add_route("PATCH", path, handler);
+        self
+    }
+
+    /// Add an OPTIONS route to this group
+    pub fn options(&mut self, path: &str, handler: impl Handler) -> &mut Self {
+        self.add_route("OPTIONS", path, handler);
+        self
+    }
+
+    /// Add a HEAD route to this group
+    pub fn head(&mut self, path: &str, handler: impl Handler) -> &mut Self {
+        self.add_route("HEAD", path, handler);
+        self
+    }
+
     /// Add middleware to this group
-    pub fn use_middleware<F, Fut>(&mut self, middleware: F) -> &mut Self
+    pub fn use_middleware<F, Fut, R>(&mut self, middleware: F) -> &mut Self
+    where
+        F: Fn(RequestCtx, Next) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
+    {
+        self.use_middleware_named(ANONYMOUS_MIDDLEWARE, middleware)
+    }
+
+    /// Add middleware under a name reported by [`RouterGroup::middleware_names`]
+    pub fn use_middleware_named<F, Fut, R>(&mut self, name: &str, middleware: F) -> &mut Self
     where
         F: Fn(RequestCtx, Next) -> Fut + Send + Sync + 'static,
-        Fut: Future<Output = Response> + Send + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
     {
         let wrapped = move |ctx, next| {
             let fut = middleware(ctx, next);
-            Box::pin(fut) as Pin<Box<dyn Future<Output = Response> + Send>>
+            Box::pin(async move { fut.await.into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send>>
         };
         self.middlewares.push(Arc::new(wrapped));
+        self.middleware_names.push(name.to_string());
+        self
+    }
+
+    /// Apply a CORS policy to this group's routes, replacing the engine's
+    ///
+    /// Preflight requests under the prefix are answered by this policy; see
+    /// [`Cors`](crate::Cors).
+    pub fn cors(&mut self, cors: crate::Cors) -> &mut Self {
+        self.use_middleware_named("cors", cors.middleware())
+    }
+
+    /// Names of the group's middleware in registration (and execution) order
+    ///
+    /// Middleware added with `use_middleware` is listed as `"<anonymous>"`.
+    pub fn middleware_names(&self) -> Vec<String> {
+        self.middleware_names.clone()
+    }
+
+    /// Set headers added to every response served by this group
+    ///
+    /// Headers already present on a response (e.g. set by the handler) are kept as-is.
+    pub fn default_headers(&mut self, headers: HeaderMap) -> &mut Self {
+        self.default_headers.extend(headers);
+        self
+    }
+
+    /// Hand handlers the path without the group prefix
+    ///
+    /// By default a handler in the `/admin` group sees `/admin/users` from
+    /// [`RequestCtx::path`]; with `strip` set it sees `/users`, which suits
+    /// handlers written to be mounted anywhere. Routing and the group's
+    /// middleware always see the full path.
+    pub fn strip_prefix(&mut self, strip: bool) -> &mut Self {
+        self.strip_prefix = strip;
         self
     }
 
     /// Handle a request using this group's router
     pub async fn handle_request(&self, ctx: RequestCtx) -> Response {
-        self.router.handle_request(ctx).await
+        let strip = self.strip_prefix.then_some(self.prefix.as_str());
+        self.router.handle_request_stripping(ctx, strip).await
+    }
+
+    /// Merge the group's default headers into a response without overwriting
+    fn apply_default_headers(&self, response: &mut Response) {
+        for name in self.default_headers.keys() {
+            if !response.headers().contains_key(name) {
+                for value in self.default_headers.get_all(name) {
+                    response.headers_mut().append(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Runtime-switchable maintenance mode answering `503` with `Retry-After`
+struct Maintenance {
+    enabled: Arc<AtomicBool>,
+    retry_after: Duration,
+    allowlist: Vec<String>,
+}
+
+impl Maintenance {
+    /// Whether a request for `path` should be short-circuited
+    fn blocks(&self, path: &str) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+            && !self.allowlist.iter().any(|allowed| allowed == path)
+    }
+
+    fn response(&self) -> Response {
+        ResponseBuilder::new()
+            .status(hyper::StatusCode::SERVICE_UNAVAILABLE)
+            .header("Retry-After", self.retry_after.as_secs().to_string())
+            .content_type("text/plain; charset=utf-8")
+            .body("503 Service Unavailable")
+    }
+}
+
+/// Builds the body of a `414`/`431` rejection, see [`Engine::oversized_response`]
+type OversizedResponse = Arc<dyn Fn(&RequestCtx, hyper::StatusCode) -> Response + Send + Sync>;
+
+/// Async setup run before the server binds, see [`Engine::on_startup`]
+type StartupHook = Box<
+    dyn FnOnce() -> Pin<
+            Box<dyn Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send>,
+        > + Send,
+>;
+
+/// Async cleanup run after the server drained, see [`Engine::on_shutdown`]
+type ShutdownHook = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+/// Names the tracing span of a request, see [`Engine::tracing_span_name`]
+type SpanName = Arc<dyn Fn(&RequestCtx) -> String + Send + Sync>;
+
+/// Builds the response for a panicking request, see [`Engine::on_panic`]
+type PanicHandler = Arc<dyn Fn(&ErrorContext, &str) -> Response + Send + Sync>;
+
+/// Every (method, pattern) routed and the metadata documented for them
+type DocumentedRoutes = (Vec<(String, String)>, HashMap<(String, String), RouteDoc>);
+
+/// Per-engine caps on the size of the request line and headers
+#[derive(Clone, Default)]
+struct SizeLimits {
+    max_uri_length: Option<usize>,
+    max_header_size: Option<usize>,
+    response: Option<OversizedResponse>,
+}
+
+impl SizeLimits {
+    /// The status to reject `ctx` with, if it is over a limit
+    fn exceeded(&self, ctx: &RequestCtx) -> Option<hyper::StatusCode> {
+        // As the request line spells it, without formatting the URI
+        let uri = ctx.uri();
+        let uri_length = uri.scheme_str().map_or(0, |scheme| scheme.len() + 3)
+            + uri.authority().map_or(0, |host| host.as_str().len())
+            + uri.path_and_query().map_or(0, |path| path.as_str().len());
+        if self.max_uri_length.is_some_and(|max| uri_length > max) {
+            return Some(hyper::StatusCode::URI_TOO_LONG);
+        }
+
+        // Counted as on the wire: `name: value\r\n`
+        let header_size: usize = ctx
+            .headers()
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len() + 4)
+            .sum();
+        if self.max_header_size.is_some_and(|max| header_size > max) {
+            return Some(hyper::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        }
+        None
+    }
+
+    fn response(&self, ctx: &RequestCtx, status: hyper::StatusCode) -> Response {
+        match &self.response {
+            Some(response) => response(ctx, status),
+            None => ResponseBuilder::error_for(ctx, status),
+        }
+    }
+}
+
+/// Swap the `charset=utf-8` of a `text/plain` or `text/html` response for `charset`
+fn set_text_charset(response: &mut Response, charset: Option<&str>) {
+    let Some(content_type) = response
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return;
+    };
+    let media_type = crate::context::media_type(content_type);
+    let is_utf8 = content_type
+        .split(';')
+        .skip(1)
+        .any(|param| param.trim().eq_ignore_ascii_case("charset=utf-8"));
+    if !matches!(media_type.as_str(), "text/plain" | "text/html") || !is_utf8 {
+        return;
+    }
+
+    let content_type = match charset {
+        Some(charset) => format!("{media_type}; charset={charset}"),
+        None => media_type,
+    };
+    if let Ok(value) = hyper::header::HeaderValue::from_str(&content_type) {
+        response
+            .headers_mut()
+            .insert(hyper::header::CONTENT_TYPE, value);
+    }
+}
+
+/// Drop the body of a response to `HEAD`, keeping the length it would have had
+fn strip_body(response: &mut Response) {
+    let body = std::mem::replace(
+        response.body_mut(),
+        ResponseBuilder::new().empty_body().into_body(),
+    );
+    if let Some(len) = hyper::body::Body::size_hint(&body).exact()
+        && len > 0
+        && !response
+            .headers()
+            .contains_key(hyper::header::CONTENT_LENGTH)
+    {
+        response
+            .headers_mut()
+            .insert(hyper::header::CONTENT_LENGTH, len.into());
     }
 }
 
@@ -85,6 +434,39 @@ pub struct Engine {
     router: Router,
     groups: HashMap<String, RouterGroup>,
     middlewares: Vec<Middleware>,
+    middleware_names: Vec<String>,
+    matched_middlewares: Vec<Middleware>,
+    matched_middleware_names: Vec<String>,
+    hosts: Vec<(String, RouterGroup)>,
+    maintenance: Option<Maintenance>,
+    maintenance_allowlist: Vec<String>,
+    duplicate_keys: DuplicateKeys,
+    json_error_formatter: Option<JsonErrorFormatter>,
+    error_mappers: ErrorMappers,
+    problem_details: bool,
+    services: Services,
+    /// Replacement for the system clock, see [`Engine::with_clock`]
+    clock: Option<Arc<dyn Clock>>,
+    case_insensitive_params: bool,
+    api_prefixes: Vec<String>,
+    size_limits: SizeLimits,
+    /// Replacement for the `utf-8` charset of text responses, `None` for no change
+    text_charset: Option<Option<String>>,
+    span_name: Option<SpanName>,
+    panic_handler: Option<PanicHandler>,
+    startup_hooks: Vec<StartupHook>,
+    shutdown_hooks: Vec<ShutdownHook>,
+    max_body_size: Option<u64>,
+    trailing_slash: TrailingSlash,
+    request_timeout: Option<Duration>,
+    max_connections_per_ip: Option<usize>,
+    max_requests_per_connection: Option<usize>,
+    reuse_address: bool,
+    reuse_port: bool,
+    route_table: RouteTable,
+    /// Set once graceful shutdown begins, see [`Engine::health_endpoints`]
+    draining: Arc<AtomicBool>,
+    quiet: bool,
 }
 
 impl Engine {
@@ -94,42 +476,715 @@ impl Engine {
             router: Router::new(),
             groups: HashMap::new(),
             middlewares: Vec::new(),
+            middleware_names: Vec::new(),
+            matched_middlewares: Vec::new(),
+            matched_middleware_names: Vec::new(),
+            hosts: Vec::new(),
+            maintenance: None,
+            maintenance_allowlist: Vec::new(),
+            duplicate_keys: DuplicateKeys::default(),
+            json_error_formatter: None,
+            error_mappers: ErrorMappers::default(),
+            problem_details: false,
+            services: Services::default(),
+            clock: None,
+            case_insensitive_params: false,
+            api_prefixes: Vec::new(),
+            size_limits: SizeLimits::default(),
+            text_charset: None,
+            span_name: None,
+            panic_handler: None,
+            startup_hooks: Vec::new(),
+            shutdown_hooks: Vec::new(),
+            max_body_size: None,
+            trailing_slash: TrailingSlash::Match,
+            request_timeout: None,
+            max_connections_per_ip: None,
+            max_requests_per_connection: None,
+            reuse_address: true,
+            reuse_port: false,
+            route_table: RouteTable::default(),
+            draining: Arc::new(AtomicBool::new(false)),
+            quiet: false,
+        }
+    }
+
+    /// Close keep-alive connections after they've carried `max` requests
+    ///
+    /// The last response is sent with `Connection: close`, so the client has to
+    /// reconnect and one connection can't hold on to a worker indefinitely.
+    pub fn max_requests_per_connection(&mut self, max: usize) -> &mut Self {
+        self.max_requests_per_connection = Some(max);
+        self
+    }
+
+    /// Set `SO_REUSEADDR` on listening sockets, on by default
+    ///
+    /// Lets a restarted server bind its port while connections of the previous
+    /// process are still in `TIME_WAIT`. Only applied on Unix; Windows gives the
+    /// option a different, unsafe meaning.
+    pub fn reuse_address(&mut self, enabled: bool) -> &mut Self {
+        self.reuse_address = enabled;
+        self
+    }
+
+    /// Set `SO_REUSEPORT` on listening sockets, off by default
+    ///
+    /// Several processes can then bind the same address, with the kernel
+    /// spreading connections between them, for multi-process scaling and
+    /// handing a port over to a new process without downtime. Binding fails with
+    /// [`ErrorKind::Unsupported`](std::io::ErrorKind::Unsupported) on platforms
+    /// without the option, such as Windows.
+    pub fn reuse_port(&mut self, enabled: bool) -> &mut Self {
+        self.reuse_port = enabled;
+        self
+    }
+
+    /// A listener on `addr` with the configured socket options
+    fn listen(&self, addr: SocketAddr) -> std::io::Result<tokio::net::TcpListener> {
+        let socket = if addr.is_ipv4() {
+            tokio::net::TcpSocket::new_v4()?
+        } else {
+            tokio::net::TcpSocket::new_v6()?
+        };
+        #[cfg(unix)]
+        socket.set_reuseaddr(self.reuse_address)?;
+        if self.reuse_port {
+            #[cfg(all(
+                unix,
+                not(target_os = "solaris"),
+                not(target_os = "illumos"),
+                not(target_os = "cygwin")
+            ))]
+            socket.set_reuseport(true)?;
+            #[cfg(not(all(
+                unix,
+                not(target_os = "solaris"),
+                not(target_os = "illumos"),
+                not(target_os = "cygwin")
+            )))]
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "SO_REUSEPORT is not supported on this platform",
+            ));
+        }
+        socket.bind(addr)?;
+        socket.listen(1024)
+    }
+
+    /// A handle for swapping the engine's top-level routes while it serves
+    ///
+    /// Once the engine is bound, [`RouteTable::replace_routes`] atomically
+    /// replaces the routes registered directly on the engine with another
+    /// [`Router`]. Server-wide middleware, groups and host routes are kept.
+    ///
+    /// ```rust
+    /// use ree::{Engine, RequestCtx, Router};
+    ///
+    /// let mut app = Engine::new();
+    /// app.get("/", |_ctx: RequestCtx| async { "v1" });
+    /// let routes = app.route_table();
+    ///
+    /// // Later, e.g. when a feature flag flips
+    /// let mut next = Router::new();
+    /// next.add_route("GET", "/", Box::new(|_ctx: RequestCtx| async { "v2" }));
+    /// routes.replace_routes(next);
+    /// ```
+    pub fn route_table(&self) -> RouteTable {
+        self.route_table.clone()
+    }
+
+    /// Don't print the startup summary when serving
+    pub fn quiet(&mut self) -> &mut Self {
+        self.quiet = true;
+        self
+    }
+
+    /// Every registered route as `(method, full pattern)`, sorted by pattern
+    ///
+    /// Host-specific routes are listed with their host in front of the pattern.
+    pub fn routes(&self) -> Vec<(String, String)> {
+        let mut routes = self.router.get_all_routes();
+        for group in self.groups.values().flat_map(RouterGroup::with_subgroups) {
+            routes.extend(group.router.get_all_routes());
+        }
+        for (host, group) in &self.hosts {
+            for group in group.with_subgroups() {
+                routes.extend(
+                    group
+                        .router
+                        .get_all_routes()
+                        .into_iter()
+                        .map(|(method, pattern)| (method, format!("{host}{pattern}"))),
+                );
+            }
+        }
+        routes.sort_by(|a, b| (&a.1, &a.0).cmp(&(&b.1, &b.0)));
+        routes
+    }
+
+    /// Number of registered routes, counting each method separately
+    ///
+    /// Covers the same routes as [`Engine::routes`].
+    pub fn route_count(&self) -> usize {
+        self.routes().len()
+    }
+
+    /// Number of route groups, nested ones included
+    ///
+    /// Counts the groups created with [`Engine::group`] or mounted, each group
+    /// created inside one with [`RouterGroup::group`], and every host from
+    /// [`Engine::host`] along with the groups nested in it.
+    pub fn group_count(&self) -> usize {
+        self.groups
+            .values()
+            .chain(self.hosts.iter().map(|(_, group)| group))
+            .map(|group| group.with_subgroups().len())
+            .sum()
+    }
+
+    /// The banner printed when serving: the address and a table of all routes
+    pub fn startup_summary(&self, addr: SocketAddr) -> String {
+        let routes = self.routes();
+        let mut summary = format!("🚀 Server running on http://{addr}\n");
+        if routes.iter().any(|(_, pattern)| pattern == "/docs/") {
+            summary.push_str(&format!("📖 Swagger UI available at http://{addr}/docs/\n"));
         }
+        summary.push_str(&format!("📋 Routes ({}):\n", routes.len()));
+        for (method, pattern) in routes {
+            summary.push_str(&format!("   {method:<7} {pattern}\n"));
+        }
+        summary
+    }
+
+    /// Print [`Engine::startup_summary`] to stdout
+    pub fn print_startup_summary(&self, addr: SocketAddr) {
+        print!("{}", self.startup_summary(addr));
+    }
+
+    /// Cap the number of simultaneous connections from a single client IP
+    ///
+    /// Connections over the cap are answered with an immediate
+    /// `503 Service Unavailable` and closed, before any request is read.
+    pub fn max_connections_per_ip(&mut self, max: usize) -> &mut Self {
+        self.max_connections_per_ip = Some(max);
+        self
+    }
+
+    /// Choose how repeated query/form keys (`?x=1&x=2`) are decoded by
+    /// [`RequestCtx::query_as`], [`RequestCtx::form`] and [`RequestCtx::body_as`]
+    pub fn duplicate_param_policy(&mut self, policy: DuplicateKeys) -> &mut Self {
+        self.duplicate_keys = policy;
+        self
+    }
+
+    /// Shape the `400` body sent when [`RequestCtx::json`] finds invalid JSON
+    ///
+    /// By default the body is a generic `{"error": "Invalid JSON body", "status": 400}`
+    /// that doesn't reveal parser internals.
+    ///
+    /// ```rust
+    /// use ree::Engine;
+    ///
+    /// let mut app = Engine::new();
+    /// app.json_error_formatter(|err| {
+    ///     serde_json::json!({ "error": "malformed JSON", "line": err.line(), "column": err.column() })
+    /// });
+    /// ```
+    pub fn json_error_formatter<F>(&mut self, formatter: F) -> &mut Self
+    where
+        F: Fn(&serde_json::Error) -> serde_json::Value + Send + Sync + 'static,
+    {
+        self.json_error_formatter = Some(Arc::new(formatter));
+        self
+    }
+
+    /// Send the framework's error responses as RFC 7807 problem details
+    ///
+    /// Errors built with [`ResponseBuilder::error_for`], such as `413`, `502`
+    /// and `503` answers, get an `application/problem+json` body like
+    /// `{"type": "about:blank", "title": "Payload Too Large", "status": 413}`
+    /// instead of depending on the client's `Accept`.
+    pub fn problem_details(&mut self, enabled: bool) -> &mut Self {
+        self.problem_details = enabled;
+        self
+    }
+
+    /// Make `service` available to handlers through [`RequestCtx::inject`]
+    ///
+    /// Services are looked up by the type they're provided as, which can be a
+    /// trait object. Providing a second service as the same type replaces the
+    /// first.
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    ///
+    /// use ree::{Engine, RequestCtx};
+    ///
+    /// trait Greeter: Send + Sync {
+    ///     fn greet(&self, name: &str) -> String;
+    /// }
+    ///
+    /// struct English;
+    ///
+    /// impl Greeter for English {
+    ///     fn greet(&self, name: &str) -> String {
+    ///         format!("Hello, {name}!")
+    ///     }
+    /// }
+    ///
+    /// let mut app = Engine::new();
+    /// app.provide::<dyn Greeter>(Arc::new(English));
+    /// app.get("/hello/:name", |ctx: RequestCtx| async move {
+    ///     let greeter = ctx.inject::<dyn Greeter>().unwrap();
+    ///     greeter.greet(ctx.get_param("name").map_or("world", String::as_str))
+    /// });
+    /// ```
+    pub fn provide<T: ?Sized + Send + Sync + 'static>(&mut self, service: Arc<T>) -> &mut Self {
+        self.services.insert(service);
+        self
+    }
+
+    /// Share `state` with every handler through [`RequestCtx::state`]
+    ///
+    /// For application state such as a connection pool or configuration; it's
+    /// [`Engine::provide`] for a value the engine wraps in an `Arc`. State
+    /// is keyed by its type, so several types coexist and registering a second
+    /// value of the same type replaces the first.
+    ///
+    /// ```rust
+    /// use ree::{Engine, RequestCtx};
+    ///
+    /// struct Config {
+    ///     greeting: String,
+    /// }
+    ///
+    /// let mut app = Engine::new();
+    /// app.with_state(Config { greeting: "hi".to_string() });
+    /// app.get("/", |ctx: RequestCtx| async move {
+    ///     ctx.state::<Config>().unwrap().greeting.clone()
+    /// });
+    /// ```
+    pub fn with_state<T: Send + Sync + 'static>(&mut self, state: T) -> &mut Self {
+        self.provide(Arc::new(state))
+    }
+
+    /// Read the time from `clock` instead of the system clock
+    ///
+    /// Handlers and middleware get it from [`RequestCtx::now`], and expiry
+    /// checks such as [`RequestCtx::signed_cookie`] use it. Meant for tests,
+    /// together with a [`ManualClock`](crate::ManualClock).
+    pub fn with_clock(&mut self, clock: impl Clock) -> &mut Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Answer handler errors of type `E` with the status its [`ErrorMapper`] picks
+    ///
+    /// See [`ErrorMapper`] for an example. The mapping only applies to this
+    /// engine's handlers, also to `E` boxed as `Box<dyn Error + Send + Sync>`;
+    /// [`register_error_mapper`](crate::register_error_mapper) sets one for
+    /// the whole process.
+    pub fn map_error<E: ErrorMapper>(&mut self) -> &mut Self {
+        self.error_mappers.insert::<E>();
+        self
+    }
+
+    /// Treat paths under `prefix` as API requests that always get JSON errors
+    ///
+    /// See [`RequestCtx::wants_json`].
+    pub fn api_prefix(&mut self, prefix: &str) -> &mut Self {
+        self.api_prefixes.push(prefix.to_string());
+        self
+    }
+
+    /// Refuse request bodies over `max` bytes with `413 Payload Too Large`
+    ///
+    /// The limit applies to bodies the engine buffers for handlers. Handlers
+    /// wrapped in [`streaming`](crate::streaming) opt out: their body stays on the
+    /// connection, and the size cap is whatever they pass to helpers like
+    /// [`RequestCtx::save_body_to_file`]. There is no other way around the limit.
+    pub fn max_body_size(&mut self, max: u64) -> &mut Self {
+        self.max_body_size = Some(max);
+        self
+    }
+
+    /// Choose how requests for `/users/` or `//users` reach a `/users` route
+    ///
+    /// By default they're served as is, see [`Router::parse_pattern`]. With
+    /// [`TrailingSlash::Redirect`] they get `308 Permanent Redirect`, query
+    /// kept, to the path with duplicate slashes collapsed and a trailing slash
+    /// exactly when the route was registered with one: `/docs/` for a `/docs/`
+    /// route, `/files/a/b` for `/files/*path`. `/` is never redirected, and
+    /// paths no route matches get their usual `404` or `405`.
+    pub fn trailing_slash(&mut self, mode: TrailingSlash) -> &mut Self {
+        self.trailing_slash = mode;
+        self
+    }
+
+    /// Answer requests not handled within `limit` with `503` JSON
+    ///
+    /// The limit covers middleware and the handler, not reading the body
+    /// beforehand. Like with the [`timeout`](crate::timeout) handler wrapper,
+    /// which sets a limit for one route, the request's future is dropped once
+    /// it's over, so the handler doesn't keep running in the background.
+    pub fn request_timeout(&mut self, limit: Duration) -> &mut Self {
+        self.request_timeout = Some(limit);
+        self
+    }
+
+    /// Answer requests whose URI is longer than `max` bytes with `414 URI Too Long`
+    pub fn max_uri_length(&mut self, max: usize) -> &mut Self {
+        self.size_limits.max_uri_length = Some(max);
+        self
+    }
+
+    /// Answer requests whose headers add up to more than `max` bytes with
+    /// `431 Request Header Fields Too Large`
+    pub fn max_header_size(&mut self, max: usize) -> &mut Self {
+        self.size_limits.max_header_size = Some(max);
+        self
+    }
+
+    /// Build the response for requests over [`Engine::max_uri_length`] or
+    /// [`Engine::max_header_size`]
+    ///
+    /// By default they get JSON or HTML depending on [`RequestCtx::wants_json`],
+    /// see [`ResponseBuilder::error_for`].
+    pub fn oversized_response<F>(&mut self, response: F) -> &mut Self
+    where
+        F: Fn(&RequestCtx, hyper::StatusCode) -> Response + Send + Sync + 'static,
+    {
+        self.size_limits.response = Some(Arc::new(response));
+        self
+    }
+
+    /// Choose the `charset` announced on `text/plain` and `text/html` responses
+    ///
+    /// The text and HTML helpers send `charset=utf-8`. `Some("iso-8859-1")`
+    /// replaces that parameter and `None` drops it, for clients that mishandle it.
+    /// Bodies aren't re-encoded, so only pick another charset if the handlers
+    /// encode their text in it.
+    pub fn text_charset(&mut self, charset: Option<&str>) -> &mut Self {
+        self.text_charset = Some(charset.map(str::to_string));
+        self
+    }
+
+    /// Name each request's tracing span with `name` instead of `request`
+    ///
+    /// Every request runs inside a `tracing` span carrying `http.method` and
+    /// `http.route`. Span names are fixed at compile time in `tracing`, so the
+    /// chosen name goes into the `otel.name` field, which OpenTelemetry exporters
+    /// (and so Jaeger) show as the span name. `name` runs after routing, so
+    /// [`RequestCtx::route_pattern`] is already known:
+    ///
+    /// ```rust
+    /// use ree::Engine;
+    ///
+    /// let mut app = Engine::new();
+    /// app.tracing_span_name(|ctx| {
+    ///     format!("{} {}", ctx.method(), ctx.route_pattern().unwrap_or("unmatched"))
+    /// });
+    /// ```
+    pub fn tracing_span_name<F>(&mut self, name: F) -> &mut Self
+    where
+        F: Fn(&RequestCtx) -> String + Send + Sync + 'static,
+    {
+        self.span_name = Some(Arc::new(name));
+        self
+    }
+
+    /// Build the response for requests whose handler or middleware panics
+    ///
+    /// Panics are always caught and answered with `500 Internal Server Error`;
+    /// `handler` replaces that response. It gets the panic message and the
+    /// request's [`ErrorContext`], recorded just before the handler was invoked.
+    ///
+    /// ```rust
+    /// use ree::{Engine, ResponseBuilder, StatusCode};
+    ///
+    /// let mut app = Engine::new();
+    /// app.on_panic(|err, message| {
+    ///     eprintln!("{} {} panicked: {message}", err.method, err.path);
+    ///     ResponseBuilder::new()
+    ///         .status(StatusCode::INTERNAL_SERVER_ERROR)
+    ///         .body(format!("request {} failed", err.request_id.as_deref().unwrap_or("-")))
+    /// });
+    /// ```
+    pub fn on_panic<F>(&mut self, handler: F) -> &mut Self
+    where
+        F: Fn(&ErrorContext, &str) -> Response + Send + Sync + 'static,
+    {
+        self.panic_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Let [`RequestCtx::param_str`] match route parameter names case-insensitively
+    pub fn case_insensitive_params(&mut self, enabled: bool) -> &mut Self {
+        self.case_insensitive_params = enabled;
+        self
+    }
+
+    /// Enable maintenance mode controlled by `enabled`
+    ///
+    /// While the flag is set, every request is answered with `503 Service Unavailable`
+    /// and a `Retry-After` header, except paths added with [`Engine::maintenance_allow`].
+    /// The flag can be flipped at runtime from anywhere holding a clone of the `Arc`.
+    pub fn maintenance_mode(
+        &mut self,
+        enabled: Arc<AtomicBool>,
+        retry_after: Duration,
+    ) -> &mut Self {
+        self.maintenance = Some(Maintenance {
+            enabled,
+            retry_after,
+            allowlist: Vec::new(),
+        });
+        self
+    }
+
+    /// Keep serving `path` (e.g. `/healthz`) while maintenance mode is enabled
+    ///
+    /// May be called before or after [`Engine::maintenance_mode`].
+    pub fn maintenance_allow(&mut self, path: &str) -> &mut Self {
+        self.maintenance_allowlist.push(path.to_string());
+        self
     }
 
     /// Add global middleware
-    pub fn use_middleware<F, Fut>(&mut self, middleware: F) -> &mut Self
+    ///
+    /// Middleware returns anything [`IntoResponse`], including a
+    /// `Result<Response, Rejection>`, so checks can bail out with `?`:
+    ///
+    /// ```rust
+    /// use ree::{Engine, Rejection, StatusCode};
+    ///
+    /// let mut app = Engine::new();
+    /// app.use_middleware(|ctx, next| async move {
+    ///     let token = ctx
+    ///         .header("authorization")
+    ///         .ok_or_else(|| Rejection::new(StatusCode::UNAUTHORIZED, "missing token"))?;
+    ///     if token != "Bearer secret" {
+    ///         return Err(Rejection::new(StatusCode::FORBIDDEN, "invalid token"));
+    ///     }
+    ///     Ok(next(ctx).await)
+    /// });
+    /// ```
+    pub fn use_middleware<F, Fut, R>(&mut self, middleware: F) -> &mut Self
+    where
+        F: Fn(RequestCtx, Next) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
+    {
+        self.use_middleware_named(ANONYMOUS_MIDDLEWARE, middleware)
+    }
+
+    /// Add middleware under a name reported by [`Engine::middleware_names`]
+    pub fn use_middleware_named<F, Fut, R>(&mut self, name: &str, middleware: F) -> &mut Self
     where
         F: Fn(RequestCtx, Next) -> Fut + Send + Sync + 'static,
-        Fut: Future<Output = Response> + Send + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
     {
         let wrapped = move |ctx, next| {
             let fut = middleware(ctx, next);
-            Box::pin(fut) as Pin<Box<dyn Future<Output = Response> + Send>>
+            Box::pin(async move { fut.await.into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send>>
         };
         self.middlewares.push(Arc::new(wrapped));
+        self.middleware_names.push(name.to_string());
+        self
+    }
+
+    /// Add global middleware that only runs for requests matching a route
+    ///
+    /// Requests that end in a 404 skip it, which suits expensive checks like
+    /// authentication. It runs after the `use_middleware` middleware and before
+    /// any group middleware.
+    pub fn use_middleware_on_match<F, Fut, R>(&mut self, middleware: F) -> &mut Self
+    where
+        F: Fn(RequestCtx, Next) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
+    {
+        self.use_middleware_on_match_named(ANONYMOUS_MIDDLEWARE, middleware)
+    }
+
+    /// Add route-only middleware under a name reported by [`Engine::middleware_names`]
+    pub fn use_middleware_on_match_named<F, Fut, R>(
+        &mut self,
+        name: &str,
+        middleware: F,
+    ) -> &mut Self
+    where
+        F: Fn(RequestCtx, Next) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
+    {
+        let wrapped = move |ctx, next| {
+            let fut = middleware(ctx, next);
+            Box::pin(async move { fut.await.into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send>>
+        };
+        self.matched_middlewares.push(Arc::new(wrapped));
+        self.matched_middleware_names.push(name.to_string());
         self
     }
 
+    /// Apply a CORS policy to every request
+    ///
+    /// Groups with a policy of their own, set with [`RouterGroup::cors`], use
+    /// that one instead.
+    pub fn cors(&mut self, cors: crate::Cors) -> &mut Self {
+        self.use_middleware_named("cors", cors.middleware())
+    }
+
+    /// Names of the global middleware in execution order
+    ///
+    /// That's registration order, except that the route-only middleware from
+    /// [`Engine::use_middleware_on_match`] comes last, as it runs after the
+    /// rest. Middleware added without a name is listed as `"<anonymous>"`.
+    pub fn middleware_names(&self) -> Vec<String> {
+        self.middleware_names
+            .iter()
+            .chain(&self.matched_middleware_names)
+            .cloned()
+            .collect()
+    }
+
     /// Create a route group with the given prefix
+    ///
+    /// The group serves the paths at and below `prefix`, segment-wise, with its
+    /// routes registered as `prefix` + pattern. When several groups and the
+    /// engine itself could serve a path, the longest prefix with a route for it
+    /// wins, then a route registered directly on the engine; a path none of
+    /// them routes gets the 404 of the longest matching group.
     pub fn group(&mut self, prefix: &str) -> &mut RouterGroup {
-        let group = RouterGroup::new(prefix.to_string());
+        let group = RouterGroup::new(prefix);
         self.groups.insert(prefix.to_string(), group);
         self.groups.get_mut(prefix).unwrap()
     }
 
+    /// Attach a group built with [`RouterGroup::new`]
+    ///
+    /// Routed the same as a group made with [`Engine::group`]; a group already
+    /// mounted at the same prefix is replaced.
+    ///
+    /// ```rust
+    /// use ree::{Engine, RouterGroup};
+    ///
+    /// fn api() -> RouterGroup {
+    ///     let mut api = RouterGroup::new("/api");
+    ///     api.get("/users", |_| async { "users" });
+    ///     api
+    /// }
+    ///
+    /// let mut app = Engine::new();
+    /// app.get("/", |_| async { "home" });
+    /// app.mount_group(api());
+    /// ```
+    pub fn mount_group(&mut self, group: RouterGroup) -> &mut Self {
+        self.groups.insert(group.prefix.clone(), group);
+        self
+    }
+
+    /// Register routes that only match requests for the given `Host`
+    ///
+    /// `pattern` is an exact host name (`api.example.com`) or a wildcard
+    /// subdomain (`*.example.com`, which doesn't match `example.com` itself).
+    /// Ports are ignored. Requests whose path has no route for the host fall
+    /// back to the host-independent routes.
+    pub fn host(&mut self, pattern: &str) -> &mut RouterGroup {
+        let pattern = pattern.to_ascii_lowercase();
+        let index = match self.hosts.iter().position(|(host, _)| *host == pattern) {
+            Some(index) => index,
+            None => {
+                self.hosts.push((pattern, RouterGroup::new("")));
+                self.hosts.len() - 1
+            }
+        };
+        &mut self.hosts[index].1
+    }
+
     /// Add a route to the main router
+    ///
+    /// Panics on a duplicate or conflicting route, see [`Router::add_route`].
     pub fn add_route(&mut self, method: &str, pattern: &str, handler: impl Handler) {
         let handler = Box::new(handler);
         self.router.add_route(method, pattern, handler);
     }
 
+    /// Add a route dispatched by the request's `Content-Type`
+    ///
+    /// See [`Router::add_route_for_content_type`].
+    pub fn add_route_for_content_type(
+        &mut self,
+        method: &str,
+        pattern: &str,
+        content_type: &str,
+        handler: impl Handler,
+    ) -> &mut Self {
+        self.router
+            .add_route_for_content_type(method, pattern, content_type, Box::new(handler));
+        self
+    }
+
+    /// Remove a top-level route, returning whether it was registered
+    pub fn remove_route(&mut self, method: &str, pattern: &str) -> bool {
+        self.router.remove_route(method, pattern).is_some()
+    }
+
+    /// Add a route to the main router with OpenAPI metadata
+    pub fn add_documented_route(
+        &mut self,
+        method: &str,
+        pattern: &str,
+        doc: RouteDoc,
+        handler: impl Handler,
+    ) {
+        self.router.set_doc(method, pattern, doc);
+        self.add_route(method, pattern, handler);
+    }
+
+    /// Add a documented GET route
+    pub fn get_documented(
+        &mut self,
+        path: &str,
+        doc: RouteDoc,
+        handler: impl Handler,
+    ) -> &mut Self {
+        self.add_documented_route("GET", path, doc, handler);
+        self
+    }
+
     /// Add a GET route
     pub fn get(&mut self, path: &str, handler: impl Handler) -> &mut Self {
         self.add_route("GET", path, handler);
         self
     }
 
+    /// Add a GET route running at most `max` requests at once
+    ///
+    /// Requests over the limit are shed with `503 Service Unavailable`; wrap the
+    /// handler in [`limit_concurrency`](crate::limit_concurrency) yourself to pick
+    /// another status or to limit other methods.
+    ///
+    /// ```rust
+    /// use ree::{Engine, RequestCtx};
+    ///
+    /// let mut app = Engine::new();
+    /// app.get_with_concurrency("/report", 1, |_ctx: RequestCtx| async { "report" });
+    /// ```
+    pub fn get_with_concurrency(
+        &mut self,
+        path: &str,
+        max: usize,
+        handler: impl Handler,
+    ) -> &mut Self {
+        self.add_route("GET", path, crate::limit_concurrency(handler, max));
+        self
+    }
+
     /// Add a POST route
     pub fn post(&mut self, path: &str, handler: impl Handler) -> &mut Self {
         self.add_route("POST", path, handler);
@@ -148,34 +1203,161 @@ impl Engine {
         self
     }
 
-    /// Automatically add swagger endpoints based on registered routes
-    fn add_swagger_endpoints(&mut self) {
-        // Collect all routes from main router and groups
-        let mut all_routes = Vec::new();
+    /// Add a PATCH route
+    pub fn patch(&mut self, path: &str, handler: impl Handler) -> &mut Self {
+        self.add_route("PATCH", path, handler);
+        self
+    }
 
-        // Add routes from main router
-        all_routes.extend(self.router.get_all_routes());
+    /// Add an OPTIONS route
+    pub fn options(&mut self, path: &str, handler: impl Handler) -> &mut Self {
+        self.add_route("OPTIONS", path, handler);
+        self
+    }
 
-        // Add routes from all groups
-        for group in self.groups.values() {
-            all_routes.extend(group.router.get_all_routes());
+    /// Add a HEAD route
+    ///
+    /// The handler can skip building the body and only set headers such as
+    /// `Content-Length`; any body it does return is dropped before sending.
+    pub fn head(&mut self, path: &str, handler: impl Handler) -> &mut Self {
+        self.add_route("HEAD", path, handler);
+        self
+    }
+
+    /// Serve the files below `fs_root` under `prefix`, e.g. `/static`
+    ///
+    /// Request paths are percent-decoded and canonicalized before the lookup;
+    /// anything resolving outside `fs_root` (via `..`, backslashes or symlinks)
+    /// is answered with `404 Not Found`, the same as a missing file, and
+    /// malformed paths with `403 Forbidden`. Directories serve their `index.html`.
+    pub fn static_files(&mut self, prefix: &str, fs_root: impl Into<PathBuf>) -> &mut Self {
+        let pattern = format!("{}/*filepath", prefix.trim_end_matches('/'));
+        self.add_route("GET", &pattern, ServeDir::new(fs_root.into()));
+        self
+    }
+
+    /// Serve the files below `fs_root` under `url_prefix`, the same as
+    /// [`Engine::static_files`]
+    pub fn static_dir(&mut self, url_prefix: &str, fs_root: impl Into<PathBuf>) -> &mut Self {
+        self.static_files(url_prefix, fs_root)
+    }
+
+    /// Host a single-page app built into `dir`, with `index` as its entry page
+    ///
+    /// GETs that no route matches are served from `dir` with the same traversal
+    /// protection as [`Engine::static_files`]. When no file matches and the
+    /// request accepts `text/html`, as browser navigation does, `index` (relative
+    /// to `dir`) is sent instead so deep links reach the client-side router.
+    /// Routes registered on the engine or its groups always take precedence, and
+    /// unrouted API calls that don't ask for HTML still get `404`.
+    ///
+    /// This adds a global middleware, so middleware added earlier, such as a
+    /// logger, sees the file or page it sends.
+    pub fn serve_spa(&mut self, dir: impl Into<PathBuf>, index: impl Into<PathBuf>) -> &mut Self {
+        self.use_middleware_named("serve_spa", spa_fallback(dir.into(), index.into()))
+    }
+
+    /// Serve the assets listed in the JSON manifest at `manifest`
+    ///
+    /// The manifest maps URL paths to files relative to it, as written by
+    /// bundlers emitting hashed file names:
+    ///
+    /// ```json
+    /// { "/assets/app.js": "dist/app.3f2a9c.js" }
+    /// ```
+    ///
+    /// Each entry becomes a GET route sent with a one-year, `immutable`
+    /// `Cache-Control`. Fails if the manifest can't be read or lists a missing file.
+    pub fn static_manifest(&mut self, manifest: impl AsRef<Path>) -> std::io::Result<&mut Self> {
+        for (url, path) in read_manifest(manifest.as_ref())? {
+            self.add_route("GET", &url, StaticAsset::new(path));
         }
+        Ok(self)
+    }
 
-        if all_routes.is_empty() {
-            return;
+    /// Register an endpoint at `path` that reflects the request back as JSON
+    ///
+    /// Like httpbin's `/anything`, the response contains the method, path,
+    /// headers, decoded query parameters and body (plus `json` when the body
+    /// parses as JSON). Useful for exercising clients and proxies end to end.
+    pub fn enable_echo(&mut self, path: &str) -> &mut Self {
+        for method in ["GET", "POST", "PUT", "PATCH", "DELETE"] {
+            self.add_route(method, path, echo);
         }
+        self
+    }
 
-        let json_path = "/docs/swagger.json";
-        let ui_path = "/docs/";
+    /// Register `GET /livez` and `GET /readyz` for load balancer health checks
+    ///
+    /// Both answer `200 ok` while serving. Once graceful shutdown begins,
+    /// `/readyz` answers `503 draining` so the balancer stops sending new
+    /// traffic, while `/livez` stays `200` as requests already on open
+    /// connections finish. The listeners stay open while draining, so probes
+    /// on new connections see this too.
+    pub fn health_endpoints(&mut self) -> &mut Self {
+        let draining = self.draining.clone();
+        self.get("/livez", |_| async { "ok" });
+        self.get("/readyz", move |_| {
+            let draining = draining.load(Ordering::Relaxed);
+            async move {
+                if draining {
+                    ResponseBuilder::new()
+                        .status(hyper::StatusCode::SERVICE_UNAVAILABLE)
+                        .body("draining")
+                } else {
+                    ResponseBuilder::new().body("ok")
+                }
+            }
+        })
+    }
+
+    /// The OpenAPI 3.0 document for the routes registered so far
+    ///
+    /// Covers the routes on the engine and in its groups, under their full
+    /// paths, with any [`RouteDoc`] metadata. This is what `/docs/swagger.json`
+    /// serves once the engine runs.
+    pub fn openapi_json(&self) -> serde_json::Value {
+        let (routes, docs) = self.documented_routes();
+        crate::swagger::generate_openapi(&routes, &docs)
+    }
+
+    /// Every (method, pattern) of the engine and its groups, with their metadata
+    fn documented_routes(&self) -> DocumentedRoutes {
+        // Collect all routes and their metadata from main router and groups
+        let mut all_routes = Vec::new();
+        let mut docs = HashMap::new();
+
+        // Add routes from main router
+        all_routes.extend(self.router.get_all_routes());
+        docs.extend(self.router.docs().clone());
+
+        // Add routes from all groups, nested ones included
+        for group in self.groups.values().flat_map(RouterGroup::with_subgroups) {
+            all_routes.extend(group.router.get_all_routes());
+            docs.extend(group.router.docs().clone());
+        }
+        (all_routes, docs)
+    }
+
+    /// Automatically add swagger endpoints based on registered routes
+    fn add_swagger_endpoints(&mut self) {
+        let (all_routes, docs) = self.documented_routes();
+        if all_routes.is_empty() {
+            return;
+        }
+
+        let json_path = "/docs/swagger.json";
+        let ui_path = "/docs/";
 
         // Add swagger.json endpoint
         self.get(json_path, move |_ctx: RequestCtx| {
             let routes = all_routes.clone();
+            let docs = docs.clone();
             async move {
                 use crate::response::ResponseBuilder;
                 use crate::swagger::generate_swagger_json;
 
-                let json = generate_swagger_json(&routes);
+                let json = generate_swagger_json(&routes, &docs);
                 ResponseBuilder::new()
                     .status(hyper::StatusCode::OK)
                     .header("Content-Type", "application/json")
@@ -196,130 +1378,2911 @@ impl Engine {
         });
     }
 
-    /// Start the HTTP server
-    pub async fn run(mut self, addr: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let addr = addr.parse::<SocketAddr>()?;
-        println!("🚀 Server running on http://{addr}");
-        let listener = tokio::net::TcpListener::bind(addr).await?;
+    /// Compose every top-level and group route into a single [`Router`]
+    ///
+    /// Group prefixes are already part of the group patterns, so the result
+    /// resolves exactly the paths the engine would. Only routing is kept:
+    /// middleware, host-specific routes and engine settings are dropped, which
+    /// makes this suited to unit-testing route tables.
+    ///
+    /// # Panics
+    ///
+    /// When a group route collides with a top-level route or another group's,
+    /// as [`Router::add_route`] would for the same pair. The engine itself
+    /// serves such routes side by side, preferring the group.
+    pub fn build_router(self) -> Router {
+        let mut router = self.router;
+        for group in self.groups.into_values().flat_map(RouterGroup::flatten) {
+            router.merge(group.router);
+        }
+        router
+    }
+
+    /// Run `hook` to completion before the server binds its socket
+    ///
+    /// Use it for setup that must finish before taking traffic, such as
+    /// migrations or cache warming. Hooks run once, in registration order, from
+    /// [`Engine::bind`] (and so [`Engine::run`]); the first error aborts startup
+    /// and is returned without binding.
+    pub fn on_startup<F, Fut, E>(&mut self, hook: F) -> &mut Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.startup_hooks.push(Box::new(move || {
+            Box::pin(async move { hook().await.map_err(Into::into) })
+        }));
+        self
+    }
+
+    /// Run `hook` once the server has stopped and its connections drained
+    ///
+    /// For flushing logs, closing pools and the like. Hooks run in registration
+    /// order after [`BoundEngine::serve`] (or [`BoundEngine::serve_with_shutdown`])
+    /// stops accepting and the open connections closed or timed out.
+    pub fn on_shutdown<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown_hooks.push(Box::new(move || Box::pin(hook())));
+        self
+    }
+
+    /// Bind the server to `addr` without accepting connections yet
+    ///
+    /// Binding to port `0` lets the OS pick a free port; the real address
+    /// is available from [`BoundEngine::local_addr`] before serving starts.
+    pub async fn bind(self, addr: &str) -> Result<BoundEngine, Box<dyn std::error::Error>> {
+        self.bind_all(&[addr]).await
+    }
+
+    /// Bind a listener to each of `addrs`, all serving the same routes
+    ///
+    /// Useful for dual-stack (`0.0.0.0:80` and `[::]:80`) or multi-interface
+    /// setups. Fails if any address can't be bound.
+    pub async fn bind_all(
+        mut self,
+        addrs: &[&str],
+    ) -> Result<BoundEngine, Box<dyn std::error::Error>> {
+        let addrs = addrs
+            .iter()
+            .map(|addr| addr.parse::<SocketAddr>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if addrs.is_empty() {
+            return Err("no address to bind".into());
+        }
+        for hook in std::mem::take(&mut self.startup_hooks) {
+            hook()
+                .await
+                .map_err(|err| err as Box<dyn std::error::Error>)?;
+        }
+        let mut listeners = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            listeners.push(self.listen(addr)?);
+        }
 
         // Add swagger endpoints automatically
         self.add_swagger_endpoints();
-        println!("📖 Swagger UI available at http://{addr}/docs/");
+        let summary = if self.quiet {
+            None
+        } else {
+            let mut summary = self.startup_summary(listeners[0].local_addr()?);
+            for listener in &listeners[1..] {
+                summary.push_str(&format!(
+                    "   Also listening on http://{}\n",
+                    listener.local_addr()?
+                ));
+            }
+            Some(summary)
+        };
+
+        Ok(BoundEngine {
+            listeners,
+            shutdown_hooks: std::mem::take(&mut self.shutdown_hooks),
+            connections: self.max_connections_per_ip.map(ConnectionLimiter::new),
+            max_requests_per_connection: self.max_requests_per_connection,
+            summary,
+            draining: self.draining.clone(),
+            #[cfg(feature = "tls")]
+            tls: None,
+            app: Arc::new(App::new(self)),
+        })
+    }
+
+    /// Start the HTTP server
+    pub async fn run(self, addr: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.bind(addr).await?.serve().await
+    }
+
+    /// Start an HTTPS server with the PEM certificate chain and private key at the given paths
+    ///
+    /// Requires the `tls` feature. Routing is the same as for [`Engine::run`];
+    /// see [`BoundEngine::with_tls`].
+    #[cfg(feature = "tls")]
+    pub async fn run_tls(
+        self,
+        addr: &str,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.bind(addr)
+            .await?
+            .with_tls(cert_path, key_path)?
+            .serve()
+            .await
+    }
+
+    /// Start the HTTP server on every address in `addrs`, see [`Engine::bind_all`]
+    ///
+    /// Ctrl-C stops all of the listeners.
+    pub async fn run_on(self, addrs: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+        self.bind_all(addrs).await?.serve().await
+    }
+
+    /// Start the HTTP server and stop it gracefully when `signal` completes
+    pub async fn run_with_shutdown(
+        self,
+        addr: &str,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.bind(addr).await?.serve_with_shutdown(signal).await
+    }
+}
+
+/// Reflect a request back as JSON, see [`Engine::enable_echo`]
+async fn echo(ctx: RequestCtx) -> serde_json::Value {
+    let mut headers = serde_json::Map::new();
+    for name in ctx.headers().keys() {
+        let values: Vec<&str> = ctx
+            .headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        headers.insert(name.to_string(), values.join(", ").into());
+    }
+
+    let query: serde_json::Map<String, serde_json::Value> =
+        serde_urlencoded::from_str::<Vec<(String, String)>>(ctx.uri().query().unwrap_or(""))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+
+    let body = ctx
+        .body_bytes()
+        .map(|body| String::from_utf8_lossy(body).into_owned());
+    let json = ctx
+        .body_bytes()
+        .and_then(|body| serde_json::from_slice::<serde_json::Value>(body).ok());
 
+    serde_json::json!({
+        "method": ctx.method().as_str(),
+        "path": ctx.path(),
+        "headers": headers,
+        "query": query,
+        "body": body,
+        "json": json,
+    })
+}
+
+/// Whether `path` is `prefix` itself or lies below it, segment-wise
+///
+/// `/api` covers `/api` and `/api/users` but not `/apiv2`.
+fn under_prefix(path: &str, prefix: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// The engine's top-level routes, swappable while serving, see [`Engine::route_table`]
+#[derive(Clone, Default)]
+pub struct RouteTable(Arc<std::sync::RwLock<Arc<Router>>>);
+
+impl RouteTable {
+    /// Serve `router` from now on
+    ///
+    /// Requests already being handled finish on the routes they started with.
+    pub fn replace_routes(&self, router: Router) {
+        *self.0.write().unwrap() = Arc::new(router);
+    }
+
+    /// The routes currently served
+    pub fn current(&self) -> Arc<Router> {
+        self.0.read().unwrap().clone()
+    }
+}
+
+/// Routing state shared by every connection once the engine is serving
+pub(crate) struct App {
+    router: RouteTable,
+    groups: Vec<(String, Arc<RouterGroup>)>,
+    hosts: Vec<(String, Arc<RouterGroup>)>,
+    middlewares: Vec<Middleware>,
+    matched_middlewares: Vec<Middleware>,
+    maintenance: Option<Maintenance>,
+    duplicate_keys: DuplicateKeys,
+    json_error_formatter: Option<JsonErrorFormatter>,
+    error_mappers: Arc<ErrorMappers>,
+    problem_details: bool,
+    services: Arc<Services>,
+    clock: Option<Arc<dyn Clock>>,
+    case_insensitive_params: bool,
+    api_prefixes: Vec<String>,
+    size_limits: SizeLimits,
+    text_charset: Option<Option<String>>,
+    span_name: Option<SpanName>,
+    panic_handler: Option<PanicHandler>,
+    max_body_size: Option<u64>,
+    trailing_slash: TrailingSlash,
+    request_timeout: Option<Duration>,
+}
+
+impl App {
+    pub(crate) fn new(engine: Engine) -> Self {
         // Pre-process groups for optimal matching
-        let mut group_data: Vec<(String, Arc<RouterGroup>)> = self
+        let mut groups: Vec<(String, Arc<RouterGroup>)> = engine
             .groups
-            .into_iter()
-            .map(|(prefix, group)| (prefix, Arc::new(group)))
+            .into_values()
+            .flat_map(RouterGroup::flatten)
+            .map(|group| (group.prefix.clone(), Arc::new(group)))
             .collect();
 
         // Sort by prefix length (longest first) for better matching
-        group_data.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        groups.sort_by_key(|b| std::cmp::Reverse(b.0.len()));
+
+        // Exact hosts win over wildcards, and more specific wildcards over broader ones
+        let mut hosts: Vec<(String, Arc<RouterGroup>)> = engine
+            .hosts
+            .into_iter()
+            .flat_map(|(host, group)| {
+                group
+                    .flatten()
+                    .into_iter()
+                    .map(move |group| (host.clone(), Arc::new(group)))
+            })
+            .collect();
+        hosts.sort_by_key(|(host, _)| (host.starts_with("*."), std::cmp::Reverse(host.len())));
+
+        engine.route_table.replace_routes(engine.router);
+        Self {
+            router: engine.route_table,
+            groups,
+            hosts,
+            middlewares: engine.middlewares,
+            matched_middlewares: engine.matched_middlewares,
+            maintenance: engine.maintenance.map(|maintenance| Maintenance {
+                allowlist: engine.maintenance_allowlist,
+                ..maintenance
+            }),
+            duplicate_keys: engine.duplicate_keys,
+            json_error_formatter: engine.json_error_formatter,
+            error_mappers: Arc::new(engine.error_mappers),
+            problem_details: engine.problem_details,
+            services: Arc::new(engine.services),
+            clock: engine.clock,
+            case_insensitive_params: engine.case_insensitive_params,
+            api_prefixes: engine.api_prefixes,
+            size_limits: engine.size_limits,
+            text_charset: engine.text_charset,
+            span_name: engine.span_name,
+            panic_handler: engine.panic_handler,
+            max_body_size: engine.max_body_size,
+            trailing_slash: engine.trailing_slash,
+            request_timeout: engine.request_timeout,
+        }
+    }
+
+    /// Handle a request, then apply response-wide settings
+    pub(crate) async fn handle(&self, ctx: RequestCtx) -> Response {
+        let is_head = ctx.is_head();
+        let sent = BytesSent::new();
+        ctx.log_context().insert(sent.clone());
+        let mut response = self.error_mappers.clone().scope(self.dispatch(ctx)).await;
+        if let Some(charset) = &self.text_charset {
+            set_text_charset(&mut response, charset.as_deref());
+        }
+        if is_head {
+            strip_body(&mut response);
+        }
+        count_body(response, sent)
+    }
+
+    /// Dispatch a request through the global middleware, the matching group and its router
+    async fn dispatch(&self, mut ctx: RequestCtx) -> Response {
+        ctx.duplicate_keys = self.duplicate_keys;
+        ctx.json_error_formatter = self.json_error_formatter.clone();
+        ctx.problem_details = self.problem_details;
+        ctx.request.extensions_mut().insert(self.services.clone());
+        if let Some(clock) = &self.clock {
+            ctx.request.extensions_mut().insert(clock.clone());
+        }
+        ctx.case_insensitive_params = self.case_insensitive_params;
+        ctx.api_request = self
+            .api_prefixes
+            .iter()
+            .any(|prefix| under_prefix(ctx.path(), prefix));
+        if let Some(status) = self.size_limits.exceeded(&ctx) {
+            return self.size_limits.response(&ctx, status);
+        }
+        let path = ctx.request.uri().path();
+
+        if let Some(maintenance) = &self.maintenance
+            && maintenance.blocks(path)
+        {
+            return maintenance.response();
+        }
+
+        // Host-specific routes are consulted before the path-based groups
+        let method = ctx.request.method().as_str();
+        let matched_host = request_host(&ctx).and_then(|host| {
+            self.hosts
+                .iter()
+                .find(|(pattern, group)| {
+                    host_matches(pattern, &host) && group.router.get_route(method, path).0.is_some()
+                })
+                .map(|(_, group)| group.clone())
+        });
+
+        // Requests keep the routes they started with if the table is swapped
+        let main_router = self.router.current();
+
+        // The longest group prefix routing the path wins. A top-level route for the
+        // path comes next, so a group never hides it; then whichever has the path
+        // under another method answers its 405, and otherwise the longest
+        // matching group answers, with its middleware and its own 404
+        let mut allow = None;
+        let matched_group = matched_host.or_else(|| {
+            let groups = || {
+                self.groups
+                    .iter()
+                    .filter(|(prefix, _)| under_prefix(path, prefix))
+                    .map(|(_, group)| group)
+            };
+            let routes = |router: &Router| router.get_route(method, path).0.is_some();
+            if let Some(group) = groups().find(|group| routes(&group.router)) {
+                return Some(group.clone());
+            }
+            if routes(&main_router) {
+                return None;
+            }
+
+            // Only worth collecting once no route answers `method`. The 405 lists
+            // what every router has for the path, not just the one answering it
+            let mut allowed = std::collections::BTreeSet::new();
+            let mut answering = None;
+            for group in groups() {
+                let methods = group.router.allowed_methods(path);
+                if answering.is_none() && !methods.is_empty() {
+                    answering = Some(group.clone());
+                }
+                allowed.extend(methods);
+            }
+            let main_methods = main_router.allowed_methods(path);
+            let main_allows = !main_methods.is_empty();
+            allowed.extend(main_methods);
+            if !allowed.is_empty() {
+                allow = Some(allowed.into_iter().collect::<Vec<_>>().join(", "));
+            }
+            match answering {
+                Some(group) => Some(group),
+                None if main_allows => None,
+                None => groups().next().cloned(),
+            }
+        });
+
+        // Buffer the body up front unless the handler streams it itself
+        let router = matched_group
+            .as_ref()
+            .map_or(&*main_router, |group| &group.router);
+        let route = router.find_route(method, path);
+        if self.trailing_slash == TrailingSlash::Redirect
+            && let Some(route) = &route
+        {
+            let spelled = crate::router::spelled_like(path, route.pattern);
+            if spelled != path {
+                let location = match ctx.request.uri().query() {
+                    Some(query) => format!("{spelled}?{query}"),
+                    None => spelled,
+                };
+                return ResponseBuilder::new()
+                    .status(hyper::StatusCode::PERMANENT_REDIRECT)
+                    .header("Location", location)
+                    .empty_body();
+            }
+        }
+        let streams_body = route
+            .as_ref()
+            .is_some_and(|route| route.handler.streams_body());
+        let route_pattern = route.map(|route| route.pattern.to_string());
+        ctx.max_body_size = self.max_body_size;
+        // With `Expect: 100-continue` the router reads the body once the handler
+        // is about to run, so the client doesn't upload for nothing
+        if !streams_body
+            && !ctx.expects_continue()
+            && let Err(response) = ctx.read_pending_body().await
+        {
+            return response;
+        }
+
+        ctx.route_pattern = route_pattern;
+        let span_name = match &self.span_name {
+            Some(span_name) => span_name(&ctx),
+            None => "request".to_string(),
+        };
+        let span = tracing::info_span!(
+            "request",
+            otel.name = %span_name,
+            http.method = %ctx.method(),
+            http.route = ctx.route_pattern().unwrap_or_default(),
+        );
+        ErrorContext::record(&ctx);
+        let log = ctx.log_context().clone();
+        let routed = self.route(matched_group, main_router, ctx);
+        let routed = async {
+            match self.request_timeout {
+                Some(limit) => tokio::time::timeout(limit, routed)
+                    .await
+                    .unwrap_or_else(|_| crate::handler::timed_out()),
+                None => routed.await,
+            }
+        };
+        let mut response = match CatchUnwind::new(routed.instrument(span)).await {
+            Ok(response) => response,
+            Err(payload) => {
+                let err = log.get::<ErrorContext>().unwrap_or_default();
+                match &self.panic_handler {
+                    Some(handler) => handler(&err, &panic_message(payload.as_ref())),
+                    None => ResponseBuilder::internal_error(),
+                }
+            }
+        };
+        if let Some(allow) = allow
+            && response.extensions().get::<crate::Unmatched>()
+                == Some(&crate::Unmatched::MethodNotAllowed)
+            && let Ok(allow) = hyper::header::HeaderValue::from_str(&allow)
+        {
+            response.headers_mut().insert(hyper::header::ALLOW, allow);
+        }
+        response
+    }
 
-        let router = Arc::new(self.router);
-        let global_middlewares = Arc::new(self.middlewares);
-        let groups = Arc::new(group_data);
+    /// The global middleware for a request, including the on-match middleware once routed
+    fn global_middlewares(&self, ctx: &RequestCtx) -> Cow<'_, [Middleware]> {
+        if ctx.route_pattern.is_none() || self.matched_middlewares.is_empty() {
+            return Cow::Borrowed(&self.middlewares);
+        }
+        let mut middlewares = self.middlewares.clone();
+        middlewares.extend(self.matched_middlewares.iter().cloned());
+        Cow::Owned(middlewares)
+    }
+
+    /// Run a request through the middleware and the router it was matched to
+    async fn route(
+        &self,
+        matched_group: Option<Arc<RouterGroup>>,
+        router: Arc<Router>,
+        ctx: RequestCtx,
+    ) -> Response {
+        if let Some(group) = matched_group {
+            self.handle_group(group, ctx).await
+        } else {
+            // Main router handling
+            let middlewares = self.global_middlewares(&ctx);
+            if middlewares.is_empty() {
+                // Fast path: no middleware
+                router.handle_request(ctx).await
+            } else {
+                // Middleware path
+                let endpoint = (move |ctx| {
+                    let router = router.clone();
+                    async move { router.handle_request(ctx).await }
+                })
+                .into_next();
+
+                execute_chain(&middlewares, endpoint, ctx).await
+            }
+        }
+    }
 
-        // Pre-calculate if we have any middleware for optimization
+    /// Run a request through the global and group middleware, then the group's router
+    async fn handle_group(&self, group: Arc<RouterGroup>, ctx: RequestCtx) -> Response {
+        let global_middlewares = self.global_middlewares(&ctx);
         let has_global_middleware = !global_middlewares.is_empty();
-        // hyper graceful shutdown
-        let graceful = GracefulShutdown::new();
+        let has_group_middleware = !group.middlewares.is_empty();
+
+        let mut response = if !has_global_middleware && !has_group_middleware {
+            // Fast path: no middleware at all
+            group.handle_request(ctx).await
+        } else {
+            // Middleware path
+            let mut combined_middlewares =
+                Vec::with_capacity(global_middlewares.len() + group.middlewares.len());
+            combined_middlewares.extend(global_middlewares.iter().cloned());
+            combined_middlewares.extend(group.middlewares.iter().cloned());
+
+            let endpoint = {
+                let group = group.clone();
+                (move |ctx| {
+                    let group = group.clone();
+                    async move { group.handle_request(ctx).await }
+                })
+                .into_next()
+            };
+
+            execute_chain(&combined_middlewares, endpoint, ctx).await
+        };
+
+        group.apply_default_headers(&mut response);
+        response
+    }
+}
+
+/// The request's host name from the `Host` header (or URI authority), lowercased and without a port
+fn request_host(ctx: &RequestCtx) -> Option<String> {
+    let host = ctx
+        .request
+        .headers()
+        .get(hyper::header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| ctx.request.uri().host())?;
+
+    let host = if host.starts_with('[') {
+        // IPv6 literal, e.g. [::1]:8080
+        host.split_inclusive(']').next().unwrap_or(host)
+    } else {
+        host.split(':').next().unwrap_or(host)
+    };
+    Some(host.to_ascii_lowercase())
+}
+
+/// Match a host against `api.example.com` or a wildcard like `*.example.com`
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .and_then(|sub| sub.strip_suffix('.'))
+            .is_some_and(|sub| !sub.is_empty()),
+        None => pattern == host,
+    }
+}
+
+/// Raw reply for connections refused by [`Engine::max_connections_per_ip`]
+const CONNECTION_LIMIT_RESPONSE: &[u8] =
+    b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// Pause after a failed accept, such as when out of file descriptors
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Shortest time between two reports of refused connections
+const REFUSAL_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reports connections refused over the per-IP limit, at most once per interval
+#[derive(Default)]
+struct RefusalLog {
+    last_report: Option<tokio::time::Instant>,
+    suppressed: usize,
+}
+
+impl RefusalLog {
+    /// Note a refused connection from `ip`, logging it unless one was just reported
+    fn refused(&mut self, ip: IpAddr) {
+        let now = tokio::time::Instant::now();
+        if self
+            .last_report
+            .is_some_and(|last| now - last < REFUSAL_LOG_INTERVAL)
+        {
+            self.suppressed += 1;
+            return;
+        }
+        match std::mem::take(&mut self.suppressed) {
+            0 => eprintln!("Too many connections from {ip}"),
+            more => eprintln!(
+                "Too many connections from {ip}, {more} more refused since the last report"
+            ),
+        }
+        self.last_report = Some(now);
+    }
+}
+
+/// Open connection counts per client IP
+struct ConnectionLimiter {
+    max: usize,
+    open: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl ConnectionLimiter {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            open: Arc::default(),
+        }
+    }
+
+    /// Count a new connection from `ip`, or `None` if it's already at the cap
+    fn acquire(&self, ip: IpAddr) -> Option<ConnectionGuard> {
+        let mut open = self.open.lock().unwrap();
+        let count = open.entry(ip).or_insert(0);
+        if *count >= self.max {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionGuard {
+            ip,
+            open: self.open.clone(),
+        })
+    }
+}
+
+/// Releases a connection slot when the connection ends
+struct ConnectionGuard {
+    ip: IpAddr,
+    open: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut open = self.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// An engine bound to a listening socket, ready to serve
+pub struct BoundEngine {
+    listeners: Vec<tokio::net::TcpListener>,
+    app: Arc<App>,
+    connections: Option<ConnectionLimiter>,
+    max_requests_per_connection: Option<usize>,
+    /// Startup banner, `None` in quiet mode
+    summary: Option<String>,
+    shutdown_hooks: Vec<ShutdownHook>,
+    draining: Arc<AtomicBool>,
+    /// Set by [`BoundEngine::with_tls`] to serve HTTPS
+    #[cfg(feature = "tls")]
+    tls: Option<tokio_rustls::TlsAcceptor>,
+}
+
+impl BoundEngine {
+    /// The address the server is actually listening on
+    ///
+    /// With [`Engine::bind_all`] this is the first address.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listeners[0].local_addr()
+    }
+
+    /// Every address the server is listening on, in the order they were bound
+    pub fn local_addrs(&self) -> std::io::Result<Vec<SocketAddr>> {
+        self.listeners
+            .iter()
+            .map(tokio::net::TcpListener::local_addr)
+            .collect()
+    }
+
+    /// Serve HTTPS with the PEM certificate chain and private key at the given paths
+    ///
+    /// Requires the `tls` feature. The first certificate in `cert_path` is the
+    /// server's, followed by any intermediates; `key_path` holds its PKCS#8,
+    /// PKCS#1 or SEC1 key. Each connection does its TLS handshake in its own
+    /// task, and one that fails or takes over 10 seconds is logged and closed
+    /// without affecting others.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(
+        mut self,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let config = crate::tls::server_config(cert_path.as_ref(), key_path.as_ref())?;
+        self.tls = Some(config.into());
+        self.summary = self
+            .summary
+            .map(|summary| summary.replace("http://", "https://"));
+        Ok(self)
+    }
+
+    /// Accept connections until a shutdown signal (Ctrl-C) is received
+    pub async fn serve(self) -> Result<(), Box<dyn std::error::Error>> {
+        self.serve_with_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+    }
+
+    /// Accept connections until `signal` completes, then shut down gracefully
+    ///
+    /// Idle keep-alive connections close at once; requests in flight get up
+    /// to 10 seconds to finish before the [`Engine::on_shutdown`] hooks run.
+    /// Until then new connections are still accepted, one request each, so
+    /// health checks can see the drain instead of being refused.
+    pub async fn serve_with_shutdown(
+        self,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(summary) = &self.summary {
+            print!("{summary}");
+        }
+
+        let BoundEngine {
+            listeners,
+            app,
+            connections,
+            max_requests_per_connection,
+            shutdown_hooks,
+            draining,
+            #[cfg(feature = "tls")]
+            tls,
+            ..
+        } = self;
+        tokio::pin!(signal);
+        // Every connection holds a receiver until it closes, so dropping the
+        // last one tells the sender the drain is over
+        let (shutdown, _) = tokio::sync::watch::channel(false);
+        let mut drain_until = None;
+        let mut refusals = RefusalLog::default();
 
         loop {
             tokio::select! {
-                Ok((stream, remote_addr)) = listener.accept() => {
-                    let io = TokioIo::new(stream);
-                    let router = router.clone();
-                    let global_middlewares = global_middlewares.clone();
-                    let groups = groups.clone();
+                accepted = accept_any(&listeners) => {
+                    let (mut stream, remote_addr) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(err) => {
+                            // Out of file descriptors, say: retrying at once would spin
+                            eprintln!("Failed to accept a connection: {err}");
+                            tokio::time::sleep(ACCEPT_BACKOFF).await;
+                            continue;
+                        }
+                    };
+                    let guard = match &connections {
+                        Some(limiter) => match limiter.acquire(remote_addr.ip()) {
+                            Some(guard) => Some(guard),
+                            None => {
+                                refusals.refused(remote_addr.ip());
+                                tokio::spawn(async move {
+                                    let _ = stream.write_all(CONNECTION_LIMIT_RESPONSE).await;
+                                    let _ = stream.shutdown().await;
+                                });
+                                continue;
+                            }
+                        },
+                        None => None,
+                    };
+                    let app = app.clone();
+                    let shutdown = shutdown.subscribe();
+                    #[cfg(feature = "tls")]
+                    let tls = tls.clone();
 
                     tokio::task::spawn(async move {
-                        let service = service_fn(move |req| {
-                            let router = router.clone();
-                            let global_middlewares = global_middlewares.clone();
-                            let groups = groups.clone();
-
-                            async move {
-                                let path = req.uri().path();
-
-                                // Fast path matching for groups
-                                let matched_group = groups
-                                    .iter()
-                                    .find(|(prefix, _)| path.starts_with(prefix))
-                                    .map(|(_, group)| group.clone());
-
-                                let Ok(ctx) = RequestCtx::new(req).await else {
-                                    eprintln!("Request context error");
-                                    return Ok("Bad Request".into_response());
-                                };
-
-                                let response = if let Some(group) = matched_group {
-                                    // Group request handling
-                                    let has_group_middleware = !group.middlewares.is_empty();
-
-                                    if !has_global_middleware && !has_group_middleware {
-                                        // Fast path: no middleware at all
-                                        group.handle_request(ctx).await
-                                    } else {
-                                        // Middleware path
-                                        let mut combined_middlewares = Vec::with_capacity(
-                                            global_middlewares.len() + group.middlewares.len()
-                                        );
-                                        combined_middlewares.extend(global_middlewares.iter().cloned());
-                                        combined_middlewares.extend(group.middlewares.iter().cloned());
-
-                                        let endpoint = (move |ctx| {
-                                            let group = group.clone();
-                                            async move { group.handle_request(ctx).await }
-                                        }).into_next();
-
-                                        execute_chain(&combined_middlewares, endpoint, ctx).await
-                                    }
-                                } else {
-                                    // Main router handling
-                                    if !has_global_middleware {
-                                        // Fast path: no middleware
-                                        router.handle_request(ctx).await
-                                    } else {
-                                        // Middleware path
-                                        let endpoint = (move |ctx| {
-                                            let router = router.clone();
-                                            async move { router.handle_request(ctx).await }
-                                        }).into_next();
-
-                                        execute_chain(&global_middlewares, endpoint, ctx).await
-                                    }
-                                };
-
-                                Ok::<_, Infallible>(response)
+                        let _guard = guard;
+                        #[cfg(feature = "tls")]
+                        if let Some(acceptor) = tls {
+                            // A failed handshake only ends this connection
+                            match crate::tls::accept(&acceptor, stream).await {
+                                Ok(stream) => {
+                                    serve_connection(
+                                        stream,
+                                        app,
+                                        remote_addr,
+                                        max_requests_per_connection,
+                                        shutdown,
+                                    )
+                                    .await
+                                }
+                                Err(err) => eprintln!("TLS handshake with {remote_addr} failed: {err}"),
                             }
-                        });
-
-                        if let Err(err) = http1::Builder::new()
-                            .serve_connection(io, service)
-                            .await
-                        {
-                            eprintln!("Connection error {remote_addr}: {err:?}");
+                            return;
                         }
+                        serve_connection(
+                            stream,
+                            app,
+                            remote_addr,
+                            max_requests_per_connection,
+                            shutdown,
+                        )
+                        .await;
                     });
                 }
 
-                _ = tokio::signal::ctrl_c() => {
-                    drop(listener);
+                _ = &mut signal, if drain_until.is_none() => {
+                    draining.store(true, Ordering::Relaxed);
                     eprintln!("\n🛑 Graceful shutdown signal received");
+                    shutdown.send_replace(true);
+                    drain_until = Some(tokio::time::Instant::now() + Duration::from_secs(10));
+                }
+
+                _ = shutdown.closed(), if drain_until.is_some() => {
+                    eprintln!("✅ All connections gracefully closed");
+                    break;
+                }
+
+                _ = tokio::time::sleep_until(drain_until.unwrap_or_else(tokio::time::Instant::now)),
+                    if drain_until.is_some() => {
+                    eprintln!("⏰ Timed out waiting for all connections to close");
                     break;
                 }
             }
         }
+        drop(listeners);
+
+        for hook in shutdown_hooks {
+            hook().await;
+        }
+        Ok(())
+    }
+}
+
+/// Serve HTTP/1 requests on one accepted connection until it closes
+///
+/// Once `shutdown` turns `true`, the request in flight is finished and the
+/// connection closed instead of kept alive; idle connections close at once.
+/// Connections accepted after that serve a single request.
+async fn serve_connection<S>(
+    stream: S,
+    app: Arc<App>,
+    remote_addr: SocketAddr,
+    max_requests_per_connection: Option<usize>,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let served = Arc::new(AtomicUsize::new(0));
+    let service = service_fn(move |req| {
+        let app = app.clone();
+        let served = served.clone();
+
+        async move {
+            let ctx = RequestCtx::from_connection(req.map(BodyExt::boxed), remote_addr);
+            let mut response = app.handle(ctx).await;
+            let count = served.fetch_add(1, Ordering::Relaxed) + 1;
+            if max_requests_per_connection.is_some_and(|max| count >= max) {
+                // hyper closes the connection after this response
+                response.headers_mut().insert(
+                    hyper::header::CONNECTION,
+                    hyper::header::HeaderValue::from_static("close"),
+                );
+            }
+            Ok::<_, Infallible>(response)
+        }
+    });
+
+    let draining = *shutdown.borrow();
+    let connection = http1::Builder::new()
+        .keep_alive(!draining)
+        .serve_connection(TokioIo::new(stream), service)
+        .with_upgrades();
+    tokio::pin!(connection);
+    let result = if draining {
+        // A graceful shutdown would close it before the request is read
+        connection.await
+    } else {
         tokio::select! {
-            _ = graceful.shutdown() => {
-                eprintln!("✅ All connections gracefully closed");
-            },
-            _ = tokio::time::sleep(std::time::Duration::from_secs(10)) => {
-                eprintln!("⏰ Timed out waiting for all connections to close");
+            result = connection.as_mut() => result,
+            _ = shutdown.changed() => {
+                connection.as_mut().graceful_shutdown();
+                connection.await
             }
         }
+    };
+    if let Err(err) = result {
+        if err.is_parse() {
+            // hyper has already answered with 400/414/431 where it could
+            eprintln!("Malformed request from {remote_addr}: {err}");
+        } else {
+            eprintln!("Connection error {remote_addr}: {err:?}");
+        }
+    }
+}
 
-        Ok(())
+/// Accept the next connection on whichever listener gets one first
+async fn accept_any(
+    listeners: &[tokio::net::TcpListener],
+) -> std::io::Result<(tokio::net::TcpStream, SocketAddr)> {
+    std::future::poll_fn(|cx| {
+        for listener in listeners {
+            if let std::task::Poll::Ready(accepted) = listener.poll_accept(cx) {
+                return std::task::Poll::Ready(accepted);
+            }
+        }
+        std::task::Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::Bytes;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn request(method: &str, uri: &str) -> RequestCtx {
+        let (parts, _) = hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap()
+            .into_parts();
+        RequestCtx::from_parts(parts, Bytes::new())
+    }
+
+    #[tokio::test]
+    async fn test_malformed_request_gets_400() {
+        let mut app = Engine::new();
+        app.get("/ping", |_| async { "pong" });
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = bound.serve().await;
+        });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"NOT A VALID REQUEST\r\n\r\n")
+            .await
+            .unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).await.unwrap();
+        assert!(
+            raw.starts_with("HTTP/1.1 400"),
+            "unexpected response: {raw}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_default_headers() {
+        let mut app = Engine::new();
+        app.get("/home", |_| async { "home" });
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Cache-Control", "no-store".parse().unwrap());
+        headers.insert("X-Api", "v1".parse().unwrap());
+        app.group("/api")
+            .default_headers(headers)
+            .get("/users", |_| async { "users" })
+            .get("/custom", |_| async {
+                ResponseBuilder::new()
+                    .header("X-Api", "custom")
+                    .body("custom")
+            });
+
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/api/users")).await;
+        assert_eq!(response.headers()["Cache-Control"], "no-store");
+
+        let response = app.handle(request("GET", "/api/custom")).await;
+        assert_eq!(response.headers()["X-Api"], "custom");
+        assert_eq!(response.headers().get_all("X-Api").iter().count(), 1);
+
+        let response = app.handle(request("GET", "/home")).await;
+        assert!(!response.headers().contains_key("Cache-Control"));
+    }
+
+    #[tokio::test]
+    async fn test_bind_ephemeral_port() {
+        let mut app = Engine::new();
+        app.get("/ping", |_| async { "pong" });
+
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+
+        tokio::spawn(async move {
+            let _ = bound.serve().await;
+        });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).await.unwrap();
+        assert!(raw.starts_with("HTTP/1.1 200 OK"));
+        assert!(raw.ends_with("pong"));
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode() {
+        let enabled = Arc::new(AtomicBool::new(false));
+        let mut app = Engine::new();
+        app.maintenance_mode(enabled.clone(), Duration::from_secs(120))
+            .maintenance_allow("/healthz")
+            .get("/users", |_| async { "users" })
+            .get("/healthz", |_| async { "ok" });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/users")).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+
+        enabled.store(true, Ordering::Relaxed);
+        let response = app.handle(request("GET", "/users")).await;
+        assert_eq!(response.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["Retry-After"], "120");
+
+        let response = app.handle(request("GET", "/healthz")).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_allow_before_mode() {
+        let mut app = Engine::new();
+        app.maintenance_allow("/healthz")
+            .maintenance_mode(Arc::new(AtomicBool::new(true)), Duration::from_secs(60))
+            .get("/users", |_| async { "users" })
+            .get("/healthz", |_| async { "ok" });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/users")).await;
+        assert_eq!(response.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+        let response = app.handle(request("GET", "/healthz")).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_log_context_after_handler() {
+        #[derive(Clone)]
+        struct UserId(u64);
+
+        let logged = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let lines = logged.clone();
+
+        let mut app = Engine::new();
+        app.use_middleware(move |ctx, next| {
+            let lines = lines.clone();
+            async move {
+                let path = ctx.request.uri().path().to_string();
+                let response = next(ctx).await;
+                let user = response
+                    .extensions()
+                    .get::<crate::LogContext>()
+                    .and_then(|log| log.get::<UserId>());
+                if let Some(UserId(id)) = user {
+                    lines.lock().unwrap().push(format!("{path} user_id={id}"));
+                }
+                response
+            }
+        })
+        .get("/profile", |ctx: RequestCtx| async move {
+            ctx.log_context().insert(UserId(42));
+            "profile"
+        });
+        let app = App::new(app);
+
+        app.handle(request("GET", "/profile")).await;
+        assert_eq!(*logged.lock().unwrap(), vec!["/profile user_id=42"]);
+    }
+
+    fn request_for_host(uri: &str, host: &str) -> RequestCtx {
+        let (parts, _) = hyper::Request::builder()
+            .uri(uri)
+            .header("Host", host)
+            .body(())
+            .unwrap()
+            .into_parts();
+        RequestCtx::from_parts(parts, Bytes::new())
+    }
+
+    async fn body_text(response: Response) -> String {
+        use http_body_util::BodyExt;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_virtual_hosts() {
+        let mut app = Engine::new();
+        app.get("/", |_| async { "default" });
+        app.host("api.example.com").get("/", |_| async { "api" });
+        app.host("*.example.com").get("/", |_| async { "tenant" });
+        let app = App::new(app);
+
+        let response = app
+            .handle(request_for_host("/", "api.example.com:8080"))
+            .await;
+        assert_eq!(body_text(response).await, "api");
+
+        let response = app.handle(request_for_host("/", "acme.example.com")).await;
+        assert_eq!(body_text(response).await, "tenant");
+
+        let response = app.handle(request_for_host("/", "example.com")).await;
+        assert_eq!(body_text(response).await, "default");
+
+        let response = app.handle(request_for_host("/", "other.org")).await;
+        assert_eq!(body_text(response).await, "default");
+    }
+
+    #[tokio::test]
+    async fn test_documented_routes_in_swagger_json() {
+        let mut app = Engine::new();
+        app.get_documented(
+            "/users/:id",
+            RouteDoc::new().summary("Fetch a user").tag("users"),
+            |_| async { "user" },
+        );
+        app.group("/admin").get_documented(
+            "/stats",
+            RouteDoc::new().summary("Statistics").tag("admin"),
+            |_| async { "stats" },
+        );
+        app.add_swagger_endpoints();
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/docs/swagger.json")).await;
+        let spec: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(
+            spec["paths"]["/users/{id}"]["get"]["summary"],
+            "Fetch a user"
+        );
+        assert_eq!(spec["paths"]["/users/{id}"]["get"]["tags"][0], "users");
+        assert_eq!(spec["paths"]["/admin/stats"]["get"]["tags"][0], "admin");
+    }
+
+    #[test]
+    fn test_openapi_json_paths_and_parameters() {
+        let mut app = Engine::new();
+        app.get("/users/:id", |_| async { "user" })
+            .delete("/users/:id", |_| async { "deleted" })
+            .get("/health", |_| async { "ok" });
+        app.group("/repos")
+            .get("/:owner/:name/issues/:number", |_| async { "issue" });
+
+        let spec = app.openapi_json();
+        assert_eq!(spec["openapi"], "3.0.0");
+        let mut paths: Vec<&str> = spec["paths"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        paths.sort_unstable();
+        assert_eq!(
+            paths,
+            [
+                "/health",
+                "/repos/{owner}/{name}/issues/{number}",
+                "/users/{id}"
+            ]
+        );
+
+        let user = &spec["paths"]["/users/{id}"];
+        assert!(user["get"].is_object() && user["delete"].is_object());
+        let id = &user["get"]["parameters"][0];
+        assert_eq!(id["name"], "id");
+        assert_eq!(id["in"], "path");
+        assert_eq!(id["required"], true);
+
+        let issue = &spec["paths"]["/repos/{owner}/{name}/issues/{number}"]["get"];
+        let names: Vec<&serde_json::Value> = issue["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|parameter| parameter["in"] == "path")
+            .map(|parameter| &parameter["name"])
+            .collect();
+        assert_eq!(names, ["owner", "name", "number"]);
+    }
+
+    #[tokio::test]
+    async fn test_echo_endpoint() {
+        let mut app = Engine::new();
+        app.enable_echo("/anything");
+        let app = App::new(app);
+
+        let (parts, _) = hyper::Request::builder()
+            .method("POST")
+            .uri("/anything?page=2&q=a%20b")
+            .header("X-Trace", "abc")
+            .header("Content-Type", "application/json")
+            .body(())
+            .unwrap()
+            .into_parts();
+        let ctx = RequestCtx::from_parts(parts, Bytes::from_static(br#"{"name":"ree"}"#));
+
+        let response = app.handle(ctx).await;
+        let echo: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(echo["method"], "POST");
+        assert_eq!(echo["path"], "/anything");
+        assert_eq!(echo["headers"]["x-trace"], "abc");
+        assert_eq!(echo["query"]["q"], "a b");
+        assert_eq!(echo["body"], r#"{"name":"ree"}"#);
+        assert_eq!(echo["json"]["name"], "ree");
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_param_policy() {
+        #[derive(serde::Deserialize)]
+        struct Query {
+            x: Vec<u32>,
+        }
+
+        let mut app = Engine::new();
+        app.duplicate_param_policy(DuplicateKeys::Collect)
+            .get("/items", |ctx: RequestCtx| async move {
+                format!("{:?}", ctx.query_as::<Query>().unwrap().x)
+            });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/items?x=1&x=2")).await;
+        assert_eq!(body_text(response).await, "[1, 2]");
+    }
+
+    #[tokio::test]
+    async fn test_streaming_handler_gets_unread_body() {
+        let pending = |body: &'static str| {
+            let body = http_body_util::Full::new(Bytes::from_static(body.as_bytes()))
+                .map_err(|never| match never {})
+                .boxed();
+            RequestCtx::streaming(hyper::Request::post("/upload").body(body).unwrap())
+        };
+
+        let mut app = Engine::new();
+        app.post(
+            "/upload",
+            crate::streaming(|mut ctx: RequestCtx| async move {
+                assert!(ctx.body.is_none());
+                let path = std::env::temp_dir().join(format!("ree-stream-{}", std::process::id()));
+                let written = ctx.save_body_to_file(&path, 64).await.unwrap();
+                std::fs::remove_file(&path).unwrap();
+                written.to_string()
+            }),
+        );
+        app.post("/buffered", |ctx: RequestCtx| async move {
+            ctx.body_string().unwrap().unwrap_or_default()
+        });
+        let app = App::new(app);
+
+        let response = app.handle(pending("streamed body")).await;
+        assert_eq!(body_text(response).await, "13");
+
+        let mut ctx = pending("buffered body");
+        ctx.request = hyper::Request::post("/buffered").body(()).unwrap();
+        assert_eq!(body_text(app.handle(ctx).await).await, "buffered body");
+    }
+
+    #[test]
+    fn test_middleware_names_follow_registration_order() {
+        async fn pass(ctx: RequestCtx, next: Next) -> Response {
+            next(ctx).await
+        }
+
+        let mut app = Engine::new();
+        app.use_middleware_named("logger", pass)
+            .use_middleware_on_match(pass)
+            .use_middleware_on_match_named("audit", pass)
+            .use_middleware(pass)
+            .use_middleware_named("auth", pass);
+        let api = app.group("/api");
+        api.use_middleware_named("rate_limit", pass);
+        assert_eq!(api.middleware_names(), ["rate_limit"]);
+
+        // Route-only middleware runs after the rest, so it's listed last
+        assert_eq!(
+            app.middleware_names(),
+            ["logger", "<anonymous>", "auth", "<anonymous>", "audit"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_static_files_blocks_encoded_traversal() {
+        let base = std::env::temp_dir().join(format!("ree-static-engine-{}", std::process::id()));
+        std::fs::create_dir_all(base.join("public")).unwrap();
+        std::fs::write(base.join("public/app.js"), "console.log(1)").unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+
+        let mut app = Engine::new();
+        app.static_files("/static", base.join("public"));
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/static/app.js")).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(body_text(response).await, "console.log(1)");
+
+        let response = app.handle(request("GET", "/static/..%2fsecret.txt")).await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_static_files_missing_and_dot_segments() {
+        let base = std::env::temp_dir().join(format!("ree-static-dots-{}", std::process::id()));
+        std::fs::create_dir_all(base.join("public/css")).unwrap();
+        std::fs::write(base.join("public/css/site.css"), "body{}").unwrap();
+        std::fs::write(base.join("passwd"), "root:x:0:0").unwrap();
+
+        let mut app = Engine::new();
+        app.static_dir("/static/", base.join("public"));
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/static/css/site.css")).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/css; charset=utf-8"
+        );
+        assert_eq!(body_text(response).await, "body{}");
+
+        let response = app.handle(request("GET", "/static/css/missing.css")).await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+
+        // Existing and missing files outside the root can't be told apart
+        for path in [
+            "/static/../passwd",
+            "/static/css/../../passwd",
+            "/static/../etc/passwd",
+            "/static/../nonexistent",
+        ] {
+            let response = app.handle(request("GET", path)).await;
+            assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND, "{path}");
+        }
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_serve_spa() {
+        let base = std::env::temp_dir().join(format!("ree-spa-{}", std::process::id()));
+        std::fs::create_dir_all(base.join("dist/assets")).unwrap();
+        std::fs::write(base.join("dist/index.html"), "<div id=app>").unwrap();
+        std::fs::write(base.join("dist/assets/app.js"), "mount()").unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+
+        let mut app = Engine::new();
+        app.serve_spa(base.join("dist"), "index.html");
+        app.group("/api").get("/users", |_| async { "[]" });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/assets/app.js")).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(body_text(response).await, "mount()");
+
+        let navigate = |uri: &str| {
+            let mut ctx = request("GET", uri);
+            ctx.request.headers_mut().insert(
+                hyper::header::ACCEPT,
+                "text/html,application/xhtml+xml,*/*;q=0.8".parse().unwrap(),
+            );
+            ctx
+        };
+        let response = app.handle(navigate("/some/deep/route")).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(body_text(response).await, "<div id=app>");
+
+        // Routes win even for navigation, and API calls keep their 404
+        let response = app.handle(navigate("/api/users")).await;
+        assert_eq!(body_text(response).await, "[]");
+        let response = app.handle(request("GET", "/api/x")).await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        let response = app
+            .handle(request("GET", "/assets/..%2f..%2fsecret.txt"))
+            .await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_max_connections_per_ip() {
+        let mut app = Engine::new();
+        app.max_connections_per_ip(2)
+            .get("/ping", |_| async { "pong" });
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = bound.serve().await;
+        });
+
+        // Two idle connections use up the allowance for 127.0.0.1
+        let first = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let _second = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+        let mut third = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut raw = String::new();
+        third.read_to_string(&mut raw).await.unwrap();
+        assert!(
+            raw.starts_with("HTTP/1.1 503"),
+            "unexpected response: {raw}"
+        );
+
+        // Closing a connection frees its slot once the server notices
+        drop(first);
+        for _ in 0..50 {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut raw = String::new();
+            let _ = stream.read_to_string(&mut raw).await;
+            if raw.starts_with("HTTP/1.1 200") {
+                assert!(raw.ends_with("pong"));
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("connection slot was never released");
+    }
+
+    #[test]
+    fn test_refused_connections_are_reported_once_per_interval() {
+        let ip: IpAddr = [127, 0, 0, 1].into();
+        let mut refusals = RefusalLog::default();
+        refusals.refused(ip);
+        refusals.refused(ip);
+        refusals.refused(ip);
+        assert_eq!(refusals.suppressed, 2);
+
+        // The next report, once the interval is over, covers the suppressed ones
+        refusals.last_report = Some(tokio::time::Instant::now() - REFUSAL_LOG_INTERVAL);
+        refusals.refused(ip);
+        assert_eq!(refusals.suppressed, 0);
+    }
+
+    #[test]
+    fn test_startup_summary_lists_routes() {
+        let mut app = Engine::new();
+        app.get("/users/:id", |_| async { "user" })
+            .post("/users", |_| async { "created" });
+        app.group("/api").get("/health", |_| async { "ok" });
+        app.host("admin.example.com")
+            .get("/", |_| async { "admin" });
+
+        let summary = app.startup_summary("127.0.0.1:8080".parse().unwrap());
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "🚀 Server running on http://127.0.0.1:8080",
+                "📋 Routes (4):",
+                "   GET     /api/health",
+                "   POST    /users",
+                "   GET     /users/:id",
+                "   GET     admin.example.com/",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_params() {
+        let handler =
+            |ctx: RequestCtx| async move { ctx.param_str("ID").unwrap_or("none").to_string() };
+
+        let mut exact = Engine::new();
+        exact.get("/users/:id", handler);
+        let response = App::new(exact).handle(request("GET", "/users/7")).await;
+        assert_eq!(body_text(response).await, "none");
+
+        let mut relaxed = Engine::new();
+        relaxed
+            .case_insensitive_params(true)
+            .get("/users/:id", handler);
+        let response = App::new(relaxed).handle(request("GET", "/users/7")).await;
+        assert_eq!(body_text(response).await, "7");
+    }
+
+    #[tokio::test]
+    async fn test_build_router_merges_groups() {
+        let mut app = Engine::new();
+        app.get("/health", |_| async { "ok" });
+        app.group("/api/v1").get("/users/:id", |_| async { "user" });
+
+        let router = app.build_router();
+        let (node, params) = router.get_route("GET", "/api/v1/users/7");
+        assert_eq!(node.unwrap().pattern, "/api/v1/users/:id");
+        assert_eq!(params["id"], "7");
+        assert!(router.get_route("GET", "/health").0.is_some());
+        assert!(router.get_route("GET", "/users/7").0.is_none());
+
+        let response = router
+            .handle_request(request("GET", "/api/v1/users/7"))
+            .await;
+        assert_eq!(body_text(response).await, "user");
+    }
+
+    #[tokio::test]
+    async fn test_map_error_is_scoped_to_the_engine() {
+        #[derive(Debug)]
+        enum ShopError {
+            Missing,
+            Sold,
+        }
+
+        impl std::fmt::Display for ShopError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{self:?}")
+            }
+        }
+
+        impl std::error::Error for ShopError {}
+
+        impl ErrorMapper for ShopError {
+            fn status(&self) -> hyper::StatusCode {
+                match self {
+                    ShopError::Missing => hyper::StatusCode::NOT_FOUND,
+                    ShopError::Sold => hyper::StatusCode::CONFLICT,
+                }
+            }
+        }
+
+        let routes = |app: &mut Engine| {
+            app.get("/missing", |_| async { Err::<&str, _>(ShopError::Missing) });
+            app.get("/sold", |_| async {
+                let sold: Result<&str, Box<dyn std::error::Error + Send + Sync>> =
+                    Err(ShopError::Sold.into());
+                sold
+            });
+        };
+        let mut mapped = Engine::new();
+        mapped.map_error::<ShopError>();
+        routes(&mut mapped);
+        let mapped = App::new(mapped);
+        let mut unmapped = Engine::new();
+        routes(&mut unmapped);
+        let unmapped = App::new(unmapped);
+
+        let response = mapped.handle(request("GET", "/missing")).await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        let response = mapped.handle(request("GET", "/sold")).await;
+        assert_eq!(response.status(), hyper::StatusCode::CONFLICT);
+
+        let response = unmapped.handle(request("GET", "/missing")).await;
+        assert_eq!(response.status(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+        let response = unmapped.handle(request("GET", "/sold")).await;
+        assert_eq!(response.status(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    #[should_panic(expected = "route GET /api/users is registered twice")]
+    fn test_build_router_rejects_colliding_group_route() {
+        let mut app = Engine::new();
+        app.get("/api/users", |_| async { "top-level" });
+        app.group("/api").get("/users", |_| async { "group" });
+        app.build_router();
+    }
+
+    #[tokio::test]
+    async fn test_max_requests_per_connection() {
+        let mut app = Engine::new();
+        app.quiet()
+            .max_requests_per_connection(2)
+            .get("/ping", |_| async { "pong" });
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = bound.serve().await;
+        });
+
+        // Three pipelined requests on one connection: only two get answered
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = "GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        stream
+            .write_all(request.repeat(3).as_bytes())
+            .await
+            .unwrap();
+
+        let mut raw = String::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut raw))
+            .await
+            .expect("connection was kept open")
+            .unwrap();
+        assert_eq!(raw.matches("HTTP/1.1 200").count(), 2, "{raw}");
+        assert_eq!(raw.matches("connection: close").count(), 1, "{raw}");
+    }
+
+    #[tokio::test]
+    async fn test_api_prefix_gets_json_errors() {
+        let mut app = Engine::new();
+        app.api_prefix("/api");
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/api/missing")).await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/json; charset=utf-8"
+        );
+
+        let response = app.handle(request("GET", "/missing")).await;
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=utf-8"
+        );
+
+        // Only whole segments count as under the prefix
+        let response = app.handle(request("GET", "/apiv2/missing")).await;
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=utf-8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_request_rejections() {
+        let mut app = Engine::new();
+        app.max_uri_length(32).max_header_size(64);
+        app.get("/search", |_ctx: RequestCtx| async { "results" });
+        let app = App::new(app);
+
+        let long_uri = format!("/search?q={}", "a".repeat(40));
+        let mut req = request("GET", &long_uri);
+        req.request
+            .headers_mut()
+            .insert("accept", "application/json".parse().unwrap());
+        let response = app.handle(req).await;
+        assert_eq!(response.status(), hyper::StatusCode::URI_TOO_LONG);
+        assert_eq!(
+            body_text(response).await,
+            r#"{"error":"URI Too Long","status":414}"#
+        );
+
+        let mut req = request("GET", "/search");
+        req.request
+            .headers_mut()
+            .insert("x-padding", "p".repeat(64).parse().unwrap());
+        let response = app.handle(req).await;
+        assert_eq!(
+            response.status(),
+            hyper::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+
+        let response = app.handle(request("GET", "/search?q=rust")).await;
+        assert_eq!(body_text(response).await, "results");
+
+        // The limit counts the path and query, exactly up to `max`
+        let at_limit = format!("/search?q={}", "a".repeat(22));
+        let response = app.handle(request("GET", &at_limit)).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        let response = app.handle(request("GET", &format!("{at_limit}a"))).await;
+        assert_eq!(response.status(), hyper::StatusCode::URI_TOO_LONG);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_override() {
+        let mut app = Engine::new();
+        app.max_uri_length(8).oversized_response(|_ctx, status| {
+            ResponseBuilder::new()
+                .status(status)
+                .content_type("text/plain; charset=utf-8")
+                .body("shorten it")
+        });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/a/long/path")).await;
+        assert_eq!(response.status(), hyper::StatusCode::URI_TOO_LONG);
+        assert_eq!(body_text(response).await, "shorten it");
+    }
+
+    #[tokio::test]
+    async fn test_text_charset() {
+        let mut app = Engine::new();
+        app.get("/text", |_ctx: RequestCtx| async { "hello" });
+        app.get("/page", |_ctx: RequestCtx| async {
+            ResponseBuilder::html("<p>hi</p>")
+        });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/text")).await;
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/plain; charset=utf-8"
+        );
+
+        let mut omitted = Engine::new();
+        omitted.text_charset(None);
+        omitted.get("/text", |_ctx: RequestCtx| async { "hello" });
+        let response = App::new(omitted).handle(request("GET", "/text")).await;
+        assert_eq!(response.headers()["content-type"], "text/plain");
+
+        let mut latin1 = Engine::new();
+        latin1.text_charset(Some("iso-8859-1"));
+        latin1.get("/page", |_ctx: RequestCtx| async {
+            ResponseBuilder::html("<p>hi</p>")
+        });
+        latin1.get("/data", |_ctx: RequestCtx| async {
+            ResponseBuilder::new()
+                .content_type("application/json; charset=utf-8")
+                .body("{}")
+        });
+        let latin1 = App::new(latin1);
+        let response = latin1.handle(request("GET", "/page")).await;
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=iso-8859-1"
+        );
+        let response = latin1.handle(request("GET", "/data")).await;
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/json; charset=utf-8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tracing_span_name_sees_pattern() {
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let mut app = Engine::new();
+        app.tracing_span_name({
+            let names = names.clone();
+            move |ctx| {
+                let name = ctx.route_pattern().unwrap_or("unmatched").to_string();
+                names.lock().unwrap().push(name.clone());
+                name
+            }
+        });
+        app.get("/users/:id", |_ctx: RequestCtx| async { "user" });
+        app.group("/api")
+            .get("/orders/:id", |_ctx: RequestCtx| async { "order" });
+        let app = App::new(app);
+
+        app.handle(request("GET", "/users/7")).await;
+        app.handle(request("GET", "/api/orders/9")).await;
+        app.handle(request("GET", "/missing")).await;
+        assert_eq!(
+            *names.lock().unwrap(),
+            ["/users/:id", "/api/orders/:id", "unmatched"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_strip_prefix() {
+        let path = |ctx: RequestCtx| async move {
+            format!("{} {}", ctx.path(), ctx.uri().query().unwrap_or(""))
+        };
+        let mut app = Engine::new();
+        app.group("/full").get("/users/:id", path);
+        app.group("/mounted")
+            .strip_prefix(true)
+            .get("/users/:id", path)
+            .get("", path);
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/full/users/1?x=1")).await;
+        assert_eq!(body_text(response).await, "/full/users/1 x=1");
+        let response = app.handle(request("GET", "/mounted/users/1?x=1")).await;
+        assert_eq!(body_text(response).await, "/users/1 x=1");
+        let response = app.handle(request("GET", "/mounted")).await;
+        assert_eq!(body_text(response).await, "/ ");
+    }
+
+    #[test]
+    fn test_method_helpers() {
+        type Register = fn(&mut Engine);
+        let cases: [(&str, Register); 5] = [
+            ("POST", |app| {
+                app.post("/items/:id", |_| async { "" });
+                app.group("/api").post("/items/:id", |_| async { "" });
+            }),
+            ("PUT", |app| {
+                app.put("/items/:id", |_| async { "" });
+                app.group("/api").put("/items/:id", |_| async { "" });
+            }),
+            ("DELETE", |app| {
+                app.delete("/items/:id", |_| async { "" });
+                app.group("/api").delete("/items/:id", |_| async { "" });
+            }),
+            ("PATCH", |app| {
+                app.patch("/items/:id", |_| async { "" });
+                app.group("/api").patch("/items/:id", |_| async { "" });
+            }),
+            ("OPTIONS", |app| {
+                app.options("/items/:id", |_| async { "" });
+                app.group("/api").options("/items/:id", |_| async { "" });
+            }),
+        ];
+        for (method, register) in cases {
+            let mut app = Engine::new();
+            register(&mut app);
+            // `/items/1` and `/api/items/1` are routed for `method` only
+            let group = &app.groups["/api"];
+            for other in ["GET", "POST", "PUT", "DELETE", "PATCH", "OPTIONS"] {
+                let expected = other == method;
+                assert_eq!(
+                    app.router.get_route(other, "/items/1").0.is_some(),
+                    expected,
+                    "{other} on the engine after {method}"
+                );
+                assert_eq!(
+                    group.router.get_route(other, "/api/items/1").0.is_some(),
+                    expected,
+                    "{other} on the group after {method}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_global_then_group_middleware_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let record = |name: &'static str| {
+            let calls = calls.clone();
+            move |ctx: RequestCtx, next: Next| {
+                let calls = calls.clone();
+                async move {
+                    calls.lock().unwrap().push(format!("{name} in"));
+                    let response = next(ctx).await;
+                    calls.lock().unwrap().push(format!("{name} out"));
+                    response
+                }
+            }
+        };
+
+        let mut app = Engine::new();
+        app.use_middleware(record("first"))
+            .use_middleware(|ctx, next| async move {
+                let mut response = next(ctx).await;
+                response
+                    .headers_mut()
+                    .insert("access-control-allow-origin", "*".parse().unwrap());
+                response
+            })
+            .use_middleware(record("second"));
+        let api = app.group("/api");
+        api.use_middleware(record("group"))
+            .use_middleware(|ctx: RequestCtx, next: Next| async move {
+                if ctx.header("authorization").is_none() {
+                    return ResponseBuilder::new()
+                        .status(hyper::StatusCode::UNAUTHORIZED)
+                        .empty_body();
+                }
+                next(ctx).await
+            })
+            .get("/users", |_| async { "users" });
+        let app = App::new(app);
+
+        let mut ctx = request("GET", "/api/users");
+        ctx.request
+            .headers_mut()
+            .insert("authorization", "Bearer t".parse().unwrap());
+        let response = app.handle(ctx).await;
+        assert_eq!(response.headers()["access-control-allow-origin"], "*");
+        assert_eq!(body_text(response).await, "users");
+        assert_eq!(
+            std::mem::take(&mut *calls.lock().unwrap()),
+            [
+                "first in",
+                "second in",
+                "group in",
+                "group out",
+                "second out",
+                "first out"
+            ]
+        );
+
+        // The auth middleware answers without calling the handler, and the
+        // middleware wrapping it still sees and decorates its response
+        let response = app.handle(request("GET", "/api/users")).await;
+        assert_eq!(response.status(), hyper::StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["access-control-allow-origin"], "*");
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "first in",
+                "second in",
+                "group in",
+                "group out",
+                "second out",
+                "first out"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_middleware_rejection_short_circuits() {
+        use crate::Rejection;
+
+        fn authenticate(ctx: &RequestCtx) -> Result<String, Rejection> {
+            match ctx.header("authorization") {
+                Some("Bearer secret") => Ok("alice".to_string()),
+                Some(_) => Err(Rejection::forbidden("invalid token")),
+                None => Err(Rejection::unauthorized("missing token")),
+            }
+        }
+
+        let mut app = Engine::new();
+        app.use_middleware(|ctx, next| async move {
+            let user = authenticate(&ctx)?;
+            ctx.log_context().insert(user);
+            Ok::<_, Rejection>(next(ctx).await)
+        });
+        app.get("/me", |_ctx: RequestCtx| async { "me" });
+        let app = App::new(app);
+
+        let with_auth = |auth: &str| {
+            let mut ctx = request("GET", "/me");
+            ctx.request
+                .headers_mut()
+                .insert("authorization", auth.parse().unwrap());
+            ctx
+        };
+
+        let response = app.handle(with_auth("Bearer secret")).await;
+        assert_eq!(body_text(response).await, "me");
+
+        let response = app.handle(request("GET", "/me")).await;
+        assert_eq!(response.status(), hyper::StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body_text(response).await,
+            r#"{"error":"missing token","status":401}"#
+        );
+
+        let response = app.handle(with_auth("Bearer guess")).await;
+        assert_eq!(response.status(), hyper::StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_route_and_group_counts() {
+        let mut app = Engine::new();
+        assert_eq!((app.route_count(), app.group_count()), (0, 0));
+
+        app.get("/", |_ctx: RequestCtx| async { "home" });
+        app.get("/users", |_ctx: RequestCtx| async { "list" });
+        app.post("/users", |_ctx: RequestCtx| async { "create" });
+        app.group("/api")
+            .get("/status", |_ctx: RequestCtx| async { "ok" })
+            .delete("/cache", |_ctx: RequestCtx| async { "cleared" });
+        app.group("/admin");
+
+        assert_eq!(app.route_count(), 5);
+        assert_eq!(app.group_count(), 2);
+
+        app.group("/v2")
+            .group("/users")
+            .get("/:id", |_ctx: RequestCtx| async { "user" });
+        app.host("api.example.com").group("/internal");
+        assert_eq!(app.group_count(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_failing_startup_hook_aborts_bind() {
+        // Find a free port, then release it for the engine
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+
+        let ran = Arc::new(AtomicBool::new(false));
+        let mut app = Engine::new();
+        app.quiet()
+            .on_startup({
+                let ran = ran.clone();
+                move || async move {
+                    ran.store(true, Ordering::SeqCst);
+                    Ok::<_, std::io::Error>(())
+                }
+            })
+            .on_startup(|| async { Err("migrations failed") });
+
+        let err = app.bind(&addr).await.err().expect("startup should fail");
+        assert_eq!(err.to_string(), "migrations failed");
+        assert!(ran.load(Ordering::SeqCst));
+        // The port was never taken
+        assert!(std::net::TcpListener::bind(&addr).is_ok());
+
+        let mut app = Engine::new();
+        app.quiet()
+            .on_startup(|| async { Ok::<_, std::io::Error>(()) });
+        assert!(app.bind("127.0.0.1:0").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_hooks_run_after_drain() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut app = Engine::new();
+        app.quiet();
+        for name in ["flush logs", "close pool"] {
+            let events = events.clone();
+            app.on_shutdown(move || async move {
+                events.lock().unwrap().push(name);
+            });
+        }
+        app.get("/", |_ctx: RequestCtx| async { "ok" });
+
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn({
+            let events = events.clone();
+            async move {
+                let _ = bound
+                    .serve_with_shutdown(async {
+                        let _ = stopped.await;
+                    })
+                    .await;
+                events.lock().unwrap().push("server returned");
+            }
+        });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.ends_with("ok"));
+        assert!(events.lock().unwrap().is_empty());
+
+        stop.send(()).unwrap();
+        server.await.unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["flush logs", "close pool", "server returned"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_request() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (started, running) = tokio::sync::oneshot::channel::<()>();
+        let started = Arc::new(Mutex::new(Some(started)));
+        let mut app = Engine::new();
+        app.quiet();
+        app.get("/slow", {
+            let events = events.clone();
+            move |_ctx: RequestCtx| {
+                let events = events.clone();
+                let started = started.lock().unwrap().take();
+                async move {
+                    if let Some(started) = started {
+                        let _ = started.send(());
+                    }
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    events.lock().unwrap().push("handler finished");
+                    "slow"
+                }
+            }
+        });
+        app.on_shutdown({
+            let events = events.clone();
+            move || async move {
+                events.lock().unwrap().push("shutdown hook");
+            }
+        });
+
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            bound
+                .serve_with_shutdown(async {
+                    let _ = stopped.await;
+                })
+                .await
+                .is_ok()
+        });
+
+        // Keep-alive, so only the drain closes the connection after the response
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /slow HTTP/1.1\r\nHost: test\r\n\r\n")
+            .await
+            .unwrap();
+        running.await.unwrap();
+        stop.send(()).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("slow"));
+        assert!(server.await.unwrap());
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["handler finished", "shutdown hook"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_readyz_draining_during_shutdown() {
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let released = Arc::new(Mutex::new(Some(released)));
+        let mut app = Engine::new();
+        app.quiet();
+        app.health_endpoints();
+        // Holds the drain open until the test has probed the server
+        app.get("/export", move |_ctx: RequestCtx| {
+            let released = released.lock().unwrap().take();
+            async move {
+                if let Some(released) = released {
+                    let _ = released.await;
+                }
+                "exported"
+            }
+        });
+
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            bound
+                .serve_with_shutdown(async {
+                    let _ = stopped.await;
+                })
+                .await
+                .is_ok()
+        });
+
+        let mut export = tokio::net::TcpStream::connect(addr).await.unwrap();
+        export
+            .write_all(b"GET /export HTTP/1.1\r\nHost: test\r\n\r\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stop.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // A balancer's probes arrive on fresh connections
+        let probe = async |path: &str| {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let request = format!("GET {path} HTTP/1.1\r\nHost: test\r\n\r\n");
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        let readyz = probe("/readyz").await;
+        assert!(readyz.starts_with("HTTP/1.1 503"), "{readyz}");
+        assert!(readyz.ends_with("draining"));
+        assert!(probe("/livez").await.starts_with("HTTP/1.1 200 OK"));
+        assert!(!server.is_finished());
+
+        release.send(()).unwrap();
+        let mut response = String::new();
+        export.read_to_string(&mut response).await.unwrap();
+        assert!(response.ends_with("exported"));
+        assert!(server.await.unwrap());
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_streaming_handlers_bypass_body_limit() {
+        use http_body_util::BodyExt;
+
+        let upload = std::env::temp_dir().join(format!("ree-limit-{}.bin", std::process::id()));
+        let mut app = Engine::new();
+        app.max_body_size(8);
+        app.post("/buffered", |ctx: RequestCtx| async move {
+            format!("{} bytes", ctx.body_bytes().map_or(0, |body| body.len()))
+        });
+        app.post("/upload", {
+            let upload = upload.clone();
+            crate::streaming(move |mut ctx: RequestCtx| {
+                let upload = upload.clone();
+                async move {
+                    match ctx.save_body_to_file(&upload, 1024).await {
+                        Ok(written) => format!("stored {written} bytes"),
+                        Err(err) => err.to_string(),
+                    }
+                }
+            })
+        });
+        let app = App::new(app);
+
+        let post = |uri: &str, body: &'static str| {
+            let body = http_body_util::Full::new(Bytes::from_static(body.as_bytes()))
+                .map_err(|never| match never {})
+                .boxed();
+            RequestCtx::streaming(hyper::Request::post(uri).body(body).unwrap())
+        };
+
+        let response = app.handle(post("/buffered", "tiny")).await;
+        assert_eq!(body_text(response).await, "4 bytes");
+        let response = app.handle(post("/buffered", "sixteen bytes!!!")).await;
+        assert_eq!(response.status(), hyper::StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = app.handle(post("/upload", "sixteen bytes!!!")).await;
+        assert_eq!(body_text(response).await, "stored 16 bytes");
+        std::fs::remove_file(upload).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_on_match_middleware_skips_404s() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut app = Engine::new();
+        app.use_middleware_on_match({
+            let runs = runs.clone();
+            move |ctx, next| {
+                runs.fetch_add(1, Ordering::SeqCst);
+                next(ctx)
+            }
+        });
+        app.get("/users/:id", |_ctx: RequestCtx| async { "user" });
+        app.group("/admin")
+            .get("/stats", |_ctx: RequestCtx| async { "stats" });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/users/7")).await;
+        assert_eq!(body_text(response).await, "user");
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        let response = app.handle(request("GET", "/admin/stats")).await;
+        assert_eq!(body_text(response).await, "stats");
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        for uri in ["/missing", "/admin/missing"] {
+            let response = app.handle(request("GET", uri)).await;
+            assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_panic_handler_sees_error_context() {
+        let seen = Arc::new(Mutex::new(None));
+        let mut app = Engine::new();
+        app.use_middleware(crate::request_id());
+        app.on_panic({
+            let seen = seen.clone();
+            move |err, message| {
+                *seen.lock().unwrap() = Some((err.clone(), message.to_string()));
+                ResponseBuilder::new()
+                    .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
+                    .body("custom error page")
+            }
+        });
+        app.get("/reports/:id", |_ctx: RequestCtx| async {
+            panic!("report generator crashed");
+            #[allow(unreachable_code)]
+            "unreachable"
+        });
+        let app = App::new(app);
+
+        let request = hyper::Request::get("/reports/9")
+            .header("X-Request-Id", "req-panic")
+            .body(Bytes::new())
+            .unwrap();
+        let response = app
+            .handle(RequestCtx::new(request, Default::default()))
+            .await;
+        assert_eq!(response.status(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body_text(response).await, "custom error page");
+
+        let (err, message) = seen.lock().unwrap().take().unwrap();
+        assert_eq!(err.method, "GET");
+        assert_eq!(err.path, "/reports/9");
+        assert_eq!(err.route_pattern.as_deref(), Some("/reports/:id"));
+        assert_eq!(err.request_id.as_deref(), Some("req-panic"));
+        assert_eq!(message, "report generator crashed");
+    }
+
+    #[tokio::test]
+    async fn test_head_handler_sets_length_without_body() {
+        let mut app = Engine::new();
+        app.quiet();
+        app.get("/report", |_ctx: RequestCtx| async {
+            "a fairly large report"
+        });
+        app.head("/report", |ctx: RequestCtx| async move {
+            assert!(ctx.is_head());
+            ResponseBuilder::new()
+                .header("Content-Length", "21")
+                .empty_body()
+        });
+        app.head("/careless", |_ctx: RequestCtx| async {
+            "body nobody asked for"
+        });
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = bound.serve().await;
+        });
+
+        for path in ["/report", "/careless"] {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let request = format!("HEAD {path} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut raw = String::new();
+            stream.read_to_string(&mut raw).await.unwrap();
+            let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+            assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+            assert!(head.contains("content-length: 21"), "{head}");
+            assert_eq!(body, "");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_static_manifest() {
+        let dir = std::env::temp_dir().join(format!("ree-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("dist")).unwrap();
+        std::fs::write(dir.join("dist/app.3f2a9c.js"), "console.log(1)").unwrap();
+        let manifest = dir.join("manifest.json");
+        std::fs::write(&manifest, r#"{"/assets/app.js": "dist/app.3f2a9c.js"}"#).unwrap();
+
+        let mut app = Engine::new();
+        app.static_manifest(&manifest).unwrap();
+        let app = App::new(app);
+        let response = app.handle(request("GET", "/assets/app.js")).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(
+            response.headers()["cache-control"],
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/javascript; charset=utf-8"
+        );
+        assert_eq!(body_text(response).await, "console.log(1)");
+
+        std::fs::write(&manifest, r#"{"/assets/gone.css": "dist/gone.css"}"#).unwrap();
+        let err = Engine::new().static_manifest(&manifest).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_json_error_formatter() {
+        let build = |formatter: bool| {
+            let mut app = Engine::new();
+            if formatter {
+                app.json_error_formatter(|err| {
+                    serde_json::json!({ "message": "bad JSON", "line": err.line(), "column": err.column() })
+                });
+            }
+            app.post("/items", |ctx: RequestCtx| async move {
+                let item: serde_json::Value = ctx.json()?;
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(item.to_string())
+            });
+            App::new(app)
+        };
+        let post = |body: &'static str| {
+            let (parts, _) = hyper::Request::post("/items")
+                .body(())
+                .unwrap()
+                .into_parts();
+            RequestCtx::from_parts(parts, Bytes::from_static(body.as_bytes()))
+        };
+
+        let response = build(true).handle(post("{\n  \"name\": ]")).await;
+        assert_eq!(response.status(), hyper::StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body_text(response).await,
+            r#"{"column":11,"line":2,"message":"bad JSON"}"#
+        );
+
+        let response = build(false).handle(post("{\"secret\": ")).await;
+        assert_eq!(response.status(), hyper::StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body_text(response).await,
+            r#"{"error":"Invalid JSON body","status":400}"#
+        );
+
+        let response = build(false).handle(post(r#"{"name": "pen"}"#)).await;
+        assert_eq!(body_text(response).await, r#"{"name":"pen"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_bind_all_serves_every_address() {
+        let mut app = Engine::new();
+        app.quiet();
+        app.get("/ping", |_| async { "pong" });
+        let bound = app.bind_all(&["127.0.0.1:0", "127.0.0.1:0"]).await.unwrap();
+        let addrs = bound.local_addrs().unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert_ne!(addrs[0], addrs[1]);
+        assert_eq!(bound.local_addr().unwrap(), addrs[0]);
+
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let _ = bound
+                .serve_with_shutdown(async {
+                    let _ = stopped.await;
+                })
+                .await;
+        });
+        for addr in &addrs {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut raw = String::new();
+            stream.read_to_string(&mut raw).await.unwrap();
+            assert!(raw.starts_with("HTTP/1.1 200 OK"), "{raw}");
+            assert!(raw.ends_with("pong"));
+        }
+
+        // Shutting down closes every listener
+        stop.send(()).unwrap();
+        server.await.unwrap();
+        for addr in &addrs {
+            assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_detects_framework_404() {
+        let mut app = Engine::new();
+        app.use_middleware(|ctx, next| async move {
+            let response = next(ctx).await;
+            match response.extensions().get::<crate::Unmatched>() {
+                Some(crate::Unmatched::NotFound) => ResponseBuilder::new().body("fallback page"),
+                _ => response,
+            }
+        });
+        app.get("/users/:id", |ctx: RequestCtx| async move {
+            match ctx.param_str("id") {
+                Some("1") => ResponseBuilder::new().body("alice"),
+                _ => ResponseBuilder::not_found(),
+            }
+        });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/missing")).await;
+        assert_eq!(body_text(response).await, "fallback page");
+
+        // A 404 from the handler itself is left alone
+        let response = app.handle(request("GET", "/users/2")).await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        assert!(response.extensions().get::<crate::Unmatched>().is_none());
+
+        let response = app.handle(request("DELETE", "/users/1")).await;
+        assert_eq!(response.status(), hyper::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()["allow"], "GET");
+        assert_eq!(
+            response.extensions().get::<crate::Unmatched>(),
+            Some(&crate::Unmatched::MethodNotAllowed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provide_trait_object_service() {
+        trait Counter: Send + Sync {
+            fn next(&self) -> usize;
+        }
+
+        struct Atomic(AtomicUsize);
+
+        impl Counter for Atomic {
+            fn next(&self) -> usize {
+                self.0.fetch_add(1, Ordering::SeqCst) + 1
+            }
+        }
+
+        let mut app = Engine::new();
+        app.provide::<dyn Counter>(Arc::new(Atomic(AtomicUsize::new(0))));
+        app.get("/count", |ctx: RequestCtx| async move {
+            let missing = ctx.inject::<String>().is_none();
+            let counter = ctx.inject::<dyn Counter>().unwrap();
+            format!("{} {missing}", counter.next())
+        });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/count")).await;
+        assert_eq!(body_text(response).await, "1 true");
+        let response = app.handle(request("GET", "/count")).await;
+        assert_eq!(body_text(response).await, "2 true");
+    }
+
+    #[tokio::test]
+    async fn test_with_state_of_two_types() {
+        struct Config {
+            name: &'static str,
+        }
+        struct Hits(AtomicUsize);
+
+        let mut app = Engine::new();
+        app.with_state(Config { name: "ree" })
+            .with_state(Hits(AtomicUsize::new(0)));
+        app.get("/", |ctx: RequestCtx| async move {
+            let config = ctx.state::<Config>().unwrap();
+            let hits = ctx.state::<Hits>().unwrap();
+            let missing = ctx.state::<String>().is_none();
+            let hits = hits.0.fetch_add(1, Ordering::SeqCst) + 1;
+            format!("{} {hits} {missing}", config.name)
+        });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/")).await;
+        assert_eq!(body_text(response).await, "ree 1 true");
+        let response = app.handle(request("GET", "/")).await;
+        assert_eq!(body_text(response).await, "ree 2 true");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_reuse_port_shares_address() {
+        let bind = |addr: String| async move {
+            let mut app = Engine::new();
+            app.quiet().reuse_port(true);
+            app.get("/", |_ctx: RequestCtx| async { "ok" });
+            app.bind(&addr).await.unwrap()
+        };
+        let first = bind("127.0.0.1:0".to_string()).await;
+        let addr = first.local_addr().unwrap();
+        let second = bind(addr.to_string()).await;
+        assert_eq!(second.local_addr().unwrap(), addr);
+
+        // Without the option the address is taken
+        let mut app = Engine::new();
+        app.quiet();
+        assert!(app.bind(&addr.to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_continue_sent_only_when_body_is_read() {
+        let mut app = Engine::new();
+        app.quiet();
+        app.post("/echo", |ctx: RequestCtx| async move {
+            ctx.body_string().unwrap().unwrap_or_default()
+        });
+        app.post(
+            "/ignore",
+            crate::streaming(|_ctx: RequestCtx| async { "ignored" }),
+        );
+        app.group("/admin")
+            .use_middleware(|_ctx, _next| async {
+                ResponseBuilder::new()
+                    .status(hyper::StatusCode::UNAUTHORIZED)
+                    .body("no")
+            })
+            .post("/upload", |_ctx: RequestCtx| async { "stored" });
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = bound.serve().await;
+        });
+
+        // Send the headers, then read what the server answers before any body
+        let first_reply = |path: &'static str| async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let head = format!(
+                "POST {path} HTTP/1.1\r\nHost: test\r\nContent-Length: 5\r\n\
+                 Expect: 100-continue\r\n\r\n"
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            let mut buf = [0; 1024];
+            let read = stream.read(&mut buf).await.unwrap();
+            (stream, String::from_utf8_lossy(&buf[..read]).to_string())
+        };
+
+        let (mut stream, reply) = first_reply("/echo").await;
+        assert!(reply.starts_with("HTTP/1.1 100 Continue"), "{reply}");
+        stream.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 1024];
+        let read = stream.read(&mut buf).await.unwrap();
+        let reply = String::from_utf8_lossy(&buf[..read]);
+        assert!(reply.starts_with("HTTP/1.1 200 OK"), "{reply}");
+        assert!(reply.ends_with("hello"), "{reply}");
+
+        let (_, reply) = first_reply("/ignore").await;
+        assert!(reply.starts_with("HTTP/1.1 200 OK"), "{reply}");
+        assert!(reply.ends_with("ignored"), "{reply}");
+
+        let (_, reply) = first_reply("/admin/upload").await;
+        assert!(reply.starts_with("HTTP/1.1 401"), "{reply}");
+
+        let (_, reply) = first_reply("/missing").await;
+        assert!(reply.starts_with("HTTP/1.1 404"), "{reply}");
+    }
+
+    #[tokio::test]
+    async fn test_replace_routes_between_requests() {
+        let mut app = Engine::new();
+        app.get("/flag", |_ctx: RequestCtx| async { "old" });
+        app.group("/api")
+            .get("/ping", |_ctx: RequestCtx| async { "pong" });
+        let routes = app.route_table();
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/flag")).await;
+        assert_eq!(body_text(response).await, "old");
+
+        let mut next = Router::new();
+        next.add_route("GET", "/flag", Box::new(|_ctx: RequestCtx| async { "new" }));
+        next.add_route(
+            "GET",
+            "/beta",
+            Box::new(|_ctx: RequestCtx| async { "beta" }),
+        );
+        routes.replace_routes(next);
+
+        let response = app.handle(request("GET", "/flag")).await;
+        assert_eq!(body_text(response).await, "new");
+        let response = app.handle(request("GET", "/beta")).await;
+        assert_eq!(body_text(response).await, "beta");
+        // Groups keep their own routes
+        let response = app.handle(request("GET", "/api/ping")).await;
+        assert_eq!(body_text(response).await, "pong");
+    }
+
+    #[tokio::test]
+    async fn test_get_with_concurrency_sheds_excess() {
+        let release = Arc::new(tokio::sync::Notify::new());
+        let mut app = Engine::new();
+        app.get_with_concurrency("/report", 1, {
+            let release = release.clone();
+            move |_ctx: RequestCtx| {
+                let release = release.clone();
+                async move {
+                    release.notified().await;
+                    "report"
+                }
+            }
+        });
+        app.get("/health", |_ctx: RequestCtx| async { "ok" });
+        let app = App::new(app);
+
+        let (first, ()) = tokio::join!(app.handle(request("GET", "/report")), async {
+            let shed = app.handle(request("GET", "/report")).await;
+            assert_eq!(shed.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+            let health = app.handle(request("GET", "/health")).await;
+            assert_eq!(body_text(health).await, "ok");
+            release.notify_one();
+        });
+        assert_eq!(body_text(first).await, "report");
+
+        // The permit is back once the first request is done
+        let (again, ()) = tokio::join!(app.handle(request("GET", "/report")), async {
+            release.notify_one();
+        });
+        assert_eq!(again.status(), hyper::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_group_routes_served_alongside_top_level() {
+        let mut app = Engine::new();
+        app.get("/api/health", |_ctx: RequestCtx| async {
+            "top-level health"
+        });
+        app.get("/apiv2/users", |_ctx: RequestCtx| async { "v2 users" });
+        app.group("/api")
+            .use_middleware(|ctx, next| async move {
+                let mut response = next(ctx).await;
+                response
+                    .headers_mut()
+                    .insert("x-group", "api".parse().unwrap());
+                response
+            })
+            .get("/users", |_ctx: RequestCtx| async { "group users" })
+            .get("/health", |_ctx: RequestCtx| async { "group health" });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/api/users")).await;
+        assert_eq!(response.headers()["x-group"], "api");
+        assert_eq!(body_text(response).await, "group users");
+
+        // Both define the route: the group, being more specific, wins
+        let response = app.handle(request("GET", "/api/health")).await;
+        assert_eq!(body_text(response).await, "group health");
+
+        // `/api` doesn't cover `/apiv2`
+        let response = app.handle(request("GET", "/apiv2/users")).await;
+        assert_eq!(body_text(response).await, "v2 users");
+
+        // Unrouted paths under a prefix get the group's middleware and 404
+        let response = app.handle(request("GET", "/api/missing")).await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()["x-group"], "api");
+    }
+
+    #[tokio::test]
+    async fn test_nested_group_runs_parent_middleware_first() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut app = Engine::new();
+        let api = app.group("/api");
+        let v1 = api.group("/v1");
+        v1.use_middleware_named("v1", {
+            let order = order.clone();
+            move |ctx: RequestCtx, next: Next| {
+                order.lock().unwrap().push("v1");
+                next(ctx)
+            }
+        })
+        .get("/users", |_ctx: RequestCtx| async { "v1 users" });
+        // Added after the nested group was created, still ahead of it
+        api.use_middleware_named("auth", {
+            let order = order.clone();
+            move |ctx: RequestCtx, next: Next| {
+                order.lock().unwrap().push("auth");
+                async move {
+                    if ctx.header("authorization") != Some("Bearer secret") {
+                        return ResponseBuilder::new()
+                            .status(hyper::StatusCode::UNAUTHORIZED)
+                            .body("unauthorized");
+                    }
+                    next(ctx).await
+                }
+            }
+        })
+        .get("/status", |_ctx: RequestCtx| async { "up" });
+        assert_eq!(
+            app.routes(),
+            [
+                ("GET".to_string(), "/api/status".to_string()),
+                ("GET".to_string(), "/api/v1/users".to_string()),
+            ]
+        );
+        let app = App::new(app);
+
+        let mut ctx = request("GET", "/api/v1/users");
+        ctx.request
+            .headers_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        let response = app.handle(ctx).await;
+        assert_eq!(body_text(response).await, "v1 users");
+        assert_eq!(*order.lock().unwrap(), ["auth", "v1"]);
+
+        order.lock().unwrap().clear();
+        let response = app.handle(request("GET", "/api/v1/users")).await;
+        assert_eq!(response.status(), hyper::StatusCode::UNAUTHORIZED);
+        assert_eq!(*order.lock().unwrap(), ["auth"]);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let mut app = Engine::new();
+        app.request_timeout(Duration::from_millis(50))
+            .use_middleware(|ctx, next| async move {
+                let mut response = next(ctx).await;
+                response
+                    .headers_mut()
+                    .insert("x-outer", "seen".parse().unwrap());
+                response
+            })
+            .get("/slow", |_ctx: RequestCtx| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "slow"
+            })
+            .get("/fast", |_ctx: RequestCtx| async { "fast" });
+        let app = App::new(app);
+
+        let started = std::time::Instant::now();
+        let response = app.handle(request("GET", "/slow")).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(response.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+        // Middleware is cut off along with the handler
+        assert!(response.headers().get("x-outer").is_none());
+        assert_eq!(
+            body_text(response).await,
+            r#"{"error":"Request timed out","status":503}"#
+        );
+
+        let response = app.handle(request("GET", "/fast")).await;
+        assert_eq!(response.headers()["x-outer"], "seen");
+        assert_eq!(body_text(response).await, "fast");
+    }
+
+    fn slash_routes(mode: TrailingSlash) -> App {
+        let mut app = Engine::new();
+        app.trailing_slash(mode)
+            .get("/", |_ctx: RequestCtx| async { "root" })
+            .get("/users", |_ctx: RequestCtx| async { "users" })
+            .get("/docs/", |_ctx: RequestCtx| async { "docs" })
+            .get("/files/*path", |ctx: RequestCtx| async move {
+                format!("file {}", ctx.param_str("path").unwrap())
+            });
+        App::new(app)
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slash_matches_by_default() {
+        let app = slash_routes(TrailingSlash::Match);
+        for (path, expected) in [
+            ("/", "root"),
+            ("//", "root"),
+            ("/users", "users"),
+            ("/users/", "users"),
+            ("//users", "users"),
+            ("/docs", "docs"),
+            ("/docs/", "docs"),
+            // Empty segments don't reach the wildcard
+            ("/files/a/b/", "file a/b"),
+            ("/files/a//b", "file a/b"),
+        ] {
+            let response = app.handle(request("GET", path)).await;
+            assert_eq!(body_text(response).await, expected, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slash_redirects_to_route_spelling() {
+        let app = slash_routes(TrailingSlash::Redirect);
+        for (path, expected) in [
+            ("/", "root"),
+            ("/users", "users"),
+            ("/docs/", "docs"),
+            ("/files/a/b", "file a/b"),
+        ] {
+            let response = app.handle(request("GET", path)).await;
+            assert_eq!(response.status(), hyper::StatusCode::OK, "{path}");
+            assert_eq!(body_text(response).await, expected, "{path}");
+        }
+        for (path, location) in [
+            ("//", "/"),
+            ("/users/?page=2", "/users?page=2"),
+            ("//users", "/users"),
+            ("/docs", "/docs/"),
+            ("/files/a/b/", "/files/a/b"),
+            ("/files//a/b", "/files/a/b"),
+        ] {
+            let response = app.handle(request("GET", path)).await;
+            assert_eq!(
+                response.status(),
+                hyper::StatusCode::PERMANENT_REDIRECT,
+                "{path}"
+            );
+            assert_eq!(response.headers()["location"], location, "{path}");
+        }
+        let response = app.handle(request("GET", "/missing/")).await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        let response = app.handle(request("POST", "/users/")).await;
+        assert_eq!(response.status(), hyper::StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_mount_detached_groups() {
+        let mut app = Engine::new();
+        let mut users = RouterGroup::new("/users");
+        users.get("/:id", |ctx: RequestCtx| async move {
+            format!("user {}", ctx.param_str("id").unwrap())
+        });
+        // Top-level routes in between, while `users` is still being built
+        app.get("/", |_ctx: RequestCtx| async { "home" });
+        let mut admin = RouterGroup::new("/admin");
+        admin
+            .use_middleware(|ctx, next| async move {
+                let mut response = next(ctx).await;
+                response
+                    .headers_mut()
+                    .insert("x-group", "admin".parse().unwrap());
+                response
+            })
+            .get("/stats", |_ctx: RequestCtx| async { "stats" });
+        users.post("", |_ctx: RequestCtx| async { "created" });
+        app.get("/health", |_ctx: RequestCtx| async { "ok" });
+        app.mount_group(users).mount_group(admin);
+        assert_eq!(app.group_count(), 2);
+        let app = App::new(app);
+
+        for (method, path, expected) in [
+            ("GET", "/", "home"),
+            ("GET", "/health", "ok"),
+            ("GET", "/users/7", "user 7"),
+            ("POST", "/users", "created"),
+            ("GET", "/admin/stats", "stats"),
+        ] {
+            let response = app.handle(request(method, path)).await;
+            assert_eq!(response.status(), hyper::StatusCode::OK, "{method} {path}");
+            assert_eq!(body_text(response).await, expected);
+        }
+        let response = app.handle(request("GET", "/admin/stats")).await;
+        assert_eq!(response.headers()["x-group"], "admin");
+    }
+
+    #[tokio::test]
+    async fn test_top_level_route_under_group_prefix() {
+        let mut app = Engine::new();
+        app.get("/admin/login", |_ctx: RequestCtx| async { "login" });
+        app.group("/admin")
+            .get("/stats", |_ctx: RequestCtx| async { "stats" });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/admin/login")).await;
+        assert_eq!(body_text(response).await, "login");
+        let response = app.handle(request("GET", "/admin/stats")).await;
+        assert_eq!(body_text(response).await, "stats");
+    }
+
+    #[tokio::test]
+    async fn test_group_method_mismatch_yields_to_top_level_route() {
+        let mut app = Engine::new();
+        app.get("/admin/stats", |_ctx: RequestCtx| async {
+            "top-level stats"
+        });
+        app.group("/admin")
+            .post("/stats", |_ctx: RequestCtx| async { "reset" });
+        let app = App::new(app);
+
+        let response = app.handle(request("GET", "/admin/stats")).await;
+        assert_eq!(body_text(response).await, "top-level stats");
+        let response = app.handle(request("POST", "/admin/stats")).await;
+        assert_eq!(body_text(response).await, "reset");
+        let response = app.handle(request("DELETE", "/admin/stats")).await;
+        assert_eq!(response.status(), hyper::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[hyper::header::ALLOW], "GET, POST");
     }
 }
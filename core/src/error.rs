@@ -0,0 +1,631 @@
+//! Mapping application error types to HTTP status codes.
+//!
+//! Handlers returning `Result<T, E>` answer errors with `500 Internal Server Error`
+//! by default. Implementing [`ErrorMapper`] for an error type and registering it
+//! once with [`Engine::map_error`](crate::Engine::map_error) makes every
+//! `Err(E)` of that engine's handlers use the status the mapper picks instead.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use hyper::StatusCode;
+
+/// Chooses the HTTP status for an application error type
+///
+/// ```rust
+/// use ree::{Engine, ErrorMapper, RequestCtx, StatusCode};
+///
+/// #[derive(Debug)]
+/// enum UserError {
+///     NotFound,
+///     Taken,
+/// }
+///
+/// impl std::fmt::Display for UserError {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         match self {
+///             UserError::NotFound => write!(f, "user not found"),
+///             UserError::Taken => write!(f, "user name taken"),
+///         }
+///     }
+/// }
+///
+/// impl std::error::Error for UserError {}
+///
+/// impl ErrorMapper for UserError {
+///     fn status(&self) -> StatusCode {
+///         match self {
+///             UserError::NotFound => StatusCode::NOT_FOUND,
+///             UserError::Taken => StatusCode::CONFLICT,
+///         }
+///     }
+/// }
+///
+/// let mut app = Engine::new();
+/// app.map_error::<UserError>();
+/// app.get("/users/:id", |_ctx: RequestCtx| async { Err::<String, _>(UserError::NotFound) });
+/// ```
+pub trait ErrorMapper: std::error::Error + Send + Sync + 'static {
+    /// Status used when a handler returns this error
+    fn status(&self) -> StatusCode;
+}
+
+type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+type StatusFn = fn(&dyn Any) -> Option<StatusCode>;
+
+/// Mappers keyed by the error type they handle
+#[derive(Clone, Default)]
+pub(crate) struct ErrorMappers(HashMap<TypeId, StatusFn>);
+
+impl ErrorMappers {
+    pub(crate) fn insert<E: ErrorMapper>(&mut self) {
+        let status: StatusFn = |err| {
+            err.downcast_ref::<E>()
+                .or_else(|| err.downcast_ref::<BoxedError>()?.downcast_ref())
+                .map(E::status)
+        };
+        self.0.insert(TypeId::of::<E>(), status);
+    }
+
+    /// The status for `err`, which may also be a boxed mapped error
+    fn status<E: 'static>(&self, err: &E) -> Option<StatusCode> {
+        if TypeId::of::<E>() == TypeId::of::<BoxedError>() {
+            return self.0.values().find_map(|status| status(err));
+        }
+        self.0.get(&TypeId::of::<E>())?(err)
+    }
+
+    /// Run `future` with these as the engine's mappers
+    pub(crate) fn scope<F: std::future::Future>(
+        self: Arc<Self>,
+        future: F,
+    ) -> impl std::future::Future<Output = F::Output> {
+        ENGINE_MAPPERS.scope(self, future)
+    }
+}
+
+tokio::task_local! {
+    /// Mappers of the engine handling the current request
+    static ENGINE_MAPPERS: Arc<ErrorMappers>;
+}
+
+/// Mappers registered with [`register_error_mapper`]
+fn registry() -> &'static RwLock<ErrorMappers> {
+    static REGISTRY: OnceLock<RwLock<ErrorMappers>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Register `E`'s [`ErrorMapper`] for every `Result<T, E>` returned by handlers
+///
+/// Unlike [`Engine::map_error`](crate::Engine::map_error) this is process-wide,
+/// and also covers results turned into responses outside any engine. An
+/// engine's own mappers take precedence.
+pub fn register_error_mapper<E: ErrorMapper>() {
+    registry().write().unwrap().insert::<E>();
+}
+
+/// The status mapped for `err`, looking inside a `Box<dyn Error + Send + Sync>` too
+///
+/// The mappers of the engine handling the current request come first, then
+/// the process-wide ones.
+pub(crate) fn mapped_status<E: 'static>(err: &E) -> Option<StatusCode> {
+    ENGINE_MAPPERS
+        .try_with(|mappers| mappers.status(err))
+        .ok()
+        .flatten()
+        .or_else(|| registry().read().unwrap().status(err))
+}
+
+/// An early exit carrying a status and a message, for use with `?`
+///
+/// Returned as the error of a `Result` from a handler or middleware, it becomes a
+/// JSON response `{"error": message, "status": code}` with that status and
+/// stops the request there. See [`Engine::use_middleware`](crate::Engine::use_middleware).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl Rejection {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// A `401 Unauthorized` rejection
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, message)
+    }
+
+    /// A `403 Forbidden` rejection
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    /// A `400 Bad Request` rejection
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl crate::IntoResponse for Rejection {
+    fn into_response(self) -> crate::Response {
+        let body = serde_json::json!({ "error": self.message, "status": self.status.as_u16() });
+        crate::ResponseBuilder::new()
+            .status(self.status)
+            .content_type("application/json; charset=utf-8")
+            .body(body.to_string())
+    }
+}
+
+/// Media type of RFC 7807 problem details
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// An RFC 7807 problem details response, see [`ResponseBuilder::problem`](crate::ResponseBuilder::problem)
+///
+/// Sent as `application/problem+json` with the `type`, `title`, `status` and,
+/// when given, `detail` members, plus any extension members.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    status: StatusCode,
+    type_uri: String,
+    title: String,
+    detail: Option<String>,
+    extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Problem {
+    /// A problem of type `type_uri` with a short, occurrence-independent `title`
+    ///
+    /// Use `about:blank` as `type_uri` when the status says it all; the title
+    /// should then be the status' reason phrase.
+    pub fn new(status: StatusCode, type_uri: impl Into<String>, title: impl Into<String>) -> Self {
+        Self {
+            status,
+            type_uri: type_uri.into(),
+            title: title.into(),
+            detail: None,
+            extensions: serde_json::Map::new(),
+        }
+    }
+
+    /// Explain this occurrence of the problem
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Add an extension member, e.g. `balance` for an out-of-credit problem
+    ///
+    /// The standard members can't be replaced this way; such names are ignored.
+    pub fn extension(mut self, name: &str, value: impl serde::Serialize) -> Self {
+        if !matches!(name, "type" | "title" | "status" | "detail") {
+            let value = serde_json::to_value(value).unwrap_or(serde_json::Value::Null);
+            self.extensions.insert(name.to_string(), value);
+        }
+        self
+    }
+
+    /// The problem as its JSON object
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut body = serde_json::Map::new();
+        body.insert("type".to_string(), self.type_uri.clone().into());
+        body.insert("title".to_string(), self.title.clone().into());
+        body.insert("status".to_string(), self.status.as_u16().into());
+        if let Some(detail) = &self.detail {
+            body.insert("detail".to_string(), detail.clone().into());
+        }
+        body.extend(self.extensions.clone());
+        body.into()
+    }
+}
+
+impl crate::IntoResponse for Problem {
+    fn into_response(self) -> crate::Response {
+        crate::ResponseBuilder::new()
+            .status(self.status)
+            .content_type(PROBLEM_JSON)
+            .body(self.to_json().to_string())
+    }
+}
+
+/// Shapes the `400` body for invalid JSON, see [`Engine::json_error_formatter`](crate::Engine::json_error_formatter)
+pub(crate) type JsonErrorFormatter =
+    std::sync::Arc<dyn Fn(&serde_json::Error) -> serde_json::Value + Send + Sync>;
+
+/// A request body that isn't valid JSON, returned by [`RequestCtx::json`](crate::RequestCtx::json)
+///
+/// Answered with `400 Bad Request` and the body built by the engine's
+/// [`json_error_formatter`](crate::Engine::json_error_formatter); by default a
+/// generic message that doesn't echo the parser error back to the client.
+#[derive(Debug)]
+pub struct JsonRejection {
+    /// The parser error, for logging
+    pub error: serde_json::Error,
+    body: serde_json::Value,
+}
+
+impl JsonRejection {
+    pub(crate) fn new(error: serde_json::Error, formatter: Option<&JsonErrorFormatter>) -> Self {
+        let body = match formatter {
+            Some(formatter) => formatter(&error),
+            None => serde_json::json!({
+                "error": "Invalid JSON body",
+                "status": StatusCode::BAD_REQUEST.as_u16(),
+            }),
+        };
+        Self { error, body }
+    }
+
+    pub(crate) fn response(&self) -> crate::Response {
+        crate::ResponseBuilder::new()
+            .status(StatusCode::BAD_REQUEST)
+            .content_type("application/json; charset=utf-8")
+            .body(self.body.to_string())
+    }
+}
+
+impl std::fmt::Display for JsonRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid JSON body: {}", self.error)
+    }
+}
+
+impl std::error::Error for JsonRejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl crate::IntoResponse for JsonRejection {
+    fn into_response(self) -> crate::Response {
+        self.response()
+    }
+}
+
+/// One invalid field, see [`ValidationErrors`]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct FieldError {
+    /// Path to the field, with `.` between nested fields and `[i]` for list items
+    pub field: String,
+    /// Machine-readable reason, e.g. `required` or `invalid`
+    pub code: String,
+    /// Human-readable explanation
+    pub message: String,
+}
+
+/// Field-level validation failures of a request
+///
+/// Answered with `422 Unprocessable Entity` and the body
+/// `{"errors": [{"field": "address.zip", "code": "invalid", "message": "..."}]}`,
+/// also when returned boxed as `Box<dyn Error + Send + Sync>`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `field` is invalid
+    pub fn add(
+        &mut self,
+        field: impl Into<String>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) -> &mut Self {
+        self.errors.push(FieldError {
+            field: field.into(),
+            code: code.into(),
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Merge the errors of a nested value, prefixing their paths with `prefix`
+    ///
+    /// `errors.nest("address", address.validate())` turns `zip` into `address.zip`.
+    pub fn nest(&mut self, prefix: &str, nested: Result<(), ValidationErrors>) -> &mut Self {
+        if let Err(nested) = nested {
+            self.errors
+                .extend(nested.errors.into_iter().map(|mut error| {
+                    error.field = if error.field.is_empty() {
+                        prefix.to_string()
+                    } else if error.field.starts_with('[') {
+                        format!("{prefix}{}", error.field)
+                    } else {
+                        format!("{prefix}.{}", error.field)
+                    };
+                    error
+                }));
+        }
+        self
+    }
+
+    /// The recorded errors, in order
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// `Ok` when nothing was recorded, for ending a [`Validate::validate`] impl
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Validation failed")?;
+        for (index, error) in self.errors.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { ", " };
+            write!(
+                f,
+                "{separator}{} ({}): {}",
+                error.field, error.code, error.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl crate::IntoResponse for ValidationErrors {
+    fn into_response(self) -> crate::Response {
+        let body = serde_json::json!({ "errors": self.errors });
+        crate::ResponseBuilder::new()
+            .status(StatusCode::UNPROCESSABLE_ENTITY)
+            .content_type("application/json; charset=utf-8")
+            .body(body.to_string())
+    }
+}
+
+/// Why [`RequestCtx::validated_body`](crate::RequestCtx::validated_body) failed
+///
+/// Each failure is answered with its own status when returned from a handler:
+/// an unsupported `Content-Type` with `415 Unsupported Media Type`, a body over
+/// the size limit with `413 Payload Too Large`, a missing or malformed body with
+/// `400 Bad Request` and failed validation with `422` as [`ValidationErrors`].
+#[derive(Debug)]
+pub enum BodyRejection {
+    /// The body couldn't be read or deserialized
+    Body(crate::BodyError),
+    /// The body deserialized but its fields are invalid
+    Validation(ValidationErrors),
+}
+
+impl BodyRejection {
+    /// The status the rejection is answered with
+    pub fn status(&self) -> StatusCode {
+        match self {
+            BodyRejection::Body(crate::BodyError::UnsupportedMediaType(_)) => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            BodyRejection::Body(crate::BodyError::TooLarge(_)) => StatusCode::PAYLOAD_TOO_LARGE,
+            BodyRejection::Body(_) => StatusCode::BAD_REQUEST,
+            BodyRejection::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    pub(crate) fn response(&self) -> crate::Response {
+        let message = match self {
+            BodyRejection::Validation(errors) => {
+                return crate::IntoResponse::into_response(errors.clone());
+            }
+            // Like `JsonRejection`, parser errors aren't echoed to the client
+            BodyRejection::Body(crate::BodyError::Json(_)) => "Invalid JSON body".to_string(),
+            BodyRejection::Body(err) => err.to_string(),
+        };
+        crate::IntoResponse::into_response(Rejection::new(self.status(), message))
+    }
+}
+
+impl std::fmt::Display for BodyRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyRejection::Body(err) => err.fmt(f),
+            BodyRejection::Validation(errors) => errors.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BodyRejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BodyRejection::Body(err) => Some(err),
+            BodyRejection::Validation(errors) => Some(errors),
+        }
+    }
+}
+
+impl crate::IntoResponse for BodyRejection {
+    fn into_response(self) -> crate::Response {
+        self.response()
+    }
+}
+
+/// Request types that check their own fields, see [`RequestCtx::json_validated`](crate::RequestCtx::json_validated)
+///
+/// ```rust
+/// use ree::{Validate, ValidationErrors};
+///
+/// struct Address {
+///     zip: String,
+/// }
+///
+/// struct Signup {
+///     email: String,
+///     address: Address,
+/// }
+///
+/// impl Validate for Address {
+///     fn validate(&self) -> Result<(), ValidationErrors> {
+///         let mut errors = ValidationErrors::new();
+///         if self.zip.len() != 5 {
+///             errors.add("zip", "invalid", "must be 5 digits");
+///         }
+///         errors.into_result()
+///     }
+/// }
+///
+/// impl Validate for Signup {
+///     fn validate(&self) -> Result<(), ValidationErrors> {
+///         let mut errors = ValidationErrors::new();
+///         if !self.email.contains('@') {
+///             errors.add("email", "invalid", "must be an email address");
+///         }
+///         errors.nest("address", self.address.validate());
+///         errors.into_result()
+///     }
+/// }
+/// ```
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntoResponse;
+
+    #[derive(Debug)]
+    enum AccountError {
+        Missing,
+        Conflict,
+    }
+
+    impl std::fmt::Display for AccountError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{self:?}")
+        }
+    }
+
+    impl std::error::Error for AccountError {}
+
+    impl ErrorMapper for AccountError {
+        fn status(&self) -> StatusCode {
+            match self {
+                AccountError::Missing => StatusCode::NOT_FOUND,
+                AccountError::Conflict => StatusCode::CONFLICT,
+            }
+        }
+    }
+
+    #[test]
+    fn test_registered_errors_use_mapped_status() {
+        register_error_mapper::<AccountError>();
+
+        let missing: Result<&str, AccountError> = Err(AccountError::Missing);
+        assert_eq!(missing.into_response().status(), StatusCode::NOT_FOUND);
+
+        let conflict: Result<&str, AccountError> = Err(AccountError::Conflict);
+        assert_eq!(conflict.into_response().status(), StatusCode::CONFLICT);
+
+        // Also as the `?`-converted error of a boxed result
+        let boxed: Result<&str, BoxedError> = Err(AccountError::Conflict.into());
+        assert_eq!(boxed.into_response().status(), StatusCode::CONFLICT);
+
+        // Unregistered error types keep the 500 default
+        let other: Result<&str, String> = Err("boom".to_string());
+        assert_eq!(
+            other.into_response().status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    struct Item {
+        name: String,
+    }
+
+    struct Order {
+        email: String,
+        zip: String,
+        items: Vec<Item>,
+    }
+
+    impl Validate for Item {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            let mut errors = ValidationErrors::new();
+            if self.name.is_empty() {
+                errors.add("name", "required", "name is required");
+            }
+            errors.into_result()
+        }
+    }
+
+    impl Validate for Order {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            let mut errors = ValidationErrors::new();
+            if !self.email.contains('@') {
+                errors.add("email", "invalid", "not an email address");
+            }
+            let mut address = ValidationErrors::new();
+            if self.zip.len() != 5 {
+                address.add("zip", "invalid", "must be 5 digits");
+            }
+            errors.nest("address", address.into_result());
+            for (index, item) in self.items.iter().enumerate() {
+                errors.nest("items", {
+                    let mut list = ValidationErrors::new();
+                    list.nest(&format!("[{index}]"), item.validate());
+                    list.into_result()
+                });
+            }
+            errors.into_result()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validation_errors_response_shape() {
+        use http_body_util::BodyExt;
+
+        let order = Order {
+            email: "nobody".to_string(),
+            zip: "123".to_string(),
+            items: vec![
+                Item {
+                    name: "tea".to_string(),
+                },
+                Item {
+                    name: String::new(),
+                },
+            ],
+        };
+        let errors = order.validate().unwrap_err();
+        let boxed: Result<String, Box<dyn std::error::Error + Send + Sync>> = Err(errors.into());
+
+        let response = boxed.into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "errors": [
+                { "field": "email", "code": "invalid", "message": "not an email address" },
+                { "field": "address.zip", "code": "invalid", "message": "must be 5 digits" },
+                { "field": "items[1].name", "code": "required", "message": "name is required" },
+            ] })
+        );
+    }
+}
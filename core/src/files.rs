@@ -0,0 +1,465 @@
+//! Helpers for sending files from disk.
+
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Bytes, Frame};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::{Handler, Next, RequestCtx, Response, ResponseBuilder, Unmatched};
+
+/// Size of the chunks a file is sent in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A response body reading a file chunk by chunk as the client consumes it
+pub(crate) struct FileBody {
+    file: tokio::fs::File,
+    buf: Box<[u8]>,
+}
+
+impl FileBody {
+    pub(crate) fn new(file: tokio::fs::File) -> Self {
+        Self {
+            file,
+            buf: vec![0; CHUNK_SIZE].into_boxed_slice(),
+        }
+    }
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, hyper::Error>>> {
+        let this = self.get_mut();
+        let mut buf = ReadBuf::new(&mut this.buf);
+        match Pin::new(&mut this.file).poll_read(cx, &mut buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(())) if buf.filled().is_empty() => Poll::Ready(None),
+            Poll::Ready(Ok(())) => {
+                Poll::Ready(Some(Ok(Frame::data(Bytes::copy_from_slice(buf.filled())))))
+            }
+            Poll::Ready(Err(err)) => {
+                // hyper::Error can't be built from an io::Error; ending the body early
+                // makes the short Content-Length visible to the client
+                eprintln!("Failed to read file for response: {err}");
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+/// Guess a `Content-Type` from a file extension
+pub(crate) fn content_type_for(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "application/javascript; charset=utf-8",
+        Some("json") => "application/json; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("webp") => "image/webp",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("mp4") => "video/mp4",
+        Some("mp3") => "audio/mpeg",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `Content-Disposition: attachment` value for `filename`
+///
+/// Plain ASCII names are sent as a quoted `filename`. Anything else also gets an
+/// RFC 5987 `filename*` with the UTF-8 name, plus an ASCII fallback for old clients.
+pub(crate) fn attachment_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect();
+
+    if fallback == filename {
+        return format!("attachment; filename=\"{fallback}\"");
+    }
+
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+/// Serves files below a root directory for a `*filepath` route
+pub(crate) struct ServeDir {
+    root: PathBuf,
+}
+
+impl ServeDir {
+    pub(crate) fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+#[async_trait]
+impl Handler for ServeDir {
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        let requested = ctx.get_param("filepath").map_or("", String::as_str);
+        match resolve_within(&self.root, requested).await {
+            Ok(path) => serve_file(&path).await,
+            Err(PathRejection::Forbidden) => ResponseBuilder::forbidden(),
+            Err(PathRejection::NotFound) => ResponseBuilder::not_found(),
+        }
+    }
+}
+
+/// Answer unrouted GETs from a single-page app's build directory
+///
+/// Runs after routing: only requests the router answered with
+/// [`Unmatched::NotFound`](crate::Unmatched::NotFound) are looked at, so every
+/// registered route wins. A file below `root` is served as is, malformed paths
+/// get `403 Forbidden`, and anything else asking for `text/html` gets
+/// `index` so the app's client-side router can take over. Other requests keep
+/// their `404`.
+pub(crate) fn spa_fallback(
+    root: PathBuf,
+    index: PathBuf,
+) -> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    let paths = Arc::new((root, index));
+    move |ctx, next| {
+        let paths = paths.clone();
+        Box::pin(async move {
+            let is_get = matches!(*ctx.method(), hyper::Method::GET | hyper::Method::HEAD);
+            let path = ctx.path().to_string();
+            let accepts_html = ctx.accepted_types().iter().any(|(media_type, quality)| {
+                *quality > 0.0 && (media_type == "text/html" || media_type == "text/*")
+            });
+
+            let response = next(ctx).await;
+            if !is_get || response.extensions().get::<Unmatched>() != Some(&Unmatched::NotFound) {
+                return response;
+            }
+            let (root, index) = &*paths;
+            match resolve_within(root, &path).await {
+                Ok(file) if file.is_file() => serve_file(&file).await,
+                Err(PathRejection::Forbidden) => ResponseBuilder::forbidden(),
+                _ if accepts_html => serve_file(&root.join(index)).await,
+                _ => response,
+            }
+        })
+    }
+}
+
+/// `Cache-Control` for assets whose names change with their content
+const IMMUTABLE_CACHE: &str = "public, max-age=31536000, immutable";
+
+/// Serves one fingerprinted file listed in a static manifest
+pub(crate) struct StaticAsset {
+    path: PathBuf,
+}
+
+impl StaticAsset {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl Handler for StaticAsset {
+    async fn handle(&self, _ctx: RequestCtx) -> Response {
+        let mut response = serve_file(&self.path).await;
+        if response.status().is_success() {
+            response.headers_mut().insert(
+                hyper::header::CACHE_CONTROL,
+                hyper::header::HeaderValue::from_static(IMMUTABLE_CACHE),
+            );
+        }
+        response
+    }
+}
+
+/// Read a JSON manifest mapping URL paths to files, relative to the manifest
+///
+/// Every listed file must exist.
+pub(crate) fn read_manifest(manifest: &Path) -> std::io::Result<Vec<(String, PathBuf)>> {
+    let contents = std::fs::read_to_string(manifest)?;
+    let entries: std::collections::BTreeMap<String, String> = serde_json::from_str(&contents)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    let base = manifest.parent().unwrap_or(Path::new(""));
+    entries
+        .into_iter()
+        .map(|(url, file)| {
+            let path = base.join(file);
+            if !path.is_file() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("asset {} for {url} not found", path.display()),
+                ));
+            }
+            let url = format!("/{}", url.trim_start_matches('/'));
+            Ok((url, path))
+        })
+        .collect()
+}
+
+/// Stream the file at `path`, or the `index.html` inside it for a directory
+pub(crate) async fn serve_file(path: &Path) -> Response {
+    let path = if path.is_dir() {
+        path.join("index.html")
+    } else {
+        path.to_path_buf()
+    };
+    let Ok(file) = tokio::fs::File::open(&path).await else {
+        return ResponseBuilder::not_found();
+    };
+    let Ok(metadata) = file.metadata().await else {
+        return ResponseBuilder::internal_error();
+    };
+
+    hyper::Response::builder()
+        .header(hyper::header::CONTENT_TYPE, content_type_for(&path))
+        .header(hyper::header::CONTENT_LENGTH, metadata.len())
+        .body(FileBody::new(file).boxed())
+        .unwrap()
+}
+
+/// Why a requested path can't be served
+#[derive(Debug, PartialEq)]
+pub(crate) enum PathRejection {
+    /// Nothing exists at the requested path, or it lies outside the root
+    NotFound,
+    /// The path is malformed
+    Forbidden,
+}
+
+/// Resolve a URL path below `root`, refusing anything that escapes it
+///
+/// The path is percent-decoded and backslashes are treated as separators, so
+/// `..%2f` and `..\` tricks are normalized before the check. A `..` climbing
+/// above the root is refused before the filesystem is touched. The result is
+/// canonicalized, which also resolves symlinks, and must stay inside the
+/// canonicalized root. Paths outside the root are reported as
+/// [`PathRejection::NotFound`], so they can't be used to probe which files
+/// exist elsewhere on the host.
+pub(crate) async fn resolve_within(root: &Path, requested: &str) -> Result<PathBuf, PathRejection> {
+    let decoded = percent_decode(requested).ok_or(PathRejection::Forbidden)?;
+    if decoded.contains('\0') {
+        return Err(PathRejection::Forbidden);
+    }
+
+    let mut relative = PathBuf::new();
+    for segment in decoded.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if !relative.pop() {
+                    return Err(PathRejection::NotFound);
+                }
+            }
+            segment => relative.push(segment),
+        }
+    }
+    if relative.is_absolute()
+        || relative
+            .components()
+            .any(|c| matches!(c, std::path::Component::Prefix(_)))
+    {
+        return Err(PathRejection::Forbidden);
+    }
+
+    let root = tokio::fs::canonicalize(root)
+        .await
+        .map_err(|_| PathRejection::NotFound)?;
+    let resolved = tokio::fs::canonicalize(root.join(relative))
+        .await
+        .map_err(|_| PathRejection::NotFound)?;
+    if resolved.starts_with(&root) {
+        Ok(resolved)
+    } else {
+        Err(PathRejection::NotFound)
+    }
+}
+
+/// Decode `%XX` escapes, returning `None` for bad escapes or non-UTF-8 results
+pub(crate) fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachment_disposition() {
+        assert_eq!(
+            attachment_disposition("report.pdf"),
+            "attachment; filename=\"report.pdf\""
+        );
+        assert_eq!(
+            attachment_disposition("say \"hi\".txt"),
+            "attachment; filename=\"say _hi_.txt\"; filename*=UTF-8''say%20%22hi%22.txt"
+        );
+    }
+
+    #[test]
+    fn test_content_type_for() {
+        assert_eq!(content_type_for(Path::new("a/b.PNG")), "image/png");
+        assert_eq!(
+            content_type_for(Path::new("archive")),
+            "application/octet-stream"
+        );
+    }
+
+    /// A `public` root inside a scratch directory that also holds `secret.txt`
+    fn scratch_root(name: &str) -> (PathBuf, PathBuf) {
+        let base = std::env::temp_dir().join(format!("ree-static-{name}-{}", std::process::id()));
+        let root = base.join("public");
+        std::fs::create_dir_all(root.join("css")).unwrap();
+        std::fs::write(root.join("css/site.css"), "body {}").unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+        (base, root)
+    }
+
+    #[tokio::test]
+    async fn test_resolve_within_rejects_traversal() {
+        let (base, root) = scratch_root("traversal");
+
+        let css = resolve_within(&root, "css/site.css").await.unwrap();
+        assert!(css.ends_with("public/css/site.css"));
+        assert!(resolve_within(&root, "css%2Fsite.css").await.is_ok());
+        assert!(resolve_within(&root, "css/../css/./site.css").await.is_ok());
+
+        for attempt in [
+            "../secret.txt",
+            "..%2fsecret.txt",
+            "..%2Fsecret.txt",
+            "..\\secret.txt",
+            "css\\..\\..\\secret.txt",
+        ] {
+            assert_eq!(
+                resolve_within(&root, attempt).await,
+                Err(PathRejection::NotFound),
+                "{attempt}"
+            );
+        }
+        assert_eq!(
+            resolve_within(&root, "%zz").await,
+            Err(PathRejection::Forbidden)
+        );
+        assert_eq!(
+            resolve_within(&root, "missing.css").await,
+            Err(PathRejection::NotFound)
+        );
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_resolve_within_rejects_escaping_symlink() {
+        let (base, root) = scratch_root("symlink");
+        std::os::unix::fs::symlink(base.join("secret.txt"), root.join("leak.txt")).unwrap();
+        std::os::unix::fs::symlink(root.join("css/site.css"), root.join("alias.css")).unwrap();
+
+        assert_eq!(
+            resolve_within(&root, "leak.txt").await,
+            Err(PathRejection::NotFound)
+        );
+        assert!(resolve_within(&root, "alias.css").await.is_ok());
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_serve_dir_handler() {
+        let (base, root) = scratch_root("handler");
+        let handler = ServeDir::new(root);
+        let serve = async |filepath: &str| {
+            let params = [("filepath".to_string(), filepath.to_string())].into();
+            let ctx = RequestCtx::new(hyper::Request::get("/").body("").unwrap(), params);
+            handler.handle(ctx).await
+        };
+
+        let response = serve("css/site.css").await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/css; charset=utf-8"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "body {}");
+
+        assert_eq!(
+            serve("nope.css").await.status(),
+            hyper::StatusCode::NOT_FOUND
+        );
+        // Files outside the root look missing whether or not they exist
+        for outside in ["..%2fsecret.txt", "../etc/passwd", "../nonexistent"] {
+            assert_eq!(
+                serve(outside).await.status(),
+                hyper::StatusCode::NOT_FOUND,
+                "{outside}"
+            );
+        }
+        assert_eq!(serve("%zz").await.status(), hyper::StatusCode::FORBIDDEN);
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+}
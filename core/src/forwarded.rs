@@ -0,0 +1,225 @@
+//! Client details as reported by reverse proxies.
+//!
+//! Behind a proxy the connection comes from the proxy, so the original client
+//! address, scheme and host have to be read from the headers it adds: the
+//! standard `Forwarded` header (RFC 7239) or the de-facto `X-Forwarded-For`,
+//! `X-Forwarded-Proto` and `X-Forwarded-Host`. `Forwarded` is preferred when
+//! both are present.
+//!
+//! These headers are set by whoever sent the request, so only rely on them
+//! when every request reaches the server through a proxy that overwrites them.
+
+use std::net::{IpAddr, SocketAddr};
+
+use crate::RequestCtx;
+
+/// One element of a `Forwarded` header, i.e. one proxy hop
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ForwardedElement {
+    /// The client the hop received the request from (`for=`)
+    pub for_: Option<String>,
+    /// The interface the hop received the request on (`by=`)
+    pub by: Option<String>,
+    /// The `Host` the hop received (`host=`)
+    pub host: Option<String>,
+    /// The scheme the hop received the request over (`proto=`)
+    pub proto: Option<String>,
+}
+
+/// Parse a `Forwarded` header value into its elements
+///
+/// Elements are separated by `,` and their parameters by `;`. Quoted values
+/// are unquoted, parameter names are case-insensitive and unknown ones are
+/// ignored.
+pub fn parse_forwarded(value: &str) -> Vec<ForwardedElement> {
+    split_unquoted(value, ',')
+        .into_iter()
+        .map(|element| {
+            let mut parsed = ForwardedElement::default();
+            for pair in split_unquoted(element, ';') {
+                let Some((name, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value)
+                    .replace("\\\"", "\"");
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "for" => parsed.for_ = Some(value),
+                    "by" => parsed.by = Some(value),
+                    "host" => parsed.host = Some(value),
+                    "proto" => parsed.proto = Some(value.to_ascii_lowercase()),
+                    _ => {}
+                }
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// Split on `separator` outside of double quotes
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ if c == separator && !quoted => {
+                parts.push(value[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(value[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// The IP of a `for=` node or `X-Forwarded-For` entry, ignoring any port
+///
+/// Obfuscated identifiers such as `_hidden` and `unknown` yield `None`.
+fn node_ip(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+impl RequestCtx {
+    /// The elements of the `Forwarded` header, nearest-to-client first
+    ///
+    /// Several `Forwarded` headers are read as one list, in order.
+    pub fn forwarded(&self) -> Vec<ForwardedElement> {
+        self.request
+            .headers()
+            .get_all(hyper::header::FORWARDED)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(parse_forwarded)
+            .collect()
+    }
+
+    /// The address of the peer the connection came from, when served by the engine
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.request.extensions().get::<SocketAddr>().copied()
+    }
+
+    /// The IP of the original client
+    ///
+    /// The first `for=` of `Forwarded`, else the first `X-Forwarded-For` entry,
+    /// else the connection's peer address.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        let forwarded = self.forwarded();
+        if let Some(node) = forwarded.iter().find_map(|element| element.for_.as_deref()) {
+            return node_ip(node);
+        }
+        if let Some(list) = self.header("x-forwarded-for")
+            && let Some(ip) = list.split(',').next().and_then(node_ip)
+        {
+            return Some(ip);
+        }
+        self.remote_addr().map(|addr| addr.ip())
+    }
+
+    /// The scheme the client used, `http` unless a proxy reports otherwise
+    pub fn scheme(&self) -> String {
+        let forwarded = self.forwarded();
+        if let Some(proto) = forwarded.into_iter().find_map(|element| element.proto) {
+            return proto;
+        }
+        self.header("x-forwarded-proto")
+            .and_then(|list| list.split(',').next())
+            .map(|proto| proto.trim().to_ascii_lowercase())
+            .filter(|proto| !proto.is_empty())
+            .unwrap_or_else(|| "http".to_string())
+    }
+
+    /// The host the client asked for, as reported by a proxy or the `Host` header
+    pub fn host(&self) -> Option<String> {
+        let forwarded = self.forwarded();
+        if let Some(host) = forwarded.into_iter().find_map(|element| element.host) {
+            return Some(host);
+        }
+        self.header("x-forwarded-host")
+            .and_then(|list| list.split(',').next())
+            .or_else(|| self.header("host"))
+            .or_else(|| self.request.uri().host())
+            .map(|host| host.trim().to_string())
+            .filter(|host| !host.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_headers(headers: &[(&str, &str)]) -> RequestCtx {
+        let mut request = hyper::Request::get("/");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        RequestCtx::new(request.body("").unwrap(), Default::default())
+    }
+
+    #[test]
+    fn test_parse_multiple_elements() {
+        let elements = parse_forwarded(
+            r#"for="[2001:db8:cafe::17]:4711";proto=HTTPS;host="example.com", for=198.51.100.17;by=_proxy"#,
+        );
+        assert_eq!(
+            elements,
+            [
+                ForwardedElement {
+                    for_: Some("[2001:db8:cafe::17]:4711".to_string()),
+                    by: None,
+                    host: Some("example.com".to_string()),
+                    proto: Some("https".to_string()),
+                },
+                ForwardedElement {
+                    for_: Some("198.51.100.17".to_string()),
+                    by: Some("_proxy".to_string()),
+                    ..ForwardedElement::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forwarded_preferred_over_x_forwarded() {
+        let ctx = with_headers(&[
+            ("Forwarded", "for=192.0.2.60;proto=https;host=shop.example"),
+            ("Forwarded", "for=10.0.0.1"),
+            ("X-Forwarded-For", "203.0.113.9"),
+            ("X-Forwarded-Proto", "http"),
+            ("Host", "internal:8080"),
+        ]);
+        assert_eq!(ctx.forwarded().len(), 2);
+        assert_eq!(ctx.client_ip(), Some("192.0.2.60".parse().unwrap()));
+        assert_eq!(ctx.scheme(), "https");
+        assert_eq!(ctx.host().as_deref(), Some("shop.example"));
+    }
+
+    #[test]
+    fn test_x_forwarded_fallbacks() {
+        let ctx = with_headers(&[
+            ("X-Forwarded-For", "203.0.113.9, 10.0.0.1"),
+            ("X-Forwarded-Proto", "https"),
+            ("Host", "internal:8080"),
+        ]);
+        assert_eq!(ctx.client_ip(), Some("203.0.113.9".parse().unwrap()));
+        assert_eq!(ctx.scheme(), "https");
+        assert_eq!(ctx.host().as_deref(), Some("internal:8080"));
+
+        let ctx = with_headers(&[]);
+        assert_eq!(ctx.client_ip(), None);
+        assert_eq!(ctx.scheme(), "http");
+    }
+}
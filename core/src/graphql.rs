@@ -0,0 +1,120 @@
+//! GraphQL integration for `async-graphql` schemas (requires the `graphql` feature).
+
+use async_graphql::{ObjectType, Schema, SubscriptionType, http::GraphiQLSource};
+
+use crate::{Engine, Handler, RequestCtx, Response, ResponseBuilder};
+
+impl Engine {
+    /// Mount a GraphQL schema at `path`
+    ///
+    /// `GET` requests read the operation from the query string
+    /// (`query`, `operationName`, `variables`), `POST` requests from a JSON body.
+    pub fn graphql<Q, M, S>(&mut self, path: &str, schema: Schema<Q, M, S>) -> &mut Self
+    where
+        Q: ObjectType + 'static,
+        M: ObjectType + 'static,
+        S: SubscriptionType + 'static,
+    {
+        self.add_route("GET", path, graphql_endpoint(schema.clone()));
+        self.add_route("POST", path, graphql_endpoint(schema));
+        self
+    }
+
+    /// Serve a GraphiQL page at `path` that talks to the GraphQL `endpoint`
+    pub fn graphiql(&mut self, path: &str, endpoint: &str) -> &mut Self {
+        let html = GraphiQLSource::build().endpoint(endpoint).finish();
+        self.get(path, move |_ctx: RequestCtx| {
+            let html = html.clone();
+            async move { ResponseBuilder::html(html) }
+        })
+    }
+}
+
+/// Build a handler executing GraphQL requests against `schema`
+fn graphql_endpoint<Q, M, S>(schema: Schema<Q, M, S>) -> impl Handler
+where
+    Q: ObjectType + 'static,
+    M: ObjectType + 'static,
+    S: SubscriptionType + 'static,
+{
+    move |ctx: RequestCtx| {
+        let schema = schema.clone();
+        async move {
+            let request = if ctx.request.method() == hyper::Method::GET {
+                async_graphql::http::parse_query_string(ctx.request.uri().query().unwrap_or(""))
+                    .map_err(|err| err.to_string())
+            } else {
+                ctx.json::<async_graphql::Request>()
+                    .map_err(|err| err.to_string())
+            };
+
+            let request = match request {
+                Ok(request) => request,
+                Err(err) => return bad_request(err),
+            };
+
+            match serde_json::to_string(&schema.execute(request).await) {
+                Ok(json) => ResponseBuilder::new()
+                    .content_type("application/json; charset=utf-8")
+                    .body(json),
+                Err(_) => ResponseBuilder::internal_error(),
+            }
+        }
+    }
+}
+
+fn bad_request(message: String) -> Response {
+    ResponseBuilder::new()
+        .status(hyper::StatusCode::BAD_REQUEST)
+        .content_type("application/json; charset=utf-8")
+        .body(serde_json::json!({ "errors": [{ "message": message }] }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::{EmptyMutation, EmptySubscription, Object};
+    use http_body_util::BodyExt;
+    use hyper::body::Bytes;
+    use serde_json::{Value, json};
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn add(&self, a: i32, b: i32) -> i32 {
+            a + b
+        }
+    }
+
+    async fn execute(method: &str, uri: &str, body: &str) -> Value {
+        let schema = Schema::new(Query, EmptyMutation, EmptySubscription);
+        let (parts, _) = hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap()
+            .into_parts();
+        let ctx = RequestCtx::from_parts(parts, Bytes::from(body.to_string()));
+        let response = graphql_endpoint(schema).handle(ctx).await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_post_query() {
+        let result = execute("POST", "/graphql", r#"{"query": "{ add(a: 1, b: 2) }"}"#).await;
+        assert_eq!(result, json!({ "data": { "add": 3 } }));
+    }
+
+    #[tokio::test]
+    async fn test_get_query() {
+        let result = execute(
+            "GET",
+            "/graphql?query=%7B%20add(a%3A%202%2C%20b%3A%203)%20%7D",
+            "",
+        )
+        .await;
+        assert_eq!(result, json!({ "data": { "add": 5 } }));
+    }
+}
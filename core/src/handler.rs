@@ -1,13 +1,24 @@
 //! Handler trait and implementations for request processing.
 
 use async_trait::async_trait;
-use std::future::Future;
-use crate::{RequestCtx, Response, response::IntoResponse};
+use http_body_util::BodyExt;
+use hyper::{HeaderMap, StatusCode, body::Bytes, header::HeaderValue};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use crate::{RequestCtx, Response, ResponseBuilder, response::IntoResponse};
 
 /// Trait for handling HTTP requests
 #[async_trait]
 pub trait Handler: Send + Sync + 'static {
     async fn handle(&self, ctx: RequestCtx) -> Response;
+
+    /// Whether the engine should leave the request body unread, see [`streaming`]
+    fn streams_body(&self) -> bool {
+        false
+    }
 }
 
 /// Implement Handler for async functions that return IntoResponse types (包括 Response)
@@ -21,4 +32,904 @@ where
     async fn handle(&self, ctx: RequestCtx) -> Response {
         (self)(ctx).await.into_response()
     }
-}
\ No newline at end of file
+}
+
+/// A handler reading the request body itself, see [`streaming`]
+pub struct Streaming<H>(H);
+
+/// Wrap a handler so the engine hands it the request body unread
+///
+/// Normal handlers get the whole body buffered in [`RequestCtx::body`]. A streaming
+/// handler sees `body` as `None` and consumes the body from the connection with
+/// helpers like [`RequestCtx::save_body_to_file`], so uploads never sit in memory.
+///
+/// ```rust
+/// use ree::{Engine, IntoResponse, RequestCtx, ResponseBuilder, streaming};
+///
+/// let mut app = Engine::new();
+/// app.post("/upload", streaming(|mut ctx: RequestCtx| async move {
+///     match ctx.save_body_to_file("/tmp/upload.bin", 10 * 1024 * 1024).await {
+///         Ok(written) => format!("stored {written} bytes").into_response(),
+///         Err(_) => ResponseBuilder::bad_request(),
+///     }
+/// }));
+/// ```
+pub fn streaming<H: Handler>(handler: H) -> Streaming<H> {
+    Streaming(handler)
+}
+
+#[async_trait]
+impl<H: Handler> Handler for Streaming<H> {
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        self.0.handle(ctx).await
+    }
+
+    fn streams_body(&self) -> bool {
+        true
+    }
+}
+
+/// A handler choosing between two others per request, see [`when`]
+pub struct When<P, T, O> {
+    predicate: P,
+    then: T,
+    otherwise: O,
+}
+
+/// Dispatch to `then` when `predicate` holds for the request, else to `otherwise`
+///
+/// Useful for A/B routing and gradual rollouts on a single route, e.g. by a
+/// feature flag or a header. The predicate runs before the body is read by
+/// either handler; if only one of them is [`streaming`], the body is buffered.
+///
+/// ```rust
+/// use ree::{Engine, RequestCtx, when};
+///
+/// let mut app = Engine::new();
+/// app.get("/search", when(
+///     |ctx: &RequestCtx| ctx.header("x-beta").is_some(),
+///     |_ctx: RequestCtx| async { "new search" },
+///     |_ctx: RequestCtx| async { "old search" },
+/// ));
+/// ```
+pub fn when<P, T, O>(predicate: P, then: T, otherwise: O) -> When<P, T, O>
+where
+    P: Fn(&RequestCtx) -> bool + Send + Sync + 'static,
+    T: Handler,
+    O: Handler,
+{
+    When {
+        predicate,
+        then,
+        otherwise,
+    }
+}
+
+#[async_trait]
+impl<P, T, O> Handler for When<P, T, O>
+where
+    P: Fn(&RequestCtx) -> bool + Send + Sync + 'static,
+    T: Handler,
+    O: Handler,
+{
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        if (self.predicate)(&ctx) {
+            self.then.handle(ctx).await
+        } else {
+            self.otherwise.handle(ctx).await
+        }
+    }
+
+    fn streams_body(&self) -> bool {
+        self.then.streams_body() && self.otherwise.streams_body()
+    }
+}
+
+/// A handler seeing the request body through a transform, see [`map_body`]
+pub struct MapBody<H, F> {
+    handler: H,
+    transform: F,
+}
+
+/// Pass the request body through `transform` before `handler` reads it
+///
+/// The body is buffered, transformed and put back with [`RequestCtx::set_body`],
+/// so the handler can use the usual body helpers on the result. Useful for
+/// normalizing webhooks, e.g. decrypting a payload or rewriting its format.
+///
+/// ```rust
+/// use ree::{Engine, RequestCtx, map_body};
+///
+/// let mut app = Engine::new();
+/// app.post("/hooks", map_body(
+///     |ctx: RequestCtx| async move { ctx.body_string().unwrap_or_default().unwrap_or_default() },
+///     |body| body.trim_ascii().to_vec().into(),
+/// ));
+/// ```
+pub fn map_body<H, F>(handler: H, transform: F) -> MapBody<H, F>
+where
+    H: Handler,
+    F: Fn(Bytes) -> Bytes + Send + Sync + 'static,
+{
+    MapBody { handler, transform }
+}
+
+#[async_trait]
+impl<H, F> Handler for MapBody<H, F>
+where
+    H: Handler,
+    F: Fn(Bytes) -> Bytes + Send + Sync + 'static,
+{
+    async fn handle(&self, mut ctx: RequestCtx) -> Response {
+        let body = ctx.body.take().unwrap_or_default();
+        ctx.set_body((self.transform)(body));
+        self.handler.handle(ctx).await
+    }
+}
+
+/// A handler whose response body passes through a transform, see [`rewrite_body`]
+pub struct RewriteBody<H, F> {
+    handler: H,
+    rewrite: F,
+}
+
+/// Pass the body of `handler`'s responses through `rewrite` before they're sent
+///
+/// The body is buffered, handed to `rewrite` with the response for its status
+/// and headers, and `Content-Length` is set to the new length. Only textual
+/// responses are rewritten: `text/*`, JSON, XML and JavaScript. Others, and
+/// responses with a `Content-Encoding`, pass through untouched and unbuffered.
+///
+/// ```rust
+/// use ree::{Engine, ResponseBuilder, rewrite_body};
+///
+/// let mut app = Engine::new();
+/// app.get("/", rewrite_body(
+///     |_| async { ResponseBuilder::html("<body>home</body>") },
+///     |body, _response| {
+///         let html = String::from_utf8_lossy(&body);
+///         html.replace("</body>", "<script src=\"/stats.js\"></script></body>").into()
+///     },
+/// ));
+/// ```
+pub fn rewrite_body<H, F>(handler: H, rewrite: F) -> RewriteBody<H, F>
+where
+    H: Handler,
+    F: Fn(Bytes, &Response) -> Bytes + Send + Sync + 'static,
+{
+    RewriteBody { handler, rewrite }
+}
+
+/// Whether a response's body is text that [`rewrite_body`] may change
+fn is_rewritable(response: &Response) -> bool {
+    let headers = response.headers();
+    if headers.contains_key(hyper::header::CONTENT_ENCODING) {
+        return false;
+    }
+    let Some(content_type) = headers
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    media_type.starts_with("text/")
+        || media_type.ends_with("+json")
+        || media_type.ends_with("+xml")
+        || matches!(
+            media_type.as_str(),
+            "application/json" | "application/xml" | "application/javascript"
+        )
+}
+
+#[async_trait]
+impl<H, F> Handler for RewriteBody<H, F>
+where
+    H: Handler,
+    F: Fn(Bytes, &Response) -> Bytes + Send + Sync + 'static,
+{
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        let mut response = self.handler.handle(ctx).await;
+        if !is_rewritable(&response) {
+            return response;
+        }
+        let body = std::mem::replace(
+            response.body_mut(),
+            ResponseBuilder::new().empty_body().into_body(),
+        );
+        let Ok(body) = body.collect().await.map(|body| body.to_bytes()) else {
+            return ResponseBuilder::internal_error();
+        };
+        let body = (self.rewrite)(body, &response);
+        response
+            .headers_mut()
+            .insert(hyper::header::CONTENT_LENGTH, body.len().into());
+        *response.body_mut() = ResponseBuilder::new().body(body).into_body();
+        response
+    }
+
+    fn streams_body(&self) -> bool {
+        self.handler.streams_body()
+    }
+}
+
+/// A handler that always returns the same response, see [`respond_with`]
+#[derive(Clone, Debug)]
+pub struct FixedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+/// Build a handler answering every request with `status` and `body`
+///
+/// Handy for stubs, mocks and placeholder routes. The handler is `Clone`,
+/// so the same response can be registered under several routes.
+///
+/// ```rust
+/// use ree::{Engine, StatusCode, respond_with};
+///
+/// let teapot = respond_with(StatusCode::IM_A_TEAPOT, "I'm a teapot")
+///     .header("Content-Type", "text/plain; charset=utf-8");
+///
+/// let mut app = Engine::new();
+/// app.get("/coffee", teapot.clone());
+/// app.post("/coffee", teapot);
+/// ```
+pub fn respond_with(status: StatusCode, body: impl Into<Bytes>) -> FixedResponse {
+    FixedResponse {
+        status,
+        headers: HeaderMap::new(),
+        body: body.into(),
+    }
+}
+
+impl FixedResponse {
+    /// Add a header to the fixed response; invalid names or values are ignored
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        if let (Ok(name), Ok(value)) = (
+            key.parse::<hyper::header::HeaderName>(),
+            HeaderValue::from_str(value),
+        ) {
+            self.headers.append(name, value);
+        }
+        self
+    }
+}
+
+#[async_trait]
+impl Handler for FixedResponse {
+    async fn handle(&self, _ctx: RequestCtx) -> Response {
+        let mut response = ResponseBuilder::new()
+            .status(self.status)
+            .body(self.body.clone());
+        response.headers_mut().extend(self.headers.clone());
+        response
+    }
+}
+
+/// A handler choosing between inner handlers by the request's `Accept`, see [`negotiate`]
+#[derive(Default)]
+pub struct Negotiate {
+    variants: Vec<(&'static str, Box<dyn Handler>)>,
+}
+
+/// Dispatch to a different handler depending on the media type the client accepts
+///
+/// Each variant is weighed by the `q` of the most specific `Accept` range
+/// covering it (`text/html` over `text/*` over `*/*`); the heaviest wins, and ties
+/// go to the variant registered first. Without an `Accept` header the first variant
+/// is used. Requests accepting none of them get `406 Not Acceptable`.
+///
+/// ```rust
+/// use ree::{Engine, RequestCtx, ResponseBuilder, negotiate};
+///
+/// let mut app = Engine::new();
+/// app.get("/report", negotiate()
+///     .json(|_ctx: RequestCtx| async {
+///         ResponseBuilder::new().content_type("application/json").body(r#"{"total": 3}"#)
+///     })
+///     .html(|_ctx: RequestCtx| async { ResponseBuilder::html("<p>Total: 3</p>") }));
+/// ```
+pub fn negotiate() -> Negotiate {
+    Negotiate::default()
+}
+
+impl Negotiate {
+    /// Serve `media_type` (e.g. `text/csv`) with `handler`
+    pub fn on<H: Handler>(mut self, media_type: &'static str, handler: H) -> Self {
+        self.variants.push((media_type, Box::new(handler)));
+        self
+    }
+
+    /// Serve `application/json` with `handler`
+    pub fn json<H: Handler>(self, handler: H) -> Self {
+        self.on("application/json", handler)
+    }
+
+    /// Serve `text/html` with `handler`
+    pub fn html<H: Handler>(self, handler: H) -> Self {
+        self.on("text/html", handler)
+    }
+
+    /// Serve `text/plain` with `handler`
+    pub fn text<H: Handler>(self, handler: H) -> Self {
+        self.on("text/plain", handler)
+    }
+
+    /// The handler for the best variant, if the client accepts any
+    fn choose(&self, ctx: &RequestCtx) -> Option<&dyn Handler> {
+        let accepted = ctx.accepted_types();
+        if accepted.is_empty() {
+            return self.variants.first().map(|(_, handler)| handler.as_ref());
+        }
+
+        let mut best: Option<(f32, &dyn Handler)> = None;
+        for (media_type, handler) in &self.variants {
+            let main_type = media_type.split('/').next().unwrap_or("");
+            // (specificity, q) of the ranges covering this variant
+            let quality = accepted
+                .iter()
+                .filter_map(|(range, q)| match range.split_once('/') {
+                    _ if range == media_type => Some((2, *q)),
+                    Some((main, "*")) if main == main_type => Some((1, *q)),
+                    Some(("*", "*")) => Some((0, *q)),
+                    _ => None,
+                })
+                .max_by_key(|(specificity, _)| *specificity)
+                .map_or(0.0, |(_, q)| q);
+            if quality > 0.0 && best.is_none_or(|(best, _)| quality > best) {
+                best = Some((quality, handler.as_ref()));
+            }
+        }
+        best.map(|(_, handler)| handler)
+    }
+}
+
+#[async_trait]
+impl Handler for Negotiate {
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        match self.choose(&ctx) {
+            Some(handler) => handler.handle(ctx).await,
+            None => ResponseBuilder::error_for(&ctx, StatusCode::NOT_ACCEPTABLE),
+        }
+    }
+}
+
+/// When a [`circuit_breaker`] trips and how long it stays open
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures that open the breaker
+    pub failure_threshold: usize,
+    /// How long the open breaker fast-fails before letting a trial request through
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum BreakerState {
+    Closed {
+        failures: usize,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A trial request is running after the cooldown
+    HalfOpen,
+}
+
+/// A handler guarded by a circuit breaker, see [`circuit_breaker`]
+#[derive(Clone)]
+pub struct CircuitBreaker<H> {
+    handler: Arc<H>,
+    config: CircuitBreakerConfig,
+    state: Arc<Mutex<BreakerState>>,
+}
+
+/// Stop calling a failing handler for a while
+///
+/// Responses with a `5xx` status, including those of handlers returning `Err`,
+/// count as failures. After `failure_threshold` of them in a row the breaker
+/// opens: requests get `503 Service Unavailable` with `Retry-After` without
+/// reaching the handler. Once `cooldown` has passed one trial request is let
+/// through; its success closes the breaker again, a failure re-opens it.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use ree::{CircuitBreakerConfig, Engine, RequestCtx, circuit_breaker};
+///
+/// let config = CircuitBreakerConfig { failure_threshold: 3, cooldown: Duration::from_secs(10) };
+/// let mut app = Engine::new();
+/// app.get("/rates", circuit_breaker(|_ctx: RequestCtx| async {
+///     // call the flaky upstream here
+///     Err::<String, _>(std::io::Error::other("upstream timed out"))
+/// }, config));
+/// ```
+pub fn circuit_breaker<H: Handler>(handler: H, config: CircuitBreakerConfig) -> CircuitBreaker<H> {
+    CircuitBreaker {
+        handler: Arc::new(handler),
+        config,
+        state: Arc::new(Mutex::new(BreakerState::Closed { failures: 0 })),
+    }
+}
+
+/// Hands the half-open trial on if its request is dropped before finishing
+struct TrialGuard<'a> {
+    state: &'a Mutex<BreakerState>,
+    armed: bool,
+}
+
+impl Drop for TrialGuard<'_> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if let BreakerState::HalfOpen = *state {
+            // No verdict on the upstream, so the next request becomes the trial
+            *state = BreakerState::Open {
+                until: Instant::now(),
+            };
+        }
+    }
+}
+
+impl<H> CircuitBreaker<H> {
+    /// Whether the request may reach the handler, `true` for the half-open trial,
+    /// or how long until it may
+    fn admit(&self) -> Result<bool, Duration> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            BreakerState::Closed { .. } => Ok(false),
+            BreakerState::Open { until } => {
+                let now = Instant::now();
+                if now < until {
+                    return Err(until - now);
+                }
+                *state = BreakerState::HalfOpen;
+                Ok(true)
+            }
+            BreakerState::HalfOpen => Err(self.config.cooldown),
+        }
+    }
+
+    /// Record the outcome of a request that reached the handler
+    fn record(&self, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        *state = match (&*state, failed) {
+            (_, false) => BreakerState::Closed { failures: 0 },
+            (BreakerState::Closed { failures }, true)
+                if failures + 1 < self.config.failure_threshold =>
+            {
+                BreakerState::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (_, true) => BreakerState::Open {
+                until: Instant::now() + self.config.cooldown,
+            },
+        };
+    }
+}
+
+#[async_trait]
+impl<H: Handler> Handler for CircuitBreaker<H> {
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        let trial = match self.admit() {
+            Ok(trial) => trial,
+            Err(retry_after) => {
+                let mut response =
+                    ResponseBuilder::error_for(&ctx, StatusCode::SERVICE_UNAVAILABLE);
+                // Round up so clients never retry before the breaker half-opens
+                let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                response
+                    .headers_mut()
+                    .insert(hyper::header::RETRY_AFTER, secs.into());
+                return response;
+            }
+        };
+        let mut guard = TrialGuard {
+            state: &self.state,
+            armed: trial,
+        };
+        let response = self.handler.handle(ctx).await;
+        guard.armed = false;
+        self.record(response.status().is_server_error());
+        response
+    }
+
+    fn streams_body(&self) -> bool {
+        self.handler.streams_body()
+    }
+}
+
+/// A handler allowing a fixed number of concurrent runs, see [`limit_concurrency`]
+pub struct ConcurrencyLimit<H> {
+    handler: H,
+    permits: Arc<tokio::sync::Semaphore>,
+    status: StatusCode,
+}
+
+/// Let at most `max` requests run `handler` at once
+///
+/// Requests arriving while `max` are in flight are shed straight away with
+/// `503 Service Unavailable`, or the status given to [`ConcurrencyLimit::status`];
+/// they don't queue. The limit is per wrapped handler, so other routes are
+/// unaffected however busy this one is.
+///
+/// ```rust
+/// use ree::{Engine, RequestCtx, StatusCode, limit_concurrency};
+///
+/// let mut app = Engine::new();
+/// app.get("/report", limit_concurrency(|_ctx: RequestCtx| async {
+///     "an expensive report"
+/// }, 2).status(StatusCode::TOO_MANY_REQUESTS));
+/// ```
+pub fn limit_concurrency<H: Handler>(handler: H, max: usize) -> ConcurrencyLimit<H> {
+    ConcurrencyLimit {
+        handler,
+        permits: Arc::new(tokio::sync::Semaphore::new(max)),
+        status: StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+impl<H> ConcurrencyLimit<H> {
+    /// Answer shed requests with `status`, e.g. `429 Too Many Requests`
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+#[async_trait]
+impl<H: Handler> Handler for ConcurrencyLimit<H> {
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        let Ok(_permit) = self.permits.try_acquire() else {
+            return ResponseBuilder::error_for(&ctx, self.status);
+        };
+        self.handler.handle(ctx).await
+    }
+
+    fn streams_body(&self) -> bool {
+        self.handler.streams_body()
+    }
+}
+
+/// The `503` sent for a request that ran past its time limit
+pub(crate) fn timed_out() -> Response {
+    ResponseBuilder::json(
+        StatusCode::SERVICE_UNAVAILABLE,
+        r#"{"error":"Request timed out","status":503}"#,
+    )
+}
+
+/// A handler answering `503` when the wrapped one runs too long, see [`timeout`]
+pub struct Timeout<H> {
+    handler: H,
+    limit: Duration,
+}
+
+/// Give `handler` at most `limit` to produce a response
+///
+/// Past the limit the request gets `503 Service Unavailable` with the JSON body
+/// `{"error":"Request timed out","status":503}`. The handler's future is
+/// dropped then: it stops at the `.await` it was waiting on and never resumes,
+/// releasing guards such as a `MutexGuard` on the way, so shared state should
+/// only be written in steps that are complete on their own. For a limit on
+/// every route see [`Engine::request_timeout`](crate::Engine::request_timeout).
+///
+/// ```rust
+/// use std::time::Duration;
+/// use ree::{Engine, RequestCtx, timeout};
+///
+/// let mut app = Engine::new();
+/// app.get("/report", timeout(|_ctx: RequestCtx| async {
+///     "a slow report"
+/// }, Duration::from_secs(30)));
+/// ```
+pub fn timeout<H: Handler>(handler: H, limit: Duration) -> Timeout<H> {
+    Timeout { handler, limit }
+}
+
+#[async_trait]
+impl<H: Handler> Handler for Timeout<H> {
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        tokio::time::timeout(self.limit, self.handler.handle(ctx))
+            .await
+            .unwrap_or_else(|_| timed_out())
+    }
+
+    fn streams_body(&self) -> bool {
+        self.handler.streams_body()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+
+    #[tokio::test]
+    async fn test_timeout_drops_slow_handler() {
+        let finished = Arc::new(Mutex::new(0));
+        let slow = timeout(
+            {
+                let finished = finished.clone();
+                move |_ctx: RequestCtx| {
+                    let finished = finished.clone();
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        *finished.lock().unwrap() += 1;
+                        "done"
+                    }
+                }
+            },
+            Duration::from_millis(20),
+        );
+
+        let ctx = RequestCtx::new(
+            hyper::Request::get("/").body("").unwrap(),
+            Default::default(),
+        );
+        let response = slow.handle(ctx).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let content_type = response.headers()["content-type"].to_str().unwrap();
+        assert!(content_type.starts_with("application/json"));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"{"error":"Request timed out","status":503}"#);
+
+        // The timed out handler never resumes past its sleep
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(*finished.lock().unwrap(), 0);
+
+        let fast = timeout(
+            |_ctx: RequestCtx| async { "fast" },
+            Duration::from_millis(200),
+        );
+        let ctx = RequestCtx::new(
+            hyper::Request::get("/").body("").unwrap(),
+            Default::default(),
+        );
+        assert_eq!(fast.handle(ctx).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_respond_with_teapot() {
+        let teapot = respond_with(StatusCode::IM_A_TEAPOT, "I'm a teapot").header("X-Brew", "tea");
+
+        let mut router = Router::new();
+        router.add_route("GET", "/coffee", Box::new(teapot.clone()));
+        router.add_route("GET", "/espresso", Box::new(teapot));
+
+        for path in ["/coffee", "/espresso"] {
+            let request = hyper::Request::get(path).body("").unwrap();
+            let ctx = RequestCtx::new(request, Default::default());
+            let response = router.handle_request(ctx).await;
+            assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+            assert_eq!(response.headers()["X-Brew"], "tea");
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, "I'm a teapot");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_when_dispatches_on_header() {
+        let variant = when(
+            |ctx: &RequestCtx| ctx.header("x-variant") == Some("b"),
+            |_ctx: RequestCtx| async { "variant b" },
+            |_ctx: RequestCtx| async { "variant a" },
+        );
+        assert!(!variant.streams_body());
+
+        let cases = [
+            (Some("b"), "variant b"),
+            (Some("a"), "variant a"),
+            (None, "variant a"),
+        ];
+        for (header, expected) in cases {
+            let mut request = hyper::Request::get("/");
+            if let Some(header) = header {
+                request = request.header("X-Variant", header);
+            }
+            let ctx = RequestCtx::new(request.body("").unwrap(), Default::default());
+            let response = variant.handle(ctx).await;
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_map_body_transforms_before_handler() {
+        let shout = map_body(
+            |ctx: RequestCtx| async move {
+                let length = ctx.header("content-length").unwrap_or_default().to_string();
+                format!("{} ({length})", ctx.body_string().unwrap().unwrap())
+            },
+            |body| [body.to_ascii_uppercase().as_slice(), b"!"].concat().into(),
+        );
+
+        let request = hyper::Request::post("/hooks")
+            .header("Content-Length", "5")
+            .body("hello")
+            .unwrap();
+        let ctx = RequestCtx::new(request, Default::default());
+        let response = shout.handle(ctx).await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "HELLO! (6)");
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_body_appends_to_html() {
+        let inject = |body: Bytes, _: &Response| -> Bytes {
+            [body.as_ref(), b"<script src=\"/stats.js\"></script>"]
+                .concat()
+                .into()
+        };
+        let page = rewrite_body(|_| async { ResponseBuilder::html("<p>home</p>") }, inject);
+        let ctx = RequestCtx::new(
+            hyper::Request::get("/").body("").unwrap(),
+            Default::default(),
+        );
+        let response = page.handle(ctx).await;
+        assert_eq!(response.headers()["content-length"], "44");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "<p>home</p><script src=\"/stats.js\"></script>");
+
+        let image = rewrite_body(
+            |_| async { ResponseBuilder::new().content_type("image/png").body("PNG") },
+            inject,
+        );
+        let ctx = RequestCtx::new(
+            hyper::Request::get("/").body("").unwrap(),
+            Default::default(),
+        );
+        let response = image.handle(ctx).await;
+        assert!(!response.headers().contains_key("content-length"));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "PNG");
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_trips_and_recovers() {
+        let healthy = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let config = CircuitBreakerConfig {
+            failure_threshold: 2,
+            cooldown: Duration::from_millis(50),
+        };
+        let breaker = circuit_breaker(
+            {
+                let (healthy, calls) = (healthy.clone(), calls.clone());
+                move |_ctx: RequestCtx| {
+                    calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    let healthy = healthy.load(std::sync::atomic::Ordering::SeqCst);
+                    async move {
+                        if healthy {
+                            Ok("fresh rates")
+                        } else {
+                            Err(std::io::Error::other("upstream down"))
+                        }
+                    }
+                }
+            },
+            config,
+        );
+        let call = async || {
+            let request = hyper::Request::get("/rates").body("").unwrap();
+            let ctx = RequestCtx::new(request, Default::default());
+            breaker.handle(ctx).await
+        };
+        let calls = || calls.load(std::sync::atomic::Ordering::SeqCst);
+
+        // Two failures trip the breaker, after which the handler isn't called
+        assert_eq!(call().await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(call().await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let response = call().await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "1");
+        assert_eq!(calls(), 2);
+
+        // A failing trial after the cooldown opens it again
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(call().await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(call().await.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(calls(), 3);
+
+        // Once the upstream recovers a successful trial closes it
+        healthy.store(true, std::sync::atomic::Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(call().await.status(), StatusCode::OK);
+        assert_eq!(call().await.status(), StatusCode::OK);
+        assert_eq!(calls(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_dropped_trial_hands_trial_on() {
+        let hang = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let config = CircuitBreakerConfig {
+            failure_threshold: 1,
+            cooldown: Duration::from_millis(50),
+        };
+        let breaker = circuit_breaker(
+            {
+                let hang = hang.clone();
+                move |_ctx: RequestCtx| {
+                    let hang = hang.load(std::sync::atomic::Ordering::SeqCst);
+                    async move {
+                        if hang {
+                            std::future::pending::<()>().await;
+                        }
+                        Err::<&str, _>(std::io::Error::other("upstream down"))
+                    }
+                }
+            },
+            config,
+        );
+        let call = async || {
+            let request = hyper::Request::get("/rates").body("").unwrap();
+            let ctx = RequestCtx::new(request, Default::default());
+            breaker.handle(ctx).await
+        };
+
+        assert_eq!(call().await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        // The client of the trial request goes away mid-request
+        hang.store(true, std::sync::atomic::Ordering::SeqCst);
+        let trial = tokio::time::timeout(Duration::from_millis(20), call());
+        assert!(trial.await.is_err());
+
+        // The next request gets to be the trial instead of a 503
+        hang.store(false, std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(call().await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(call().await.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_negotiate_by_accept() {
+        let handler = negotiate()
+            .json(|_ctx: RequestCtx| async { "handler_a" })
+            .html(|_ctx: RequestCtx| async { "handler_b" });
+        let call = async |accept: Option<&str>| {
+            let mut request = hyper::Request::get("/report");
+            if let Some(accept) = accept {
+                request = request.header("Accept", accept);
+            }
+            let ctx = RequestCtx::new(request.body("").unwrap(), Default::default());
+            let response = handler.handle(ctx).await;
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (status, body)
+        };
+
+        assert_eq!(call(Some("application/json")).await.1, "handler_a");
+        assert_eq!(
+            call(Some("text/html,application/xhtml+xml,*/*;q=0.8"))
+                .await
+                .1,
+            "handler_b"
+        );
+        assert_eq!(
+            call(Some("text/*;q=0.5, application/json;q=0.4")).await.1,
+            "handler_b"
+        );
+        assert_eq!(call(Some("*/*")).await.1, "handler_a");
+        assert_eq!(call(None).await.1, "handler_a");
+
+        let (status, _) = call(Some("image/png, text/html;q=0")).await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    }
+}
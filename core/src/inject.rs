@@ -0,0 +1,53 @@
+//! Services shared with handlers by type.
+//!
+//! Services are registered once on the engine with
+//! [`Engine::provide`](crate::Engine::provide) and resolved per request with
+//! [`RequestCtx::inject`]. They're keyed by the type they are provided as, so a
+//! service behind a trait object is resolved through the trait and handlers
+//! don't depend on the concrete implementation. Plain application state, set
+//! with [`Engine::with_state`](crate::Engine::with_state) and read with
+//! [`RequestCtx::state`], is stored the same way.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::Arc,
+};
+
+use crate::RequestCtx;
+
+/// The provided services, each an `Arc<T>` keyed by `T`
+#[derive(Default)]
+pub(crate) struct Services(HashMap<TypeId, Box<dyn Any + Send + Sync>>);
+
+impl Services {
+    /// Register `service` as the `T`, replacing any previous one
+    pub(crate) fn insert<T: ?Sized + Send + Sync + 'static>(&mut self, service: Arc<T>) {
+        self.0.insert(TypeId::of::<T>(), Box::new(service));
+    }
+
+    /// The service provided as a `T`
+    pub(crate) fn get<T: ?Sized + Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.0
+            .get(&TypeId::of::<T>())?
+            .downcast_ref::<Arc<T>>()
+            .cloned()
+    }
+}
+
+impl RequestCtx {
+    /// The service the engine provides as a `T`, see [`Engine::provide`](crate::Engine::provide)
+    ///
+    /// `T` is the type given when providing, e.g. `dyn UserStore` for an
+    /// `Arc<dyn UserStore>`. `None` when nothing was provided as a `T`.
+    pub fn inject<T: ?Sized + Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.request.extensions().get::<Arc<Services>>()?.get::<T>()
+    }
+
+    /// The application state of type `T`, see [`Engine::with_state`](crate::Engine::with_state)
+    ///
+    /// `None` when the engine has no state of this type.
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.inject::<T>()
+    }
+}
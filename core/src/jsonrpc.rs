@@ -0,0 +1,262 @@
+//! JSON-RPC 2.0 dispatch over a single POST endpoint.
+//!
+//! ```rust,no_run
+//! use ree::{Engine, JsonRpcError, JsonRpcRouter};
+//! use serde_json::json;
+//!
+//! let mut rpc = JsonRpcRouter::new();
+//! rpc.register("add", |params| async move {
+//!     let a = params[0].as_i64().ok_or_else(|| JsonRpcError::invalid_params("expected numbers"))?;
+//!     let b = params[1].as_i64().ok_or_else(|| JsonRpcError::invalid_params("expected numbers"))?;
+//!     Ok(json!(a + b))
+//! });
+//!
+//! let mut app = Engine::new();
+//! app.post("/rpc", rpc);
+//! ```
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use serde_json::{Value, json};
+
+use crate::{Handler, RequestCtx, Response, ResponseBuilder, response::IntoResponse};
+
+/// A JSON-RPC 2.0 error object
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    /// Create an error with an application-defined code
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Attach additional data to the error
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// Invalid JSON was received (-32700)
+    pub fn parse_error() -> Self {
+        Self::new(-32700, "Parse error")
+    }
+
+    /// The JSON sent is not a valid request object (-32600)
+    pub fn invalid_request() -> Self {
+        Self::new(-32600, "Invalid Request")
+    }
+
+    /// The method does not exist (-32601)
+    pub fn method_not_found() -> Self {
+        Self::new(-32601, "Method not found")
+    }
+
+    /// Invalid method parameters (-32602)
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(-32602, message)
+    }
+
+    /// Internal JSON-RPC error (-32603)
+    pub fn internal_error(message: impl Into<String>) -> Self {
+        Self::new(-32603, message)
+    }
+
+    fn to_value(&self) -> Value {
+        let mut error = json!({
+            "code": self.code,
+            "message": self.message,
+        });
+        if let Some(data) = &self.data {
+            error["data"] = data.clone();
+        }
+        error
+    }
+}
+
+type RpcMethod = Arc<
+    dyn Fn(Value) -> Pin<Box<dyn Future<Output = Result<Value, JsonRpcError>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// A set of named JSON-RPC methods served from one endpoint
+#[derive(Default)]
+pub struct JsonRpcRouter {
+    methods: HashMap<String, RpcMethod>,
+}
+
+impl JsonRpcRouter {
+    /// Create an empty JSON-RPC router
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a method; it receives `params` (or `null` when omitted)
+    pub fn register<F, Fut>(&mut self, name: &str, method: F) -> &mut Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, JsonRpcError>> + Send + 'static,
+    {
+        let method: RpcMethod = Arc::new(move |params| Box::pin(method(params)));
+        self.methods.insert(name.to_string(), method);
+        self
+    }
+
+    /// Process a single request object, returning `None` for notifications
+    async fn call(&self, request: Value) -> Option<Value> {
+        let Some(object) = request.as_object() else {
+            return Some(error_response(Value::Null, JsonRpcError::invalid_request()));
+        };
+
+        // A request without an "id" member is a notification
+        let id = object.get("id").cloned();
+        let valid_id = matches!(
+            id,
+            None | Some(Value::Null) | Some(Value::String(_)) | Some(Value::Number(_))
+        );
+        let method = object.get("method").and_then(Value::as_str);
+        let params = object.get("params").cloned().unwrap_or(Value::Null);
+
+        if object.get("jsonrpc").and_then(Value::as_str) != Some("2.0")
+            || !valid_id
+            || method.is_none()
+            || !matches!(params, Value::Null | Value::Array(_) | Value::Object(_))
+        {
+            return Some(error_response(
+                id.unwrap_or(Value::Null),
+                JsonRpcError::invalid_request(),
+            ));
+        }
+
+        let result = match self.methods.get(method.unwrap()) {
+            Some(method) => method(params).await,
+            None => Err(JsonRpcError::method_not_found()),
+        };
+
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+            Err(err) => error_response(id, err),
+        })
+    }
+}
+
+fn error_response(id: Value, error: JsonRpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "error": error.to_value(), "id": id })
+}
+
+#[async_trait]
+impl Handler for JsonRpcRouter {
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        let payload = match ctx
+            .body_bytes()
+            .map(|body| serde_json::from_slice::<Value>(body))
+        {
+            Some(Ok(payload)) => payload,
+            _ => return error_response(Value::Null, JsonRpcError::parse_error()).into_response(),
+        };
+
+        match payload {
+            Value::Array(batch) if batch.is_empty() => {
+                error_response(Value::Null, JsonRpcError::invalid_request()).into_response()
+            }
+            Value::Array(batch) => {
+                let mut responses = Vec::with_capacity(batch.len());
+                for request in batch {
+                    responses.extend(self.call(request).await);
+                }
+                if responses.is_empty() {
+                    ResponseBuilder::no_content()
+                } else {
+                    Value::Array(responses).into_response()
+                }
+            }
+            request => match self.call(request).await {
+                Some(response) => response.into_response(),
+                None => ResponseBuilder::no_content(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use hyper::body::Bytes;
+
+    fn rpc() -> JsonRpcRouter {
+        let mut rpc = JsonRpcRouter::new();
+        rpc.register("add", |params| async move {
+            match (params[0].as_i64(), params[1].as_i64()) {
+                (Some(a), Some(b)) => Ok(json!(a + b)),
+                _ => Err(JsonRpcError::invalid_params("expected two integers")),
+            }
+        });
+        rpc
+    }
+
+    async fn post(rpc: &JsonRpcRouter, body: Value) -> Value {
+        let (parts, _) = hyper::Request::builder()
+            .method("POST")
+            .uri("/rpc")
+            .body(())
+            .unwrap()
+            .into_parts();
+        let ctx = RequestCtx::from_parts(parts, Bytes::from(body.to_string()));
+        let response = rpc.handle(ctx).await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_single_call() {
+        let response = post(
+            &rpc(),
+            json!({"jsonrpc": "2.0", "method": "add", "params": [1, 2], "id": 1}),
+        )
+        .await;
+        assert_eq!(response, json!({"jsonrpc": "2.0", "result": 3, "id": 1}));
+    }
+
+    #[tokio::test]
+    async fn test_batch_skips_notifications() {
+        let response = post(
+            &rpc(),
+            json!([
+                {"jsonrpc": "2.0", "method": "add", "params": [1, 1], "id": "a"},
+                {"jsonrpc": "2.0", "method": "add", "params": [2, 2]},
+                {"jsonrpc": "2.0", "method": "add", "params": [3, 3], "id": "b"}
+            ]),
+        )
+        .await;
+        assert_eq!(
+            response,
+            json!([
+                {"jsonrpc": "2.0", "result": 2, "id": "a"},
+                {"jsonrpc": "2.0", "result": 6, "id": "b"}
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_method_not_found() {
+        let response = post(
+            &rpc(),
+            json!({"jsonrpc": "2.0", "method": "subtract", "params": [1, 2], "id": 7}),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], -32601);
+        assert_eq!(response["id"], 7);
+    }
+}
@@ -2,43 +2,151 @@
 // Internal Module Declarations
 // =============================================================================
 
+mod builtin;
+mod cache_control;
+mod clock;
 mod context;
+mod cookie;
+mod cors;
+mod egress;
 mod engine;
+mod error;
+mod files;
+mod forwarded;
+#[cfg(feature = "graphql")]
+mod graphql;
 mod handler;
+mod inject;
+mod jsonrpc;
 mod middleware;
+mod multipart;
+mod panic;
+mod proxy;
+mod query;
+mod range;
+mod request_id;
 mod response;
 mod router;
+mod session;
+mod sse;
 mod swagger;
-mod trie;
+mod testing;
+mod timing;
+#[cfg(feature = "tls")]
+mod tls;
+mod trace;
+mod websocket;
 
 // =============================================================================
 // Internal System Imports (not exposed to users)
 // =============================================================================
 
 // These are used internally by the framework
-use middleware::{execute_chain, Middleware};
-use router::Router;
+use middleware::{Middleware, execute_chain};
+
 // =============================================================================
 // Public API Exports
 // =============================================================================
 
-pub use context::RequestCtx;
+pub use context::{BodyError, JsonLines, LogContext, ParamError, RequestCtx};
+
 /// Core framework components
-pub use engine::Engine;
+pub use engine::{BoundEngine, Engine, RouteTable, RouterGroup};
+
+/// Streaming multipart uploads
+pub use multipart::{MultipartField, MultipartLimits, MultipartSummary, SavedFile};
+
+/// Query string and form decoding
+pub use query::{DuplicateKeys, QueryError};
 
 /// Handler trait for request processing
-pub use handler::Handler;
+pub use handler::{
+    CircuitBreaker, CircuitBreakerConfig, ConcurrencyLimit, FixedResponse, Handler, MapBody,
+    Negotiate, RewriteBody, Streaming, Timeout, When, circuit_breaker, limit_concurrency, map_body,
+    negotiate, respond_with, rewrite_body, streaming, timeout, when,
+};
 
 /// Response handling
-pub use response::{IntoResponse, Response, ResponseBuilder};
+pub use response::{IntoResponse, Response, ResponseBuilder, ResponseExt, response_set_header};
+
+/// OpenAPI route metadata
+pub use swagger::RouteDoc;
+
+/// Mapping handler errors to status codes
+pub use error::{
+    BodyRejection, ErrorMapper, FieldError, JsonRejection, PROBLEM_JSON, Problem, Rejection,
+    Validate, ValidationErrors, register_error_mapper,
+};
+
+/// Request details for panic handlers
+pub use panic::ErrorContext;
 
 /// Middleware system
 pub use middleware::{IntoNext, Next};
 
+/// Cookie sessions
+pub use session::{
+    MemoryStore, SESSION_COOKIE, Session, SessionData, SessionStore, session, session_with_store,
+};
+
+/// Server-sent event streams
+pub use sse::{Sse, SseEvent};
+
+/// Reverse proxying
+pub use proxy::{Proxy, proxy};
+
+/// Reverse proxy headers
+pub use forwarded::{ForwardedElement, parse_forwarded};
+
+/// `Range` header parsing
+pub use range::{RangeError, parse_range};
+
+/// Cross-origin resource sharing
+pub use cors::Cors;
+
+/// `Cache-Control` header parsing
+pub use cache_control::{CacheControl, parse_cache_control};
+
+/// In-process requests for tests
+pub use testing::{RecordedResponse, TestClient, TestRequest};
+
+/// Request ids carried into spawned tasks
+pub use request_id::{REQUEST_ID_HEADER, RequestId, current_request_id, request_id};
+
+/// `Server-Timing` reporting
+pub use timing::server_timing;
+
+/// Response bytes sent per request
+pub use egress::BytesSent;
+
+/// Time source, replaceable in tests
+pub use clock::{Clock, ManualClock};
+
+/// W3C trace context propagation
+pub use trace::{TRACEPARENT_HEADER, TRACESTATE_HEADER, TraceContext, current_trace_context};
+
+/// Built-in middleware
+pub use builtin::{
+    NormalizePath, default_json_content_type, logger, logger_with, max_path_depth, normalize_path,
+    require_content_type, single_flight,
+};
+
+/// WebSocket handshakes
+pub use websocket::{WebSocketRejection, WebSocketUpgrade};
+
+/// JSON-RPC 2.0 support
+pub use jsonrpc::{JsonRpcError, JsonRpcRouter};
+
 // =============================================================================
 // Advanced/Internal API Exports
 // =============================================================================
 
+/// Route table, see [`Engine::build_router`]
+pub use router::{RouteMatch, Router, TrailingSlash, Unmatched};
+
+// Routing trie, usable on its own for custom dispatch
+pub mod trie;
+
 // =============================================================================
 // Convenient Re-exports
 // =============================================================================
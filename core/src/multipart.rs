@@ -0,0 +1,232 @@
+//! Streaming `multipart/form-data` uploads to disk.
+
+use std::path::PathBuf;
+
+use http_body_util::BodyDataStream;
+use multer::{Constraints, Multipart, SizeLimit};
+use tokio::io::AsyncWriteExt;
+
+use crate::{BodyError, RequestCtx};
+
+/// Size caps for [`RequestCtx::multipart_save`], in bytes
+#[derive(Clone, Copy, Debug)]
+pub struct MultipartLimits {
+    /// Largest single part, file or text
+    pub per_file: u64,
+    /// Largest whole body
+    pub total: u64,
+}
+
+impl Default for MultipartLimits {
+    /// 10 MiB per part, 50 MiB overall
+    fn default() -> Self {
+        Self {
+            per_file: 10 * 1024 * 1024,
+            total: 50 * 1024 * 1024,
+        }
+    }
+}
+
+/// A part's headers, offered to the target closure before any data is read
+#[derive(Debug)]
+pub struct MultipartField {
+    /// The form field name
+    pub name: String,
+    /// The client's file name; `None` for plain text fields
+    pub file_name: Option<String>,
+    /// The part's `Content-Type`, if sent
+    pub content_type: Option<String>,
+}
+
+/// A file part written to disk by [`RequestCtx::multipart_save`]
+#[derive(Debug)]
+pub struct SavedFile {
+    /// The form field name
+    pub field: String,
+    /// The client's file name
+    pub file_name: Option<String>,
+    /// Where the part was written
+    pub path: PathBuf,
+    /// Bytes written
+    pub size: u64,
+}
+
+/// Everything [`RequestCtx::multipart_save`] found in the body
+#[derive(Debug, Default)]
+pub struct MultipartSummary {
+    /// Saved file parts, in body order
+    pub files: Vec<SavedFile>,
+    /// Text fields as `(name, value)`, in body order
+    pub fields: Vec<(String, String)>,
+}
+
+impl RequestCtx {
+    /// Stream each file part of a `multipart/form-data` body to its own file
+    ///
+    /// `target` sees every file part's headers and returns where to write it,
+    /// or `None` to skip the part. Parts are copied chunk by chunk, so in a
+    /// [`streaming`](crate::streaming) handler no file is held in memory. Text
+    /// fields are collected into the summary. Exceeding either limit fails with
+    /// [`BodyError::TooLarge`]; on any error the files written so far are removed.
+    pub async fn multipart_save<F>(
+        &mut self,
+        limits: MultipartLimits,
+        mut target: F,
+    ) -> Result<MultipartSummary, BodyError>
+    where
+        F: FnMut(&MultipartField) -> Option<PathBuf>,
+    {
+        let content_type = self.header("content-type").unwrap_or("");
+        let boundary = multer::parse_boundary(content_type).map_err(BodyError::Multipart)?;
+        let body = self.take_body_stream().ok_or(BodyError::Missing)?;
+
+        let constraints = Constraints::new().size_limit(
+            SizeLimit::new()
+                .whole_stream(limits.total)
+                .per_field(limits.per_file),
+        );
+        let mut multipart =
+            Multipart::with_constraints(BodyDataStream::new(body), boundary, constraints);
+
+        let mut summary = MultipartSummary::default();
+        let result = save_parts(&mut multipart, &mut target, &mut summary).await;
+        if let Err(err) = result {
+            for file in &summary.files {
+                let _ = tokio::fs::remove_file(&file.path).await;
+            }
+            return Err(err);
+        }
+        Ok(summary)
+    }
+}
+
+async fn save_parts<F>(
+    multipart: &mut Multipart<'_>,
+    target: &mut F,
+    summary: &mut MultipartSummary,
+) -> Result<(), BodyError>
+where
+    F: FnMut(&MultipartField) -> Option<PathBuf>,
+{
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        let info = MultipartField {
+            name: field.name().unwrap_or_default().to_string(),
+            file_name: field.file_name().map(str::to_string),
+            content_type: field.content_type().map(ToString::to_string),
+        };
+
+        if info.file_name.is_none() {
+            let value = field.text().await.map_err(multipart_error)?;
+            summary.fields.push((info.name, value));
+            continue;
+        }
+        // Skipped parts are drained by the next `next_field` call
+        let Some(path) = target(&info) else {
+            continue;
+        };
+
+        let mut file = tokio::fs::File::create(&path)
+            .await
+            .map_err(BodyError::Io)?;
+        summary.files.push(SavedFile {
+            field: info.name,
+            file_name: info.file_name,
+            path,
+            size: 0,
+        });
+        let saved = summary.files.last_mut().unwrap();
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            file.write_all(&chunk).await.map_err(BodyError::Io)?;
+            saved.size += chunk.len() as u64;
+        }
+        file.flush().await.map_err(BodyError::Io)?;
+    }
+    Ok(())
+}
+
+fn multipart_error(err: multer::Error) -> BodyError {
+    match err {
+        multer::Error::FieldSizeExceeded { limit, .. }
+        | multer::Error::StreamSizeExceeded { limit } => BodyError::TooLarge(limit),
+        err => BodyError::Multipart(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use hyper::body::Bytes;
+
+    const BODY: &str = "--XYZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        Holiday\r\n\
+        --XYZ\r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"beach.jpg\"\r\n\
+        Content-Type: image/jpeg\r\n\r\n\
+        JPEGDATA\r\n\
+        --XYZ\r\n\
+        Content-Disposition: form-data; name=\"notes\"; filename=\"notes.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        remember sunscreen\r\n\
+        --XYZ--\r\n";
+
+    fn upload() -> RequestCtx {
+        let body = http_body_util::Full::new(Bytes::from_static(BODY.as_bytes()))
+            .map_err(|never| match never {})
+            .boxed();
+        let request = hyper::Request::post("/upload")
+            .header("Content-Type", "multipart/form-data; boundary=XYZ")
+            .body(body)
+            .unwrap();
+        RequestCtx::streaming(request)
+    }
+
+    #[tokio::test]
+    async fn test_multipart_save_two_files() {
+        let dir = std::env::temp_dir().join(format!("ree-multipart-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let summary = upload()
+            .multipart_save(MultipartLimits::default(), |field| {
+                Some(dir.join(format!("{}.bin", field.name)))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            summary.fields,
+            [("title".to_string(), "Holiday".to_string())]
+        );
+        assert_eq!(summary.files.len(), 2);
+        assert_eq!(summary.files[0].file_name.as_deref(), Some("beach.jpg"));
+        assert_eq!(summary.files[0].size, 8);
+        assert_eq!(std::fs::read(dir.join("photo.bin")).unwrap(), b"JPEGDATA");
+        assert_eq!(
+            std::fs::read(dir.join("notes.bin")).unwrap(),
+            b"remember sunscreen"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_multipart_save_limits_clean_up() {
+        let dir = std::env::temp_dir().join(format!("ree-multipart-limit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let limits = MultipartLimits {
+            per_file: 10,
+            total: 1024,
+        };
+        let result = upload()
+            .multipart_save(limits, |field| Some(dir.join(&field.name)))
+            .await;
+
+        assert!(matches!(result, Err(BodyError::TooLarge(10))));
+        assert!(!dir.join("photo").exists());
+        assert!(!dir.join("notes").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+//! Turning handler panics into responses.
+//!
+//! A panicking handler or middleware is caught by the engine and answered with
+//! `500 Internal Server Error`, or with the response built by the handler given
+//! to [`Engine::on_panic`](crate::Engine::on_panic). The panic unwinds the
+//! [`RequestCtx`], so what the panic handler needs to know about the request is
+//! recorded as an [`ErrorContext`] in the request's [`crate::LogContext`] before
+//! the handler is invoked.
+
+use std::{
+    any::Any,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::RequestCtx;
+
+/// What is known about a request when its handler panics
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub method: String,
+    pub path: String,
+    /// Pattern of the matched route, `None` if the panic came before routing
+    pub route_pattern: Option<String>,
+    /// Id assigned by the [`request_id`](crate::request_id) middleware
+    pub request_id: Option<String>,
+}
+
+impl ErrorContext {
+    /// Record the request as it is now in its log context
+    pub(crate) fn record(ctx: &RequestCtx) {
+        ctx.log_context().insert(ErrorContext {
+            method: ctx.method().to_string(),
+            path: ctx.path().to_string(),
+            route_pattern: ctx.route_pattern().map(str::to_string),
+            request_id: ctx.request_id().map(str::to_string),
+        });
+    }
+}
+
+/// The message a panic was raised with, for `panic!("...")` style payloads
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "<non-string panic payload>".to_string()
+    }
+}
+
+/// A future resolving to `Err` with the panic payload if polling it panics
+pub(crate) struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> CatchUnwind<F> {
+    pub(crate) fn new(future: F) -> Self {
+        Self(Box::pin(future))
+    }
+}
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.0.as_mut();
+        match std::panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_catch_unwind_returns_payload() {
+        assert_eq!(CatchUnwind::new(async { 7 }).await.unwrap(), 7);
+
+        let caught = CatchUnwind::new(async {
+            tokio::task::yield_now().await;
+            panic!("boom {}", 42);
+        })
+        .await;
+        assert_eq!(panic_message(caught.unwrap_err().as_ref()), "boom 42");
+    }
+}
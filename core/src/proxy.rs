@@ -0,0 +1,296 @@
+//! Reverse proxying to an upstream HTTP server.
+//!
+//! [`proxy`] forwards requests to another server and relays its response.
+//! Bodies are streamed in both directions: the request body goes to the
+//! upstream as it arrives from the client and the response body goes back as
+//! the upstream sends it, so neither is held in memory and a slow reader on
+//! either side slows the other down instead of filling buffers.
+
+use async_trait::async_trait;
+use http_body_util::BodyExt;
+use hyper_util::{
+    client::legacy::{Client, connect::HttpConnector},
+    rt::TokioExecutor,
+};
+
+use crate::{Handler, RequestCtx, Response, ResponseBuilder, context::BodyStream};
+
+/// Headers describing a single connection, never forwarded (RFC 9110, 7.6.1)
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Remove the headers that only apply to one connection
+///
+/// That is the fixed hop-by-hop headers, every `Proxy-*` header and any header
+/// the `Connection` header lists.
+pub(crate) fn strip_hop_by_hop(headers: &mut hyper::HeaderMap) {
+    let listed: Vec<hyper::header::HeaderName> = headers
+        .get_all(hyper::header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(*name);
+    }
+    let proxy_headers: Vec<hyper::header::HeaderName> = headers
+        .keys()
+        .filter(|name| name.as_str().starts_with("proxy-"))
+        .cloned()
+        .collect();
+    for name in proxy_headers {
+        headers.remove(name);
+    }
+}
+
+/// A handler forwarding requests to an upstream server, see [`proxy`]
+pub struct Proxy {
+    /// `scheme://authority` of the upstream
+    origin: String,
+    /// Path prefix the request path is appended to, without a trailing `/`
+    base_path: String,
+    client: Client<HttpConnector, BodyStream>,
+}
+
+/// Forward requests to `upstream`, e.g. `http://127.0.0.1:9000`
+///
+/// The request path and query are appended to the upstream URL, so
+/// `http://backend/v1` turns `GET /users?page=2` into
+/// `GET http://backend/v1/users?page=2`; combine with
+/// [`RouterGroup::strip_prefix`](crate::RouterGroup::strip_prefix) to drop a
+/// mount prefix. Hop-by-hop headers are removed, the upstream's `Host` is used
+/// and `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` are set.
+/// Unreachable upstreams are answered with `502 Bad Gateway`.
+///
+/// Only plain `http` upstreams are supported.
+///
+/// ```rust
+/// use ree::{Engine, proxy};
+///
+/// let mut app = Engine::new();
+/// app.add_route("GET", "/api/*path", proxy("http://127.0.0.1:9000").unwrap());
+/// app.add_route("POST", "/api/*path", proxy("http://127.0.0.1:9000").unwrap());
+/// ```
+pub fn proxy(upstream: &str) -> Result<Proxy, hyper::http::uri::InvalidUri> {
+    let uri: hyper::Uri = upstream.parse()?;
+    let authority = uri.authority().map_or("", |authority| authority.as_str());
+    let scheme = uri.scheme_str().unwrap_or("http");
+    Ok(Proxy {
+        origin: format!("{scheme}://{authority}"),
+        base_path: uri.path().trim_end_matches('/').to_string(),
+        client: Client::builder(TokioExecutor::new()).build_http(),
+    })
+}
+
+impl Proxy {
+    /// The upstream request for `ctx`, with its body still streaming
+    fn upstream_request(&self, ctx: &mut RequestCtx) -> Option<hyper::Request<BodyStream>> {
+        let path_and_query = ctx
+            .request
+            .uri()
+            .path_and_query()
+            .map_or("/", |path_and_query| path_and_query.as_str());
+        let uri: hyper::Uri = format!("{}{}{}", self.origin, self.base_path, path_and_query)
+            .parse()
+            .ok()?;
+
+        let mut headers = ctx.request.headers().clone();
+        strip_hop_by_hop(&mut headers);
+        headers.remove(hyper::header::HOST);
+        if let Some(ip) = ctx.remote_addr().map(|addr| addr.ip()) {
+            let forwarded_for = match ctx.header("x-forwarded-for") {
+                Some(existing) => format!("{existing}, {ip}"),
+                None => ip.to_string(),
+            };
+            if let Ok(value) = forwarded_for.parse() {
+                headers.insert("x-forwarded-for", value);
+            }
+        }
+        if let Ok(value) = ctx.scheme().parse() {
+            headers.insert("x-forwarded-proto", value);
+        }
+        if let Some(host) = ctx.host()
+            && let Ok(value) = host.parse()
+        {
+            headers.insert("x-forwarded-host", value);
+        }
+
+        let body = ctx.take_body_stream().unwrap_or_else(|| {
+            http_body_util::Empty::new()
+                .map_err(|never| match never {})
+                .boxed()
+        });
+        let mut request = hyper::Request::builder()
+            .method(ctx.request.method().clone())
+            .uri(uri)
+            .body(body)
+            .ok()?;
+        *request.headers_mut() = headers;
+        Some(request)
+    }
+}
+
+#[async_trait]
+impl Handler for Proxy {
+    async fn handle(&self, mut ctx: RequestCtx) -> Response {
+        let Some(request) = self.upstream_request(&mut ctx) else {
+            return ResponseBuilder::error_for(&ctx, hyper::StatusCode::BAD_GATEWAY);
+        };
+        match self.client.request(request).await {
+            Ok(response) => ResponseBuilder::proxy_response(response),
+            Err(err) => {
+                eprintln!("Proxy request to {} failed: {err}", self.origin);
+                ResponseBuilder::error_for(&ctx, hyper::StatusCode::BAD_GATEWAY)
+            }
+        }
+    }
+
+    fn streams_body(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        pin::Pin,
+        sync::{
+            Arc,
+            atomic::{AtomicU64, Ordering},
+        },
+        task::{Context, Poll},
+    };
+
+    use hyper::body::{Body, Bytes, Frame};
+
+    use super::*;
+
+    const CHUNK: usize = 64 * 1024;
+    const CHUNKS: u64 = 1024;
+
+    /// Bytes produced by the client and consumed by the upstream so far
+    #[derive(Default)]
+    struct Progress {
+        produced: AtomicU64,
+        consumed: AtomicU64,
+        max_in_flight: AtomicU64,
+    }
+
+    /// A request body made up on demand, one chunk per poll
+    struct LazyBody {
+        remaining: u64,
+        progress: Arc<Progress>,
+    }
+
+    impl Body for LazyBody {
+        type Data = Bytes;
+        type Error = hyper::Error;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, hyper::Error>>> {
+            if self.remaining == 0 {
+                return Poll::Ready(None);
+            }
+            self.remaining -= 1;
+            let progress = &self.progress;
+            let produced =
+                progress.produced.fetch_add(CHUNK as u64, Ordering::SeqCst) + CHUNK as u64;
+            let in_flight = produced - progress.consumed.load(Ordering::SeqCst);
+            progress
+                .max_in_flight
+                .fetch_max(in_flight, Ordering::SeqCst);
+            Poll::Ready(Some(Ok(Frame::data(Bytes::from(vec![b'x'; CHUNK])))))
+        }
+    }
+
+    /// An upstream reading the body frame by frame and answering with its length
+    async fn counting_upstream(progress: Arc<Progress>) -> std::net::SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = hyper::service::service_fn(
+                move |request: hyper::Request<hyper::body::Incoming>| {
+                    let progress = progress.clone();
+                    async move {
+                        let path = request.uri().to_string();
+                        let mut body = request.into_body();
+                        while let Some(frame) = body.frame().await {
+                            if let Ok(data) = frame.unwrap().into_data() {
+                                progress
+                                    .consumed
+                                    .fetch_add(data.len() as u64, Ordering::SeqCst);
+                                // A slow consumer, so an unbounded proxy would run ahead
+                                tokio::task::yield_now().await;
+                            }
+                        }
+                        let consumed = progress.consumed.load(Ordering::SeqCst);
+                        let reply = format!("{path} {consumed}");
+                        Ok::<_, Infallible>(hyper::Response::new(http_body_util::Full::new(
+                            Bytes::from(reply),
+                        )))
+                    }
+                },
+            );
+            let io = hyper_util::rt::TokioIo::new(stream);
+            let _ = hyper::server::conn::http1::Builder::new()
+                .serve_connection(io, service)
+                .await;
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_proxy_streams_large_body() {
+        let progress = Arc::new(Progress::default());
+        let upstream = counting_upstream(progress.clone()).await;
+        let proxy = proxy(&format!("http://{upstream}/v1")).unwrap();
+        assert!(proxy.streams_body());
+
+        let body = LazyBody {
+            remaining: CHUNKS,
+            progress: progress.clone(),
+        };
+        let request = hyper::Request::post("/upload?name=big")
+            .header("Connection", "keep-alive")
+            .body(body.boxed())
+            .unwrap();
+        let response = proxy.handle(RequestCtx::streaming(request)).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        let reply = response.into_body().collect().await.unwrap().to_bytes();
+
+        let total = CHUNKS * CHUNK as u64;
+        assert_eq!(reply, format!("/v1/upload?name=big {total}"));
+        // 64 MiB went through, but at most what fits in socket and hyper buffers
+        // was ever ahead of the upstream
+        let max_in_flight = progress.max_in_flight.load(Ordering::SeqCst);
+        assert!(max_in_flight <= total / 4, "{max_in_flight} bytes buffered");
+    }
+
+    #[tokio::test]
+    async fn test_proxy_unreachable_upstream() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let proxy = proxy(&format!("http://{addr}")).unwrap();
+        let request = hyper::Request::get("/").body("").unwrap();
+        let response = proxy
+            .handle(RequestCtx::new(request, Default::default()))
+            .await;
+        assert_eq!(response.status(), hyper::StatusCode::BAD_GATEWAY);
+    }
+}
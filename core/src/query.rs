@@ -0,0 +1,398 @@
+//! Query string and urlencoded form decoding with a configurable policy for repeated keys.
+
+use std::collections::HashMap;
+
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor, value::MapDeserializer};
+
+/// Error produced when a query string or form doesn't match the target type
+pub type QueryError = de::value::Error;
+
+/// How repeated keys such as `?x=1&x=2` are decoded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Keep the first occurrence; `Vec` fields receive a single element
+    FirstWins,
+    /// Keep the last occurrence; `Vec` fields receive a single element
+    #[default]
+    LastWins,
+    /// Keep every occurrence; `Vec` fields receive all of them and
+    /// scalar fields reject repeated keys
+    Collect,
+}
+
+/// Decode an urlencoded string (`a=1&b=2`) into `T`
+pub fn from_urlencoded<T: DeserializeOwned>(
+    input: &[u8],
+    policy: DuplicateKeys,
+) -> Result<T, QueryError> {
+    let pairs = form_urlencoded::parse(input).into_owned();
+    from_pairs(pairs, policy)
+}
+
+/// Decode already-split key/value pairs into `T`
+///
+/// Keys using array syntax (`tags[]=a&tags[]=b` or `tags[0]=a&tags[1]=b`)
+/// always collect every value, ordered by index when one is given,
+/// regardless of `policy`.
+pub(crate) fn from_pairs<T: DeserializeOwned>(
+    pairs: impl IntoIterator<Item = (String, String)>,
+    policy: DuplicateKeys,
+) -> Result<T, QueryError> {
+    // Group values by key, preserving first-seen key order
+    let mut grouped: Vec<Group> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (key, value) in pairs {
+        let (key, index) = split_array_key(&key);
+        let position = match positions.get(key) {
+            Some(&position) => position,
+            None => {
+                positions.insert(key.to_string(), grouped.len());
+                grouped.push(Group {
+                    key: key.to_string(),
+                    array: false,
+                    values: Vec::new(),
+                });
+                grouped.len() - 1
+            }
+        };
+        let group = &mut grouped[position];
+        group.array |= index.is_some();
+        group.values.push((index.flatten(), value));
+    }
+
+    let entries = grouped.into_iter().map(|mut group| {
+        if group.array {
+            // Stable, so `[]` entries keep their order; `None` sorts before any index
+            group.values.sort_by_key(|(index, _)| *index);
+        }
+        let values = Values {
+            values: group.values.into_iter().map(|(_, value)| value).collect(),
+            policy: if group.array {
+                DuplicateKeys::Collect
+            } else {
+                policy
+            },
+            array: group.array,
+        };
+        (group.key, values)
+    });
+    T::deserialize(MapDeserializer::new(entries))
+}
+
+/// Values collected for one key while grouping
+struct Group {
+    key: String,
+    array: bool,
+    values: Vec<(Option<usize>, String)>,
+}
+
+/// Split `tags[]` into `("tags", Some(None))` and `tags[2]` into `("tags", Some(Some(2)))`
+fn split_array_key(key: &str) -> (&str, Option<Option<usize>>) {
+    let Some(open) = key.strip_suffix(']').and_then(|rest| rest.rfind('[')) else {
+        return (key, None);
+    };
+    let name = &key[..open];
+    let inner = &key[open + 1..key.len() - 1];
+    if name.is_empty() {
+        return (key, None);
+    }
+    if inner.is_empty() {
+        return (name, Some(None));
+    }
+    match inner.parse() {
+        Ok(index) => (name, Some(Some(index))),
+        Err(_) => (key, None),
+    }
+}
+
+/// All values seen for one key
+struct Values {
+    values: Vec<String>,
+    policy: DuplicateKeys,
+    /// Sent with array syntax, so a sequence even with a single value
+    array: bool,
+}
+
+impl Values {
+    /// The single value a scalar field receives under the policy
+    fn single(mut self) -> Result<Text, QueryError> {
+        let value = match self.policy {
+            DuplicateKeys::FirstWins => self.values.into_iter().next(),
+            DuplicateKeys::LastWins => self.values.pop(),
+            DuplicateKeys::Collect if self.values.len() > 1 => {
+                return Err(de::Error::custom(
+                    "expected a single value for a repeated key",
+                ));
+            }
+            DuplicateKeys::Collect => self.values.pop(),
+        };
+        Ok(Text(value.unwrap_or_default()))
+    }
+
+    /// The values a sequence field receives under the policy
+    fn sequence(mut self) -> Vec<Text> {
+        let values = match self.policy {
+            DuplicateKeys::FirstWins => self.values.into_iter().take(1).collect(),
+            DuplicateKeys::LastWins => self.values.pop().into_iter().collect(),
+            DuplicateKeys::Collect => self.values,
+        };
+        values.into_iter().map(Text).collect()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, QueryError> for Values {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Values {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        if self.array || (self.policy == DuplicateKeys::Collect && self.values.len() > 1) {
+            return self.deserialize_seq(visitor);
+        }
+        self.single()?.deserialize_any(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_seq(de::value::SeqDeserializer::new(self.sequence().into_iter()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, QueryError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, QueryError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, QueryError> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_unit
+        deserialize_identifier deserialize_ignored_any deserialize_bytes deserialize_byte_buf
+    }
+
+    serde::forward_to_deserialize_any! {
+        unit_struct tuple_struct map struct
+    }
+}
+
+/// A single decoded value, parsed into primitives on demand
+struct Text(String);
+
+impl<'de> IntoDeserializer<'de, QueryError> for Text {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! parse_primitive {
+    ($($method:ident => $visit:ident: $ty:ty,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+                match self.0.parse::<$ty>() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(err) => Err(de::Error::custom(format!("invalid value {:?}: {err}", self.0))),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Text {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, QueryError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, QueryError> {
+        self.0
+            .into_deserializer()
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    parse_primitive! {
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+        deserialize_char => visit_char: char,
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Scalar {
+        x: u32,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Many {
+        x: Vec<u32>,
+    }
+
+    #[test]
+    fn test_first_wins() {
+        let scalar: Scalar = from_urlencoded(b"x=1&x=2", DuplicateKeys::FirstWins).unwrap();
+        assert_eq!(scalar, Scalar { x: 1 });
+        let many: Many = from_urlencoded(b"x=1&x=2", DuplicateKeys::FirstWins).unwrap();
+        assert_eq!(many, Many { x: vec![1] });
+    }
+
+    #[test]
+    fn test_last_wins() {
+        let scalar: Scalar = from_urlencoded(b"x=1&x=2", DuplicateKeys::LastWins).unwrap();
+        assert_eq!(scalar, Scalar { x: 2 });
+        let many: Many = from_urlencoded(b"x=1&x=2", DuplicateKeys::LastWins).unwrap();
+        assert_eq!(many, Many { x: vec![2] });
+    }
+
+    #[test]
+    fn test_collect() {
+        let many: Many = from_urlencoded(b"x=1&x=2", DuplicateKeys::Collect).unwrap();
+        assert_eq!(many, Many { x: vec![1, 2] });
+        let single: Many = from_urlencoded(b"x=7", DuplicateKeys::Collect).unwrap();
+        assert_eq!(single, Many { x: vec![7] });
+        assert!(from_urlencoded::<Scalar>(b"x=1&x=2", DuplicateKeys::Collect).is_err());
+    }
+
+    #[test]
+    fn test_decoding_and_optional_fields() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Search {
+            q: String,
+            page: Option<u32>,
+            exact: bool,
+        }
+
+        let search: Search =
+            from_urlencoded(b"q=hello%20world&exact=true", DuplicateKeys::default()).unwrap();
+        assert_eq!(
+            search,
+            Search {
+                q: "hello world".to_string(),
+                page: None,
+                exact: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_bracket_array_syntax() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Tags {
+            tags: Vec<String>,
+            page: u32,
+        }
+
+        let tags: Tags =
+            from_urlencoded(b"tags[]=a&page=2&tags[]=b", DuplicateKeys::LastWins).unwrap();
+        assert_eq!(
+            tags,
+            Tags {
+                tags: vec!["a".to_string(), "b".to_string()],
+                page: 2,
+            }
+        );
+
+        // Percent-encoded brackets, as browsers send them
+        let tags: Tags = from_urlencoded(b"tags%5B%5D=x&page=1", DuplicateKeys::FirstWins).unwrap();
+        assert_eq!(tags.tags, ["x"]);
+    }
+
+    #[test]
+    fn test_indexed_array_syntax() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Tags {
+            tags: Vec<String>,
+        }
+
+        let tags: Tags = from_urlencoded(
+            b"tags[1]=second&tags[0]=first&tags[2]=third",
+            DuplicateKeys::default(),
+        )
+        .unwrap();
+        assert_eq!(tags.tags, ["first", "second", "third"]);
+
+        // Not array syntax: kept as a literal key
+        let map: std::collections::HashMap<String, String> =
+            from_urlencoded(b"filter[name]=x", DuplicateKeys::default()).unwrap();
+        assert_eq!(map["filter[name]"], "x");
+    }
+}
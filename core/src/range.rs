@@ -0,0 +1,165 @@
+//! `Range` request header parsing.
+
+use crate::RequestCtx;
+
+/// Most ranges accepted in one header, to bound the work a request can cause
+const MAX_RANGES: usize = 64;
+
+/// Why a `Range` header can't be honored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeError {
+    /// Not a valid `bytes=` range; the spec says to ignore it and send the whole body
+    Malformed,
+    /// No range overlaps the content; answer `416 Range Not Satisfiable`
+    /// with `Content-Range: bytes */<total_len>`
+    Unsatisfiable,
+}
+
+impl std::fmt::Display for RangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RangeError::Malformed => write!(f, "Malformed Range header"),
+            RangeError::Unsatisfiable => write!(f, "Range not satisfiable"),
+        }
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+/// Resolve a `Range` header value against content of `total_len` bytes
+///
+/// Returns inclusive `(first, last)` byte positions in header order. Open
+/// ranges (`500-`) run to the end, suffix ranges (`-500`) cover the last bytes,
+/// and ranges reaching past the end are cut off there. Ranges starting past the
+/// end are dropped; if that leaves none the range is unsatisfiable.
+pub fn parse_range(header: &str, total_len: u64) -> Result<Vec<(u64, u64)>, RangeError> {
+    let (unit, specs) = header.split_once('=').ok_or(RangeError::Malformed)?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return Err(RangeError::Malformed);
+    }
+
+    let specs: Vec<&str> = specs
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return Err(RangeError::Malformed);
+    }
+
+    let mut ranges = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let (first, last) = spec.split_once('-').ok_or(RangeError::Malformed)?;
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| RangeError::Malformed)
+        };
+        let range = match (first.trim(), last.trim()) {
+            ("", "") => return Err(RangeError::Malformed),
+            ("", suffix) => {
+                let suffix = number(suffix)?;
+                (suffix > 0 && total_len > 0)
+                    .then(|| (total_len.saturating_sub(suffix), total_len - 1))
+            }
+            (first, "") => {
+                let first = number(first)?;
+                (first < total_len).then(|| (first, total_len - 1))
+            }
+            (first, last) => {
+                let (first, last) = (number(first)?, number(last)?);
+                if last < first {
+                    return Err(RangeError::Malformed);
+                }
+                (first < total_len).then(|| (first, last.min(total_len - 1)))
+            }
+        };
+        ranges.extend(range);
+    }
+
+    if ranges.is_empty() {
+        return Err(RangeError::Unsatisfiable);
+    }
+    Ok(ranges)
+}
+
+impl RequestCtx {
+    /// The byte ranges the `Range` header asks for out of `total_len` bytes
+    ///
+    /// Empty when the request has no `Range` header. See [`parse_range`] for how
+    /// ranges are resolved; handlers serving a range answer `206 Partial Content`.
+    pub fn parse_range(&self, total_len: u64) -> Result<Vec<(u64, u64)>, RangeError> {
+        match self.header("range") {
+            Some(header) => parse_range(header, total_len),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suffix_open_and_closed_ranges() {
+        assert_eq!(parse_range("bytes=-500", 10_000), Ok(vec![(9_500, 9_999)]));
+        assert_eq!(parse_range("bytes=-500", 100), Ok(vec![(0, 99)]));
+        assert_eq!(parse_range("bytes=9500-", 10_000), Ok(vec![(9_500, 9_999)]));
+        assert_eq!(parse_range("bytes=0-499", 10_000), Ok(vec![(0, 499)]));
+        assert_eq!(parse_range("bytes=0-99999", 10_000), Ok(vec![(0, 9_999)]));
+    }
+
+    #[test]
+    fn test_multiple_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-49, 100-149,-10", 1_000),
+            Ok(vec![(0, 49), (100, 149), (990, 999)])
+        );
+        // Ranges past the end are dropped as long as one remains
+        assert_eq!(parse_range("bytes=0-9,5000-", 1_000), Ok(vec![(0, 9)]));
+    }
+
+    #[test]
+    fn test_unsatisfiable_and_malformed() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1_000),
+            Err(RangeError::Unsatisfiable)
+        );
+        assert_eq!(
+            parse_range("bytes=-0", 1_000),
+            Err(RangeError::Unsatisfiable)
+        );
+        assert_eq!(parse_range("bytes=0-", 0), Err(RangeError::Unsatisfiable));
+        for header in [
+            "items=0-5",
+            "bytes=",
+            "bytes=5-1",
+            "bytes=a-b",
+            "bytes=-",
+            "0-5",
+        ] {
+            assert_eq!(
+                parse_range(header, 1_000),
+                Err(RangeError::Malformed),
+                "{header}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ctx_parse_range() {
+        let request = hyper::Request::get("/video")
+            .header("Range", "bytes=-500")
+            .body("")
+            .unwrap();
+        let ctx = RequestCtx::new(request, Default::default());
+        assert_eq!(ctx.parse_range(2_000), Ok(vec![(1_500, 1_999)]));
+
+        let ctx = RequestCtx::new(
+            hyper::Request::get("/").body("").unwrap(),
+            Default::default(),
+        );
+        assert_eq!(ctx.parse_range(2_000), Ok(vec![]));
+    }
+}
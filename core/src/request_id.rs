@@ -0,0 +1,151 @@
+//! Request ids that follow a request into the tasks it spawns.
+//!
+//! The [`request_id`] middleware gives every request an id, taken from an
+//! incoming `X-Request-Id` or freshly generated, and echoes it in the response.
+//! While the request is handled the id is available from
+//! [`current_request_id`], also inside tasks started with [`RequestCtx::spawn`],
+//! so log lines written by background work can be matched to their request.
+
+use std::{future::Future, pin::Pin};
+
+use rand::RngCore;
+
+use crate::{Next, RequestCtx, Response};
+
+/// Header the id is read from and echoed in
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest incoming id that is kept rather than replaced
+const MAX_INCOMING_LEN: usize = 128;
+
+/// The id of a request, stored in its extensions by [`request_id`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// The id of the request the current task is working for
+///
+/// Set while the handler runs behind [`request_id`] and inside tasks spawned
+/// with [`RequestCtx::spawn`]; `None` anywhere else.
+pub fn current_request_id() -> Option<String> {
+    CURRENT.try_with(|id| id.0.clone()).ok()
+}
+
+/// 128 random bits as hex
+fn new_request_id() -> String {
+    let mut id = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut id);
+    id.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl RequestCtx {
+    /// The id assigned by the [`request_id`] middleware
+    pub fn request_id(&self) -> Option<&str> {
+        self.request
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.0.as_str())
+    }
+
+    /// Spawn a background task that keeps this request's id and trace
+    ///
+    /// Inside `future`, [`current_request_id`] returns the same id as in the
+    /// handler and [`current_trace_context`](crate::current_trace_context) the
+    /// request's [`trace_context`](RequestCtx::trace_context). The task isn't
+    /// tied to the request and keeps running after the response is sent.
+    pub fn spawn<F>(&self, future: F) -> tokio::task::JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let future = self.trace_context().clone().scope(future);
+        match self.request.extensions().get::<RequestId>() {
+            Some(id) => tokio::spawn(CURRENT.scope(id.clone(), future)),
+            None => tokio::spawn(future),
+        }
+    }
+}
+
+/// Assign each request an id and echo it in the `X-Request-Id` response header
+///
+/// An incoming `X-Request-Id` of up to 128 visible ASCII characters is reused,
+/// so ids set by a proxy in front carry through; otherwise a random one is made.
+pub fn request_id()
+-> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    |mut ctx, next| {
+        Box::pin(async move {
+            let id = ctx
+                .header(REQUEST_ID_HEADER)
+                .filter(|id| {
+                    !id.is_empty()
+                        && id.len() <= MAX_INCOMING_LEN
+                        && id.bytes().all(|byte| byte.is_ascii_graphic())
+                })
+                .map_or_else(new_request_id, str::to_string);
+            let id = RequestId(id);
+            ctx.request.extensions_mut().insert(id.clone());
+            ctx.log_context().insert(id.clone());
+
+            let mut response = CURRENT.scope(id.clone(), next(ctx)).await;
+            if let Ok(value) = hyper::header::HeaderValue::from_str(&id.0) {
+                response.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntoNext;
+
+    #[tokio::test]
+    async fn test_spawned_task_sees_request_id() {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let sender = std::sync::Mutex::new(Some(sender));
+        let endpoint = (move |ctx: RequestCtx| {
+            let sender = sender.lock().unwrap().take().unwrap();
+            async move {
+                let in_handler = current_request_id();
+                ctx.spawn(async move {
+                    let _ = sender.send(current_request_id());
+                });
+                crate::ResponseBuilder::new().body(in_handler.unwrap_or_default())
+            }
+        })
+        .into_next();
+
+        let request = hyper::Request::get("/")
+            .header("X-Request-Id", "req-42")
+            .body("")
+            .unwrap();
+        let response = request_id()(RequestCtx::new(request, Default::default()), endpoint).await;
+        assert_eq!(response.headers()["x-request-id"], "req-42");
+        assert_eq!(receiver.await.unwrap().as_deref(), Some("req-42"));
+        assert_eq!(current_request_id(), None);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_generated_when_missing_or_invalid() {
+        let endpoint = (|ctx: RequestCtx| async move {
+            crate::ResponseBuilder::new().body(ctx.request_id().unwrap().to_string())
+        })
+        .into_next();
+
+        for incoming in [None, Some("has spaces"), Some("")] {
+            let mut request = hyper::Request::get("/");
+            if let Some(incoming) = incoming {
+                request = request.header("X-Request-Id", incoming);
+            }
+            let ctx = RequestCtx::new(request.body("").unwrap(), Default::default());
+            let response = request_id()(ctx, endpoint.clone()).await;
+            let id = response.headers()["x-request-id"].to_str().unwrap();
+            assert_eq!(id.len(), 32);
+        }
+    }
+}
@@ -3,6 +3,8 @@
 use http_body_util::{BodyExt, Empty, Full, combinators::BoxBody};
 use hyper::body::Bytes;
 
+use crate::files::{self, FileBody};
+
 pub type Response = hyper::Response<BoxBody<Bytes, hyper::Error>>;
 
 /// Create a full body from any type that can convert to Bytes
@@ -64,11 +66,103 @@ impl ResponseBuilder {
 
     /// Build an HTML response
     pub fn html<T: Into<Bytes>>(body: T) -> Response {
+        Self::html_with_status(hyper::StatusCode::OK, body)
+    }
+
+    /// Build an HTML response with the given status
+    pub fn html_with_status<T: Into<Bytes>>(status: hyper::StatusCode, body: T) -> Response {
         Self::new()
+            .status(status)
             .content_type("text/html; charset=utf-8")
             .body(body)
     }
 
+    /// Build a plain text response
+    pub fn text<T: Into<Bytes>>(body: T) -> Response {
+        Self::text_with_status(hyper::StatusCode::OK, body)
+    }
+
+    /// Build a plain text response with the given status
+    pub fn text_with_status<T: Into<Bytes>>(status: hyper::StatusCode, body: T) -> Response {
+        Self::new()
+            .status(status)
+            .content_type("text/plain; charset=utf-8")
+            .body(body)
+    }
+
+    /// Build a plain text response with the given status, like [`ResponseBuilder::text_with_status`]
+    pub fn with_status<T: Into<Bytes>>(status: hyper::StatusCode, text: T) -> Response {
+        Self::text_with_status(status, text)
+    }
+
+    /// Build a response from an already serialized JSON body with the given status
+    ///
+    /// `body` is sent as is, so string literals and `format!` output both work.
+    pub fn json<T: Into<Bytes>>(status: hyper::StatusCode, body: T) -> Response {
+        Self::new()
+            .status(status)
+            .content_type("application/json; charset=utf-8")
+            .body(body)
+    }
+
+    /// Build a 401 response with a JSON body
+    pub fn unauthorized_json<T: Into<Bytes>>(body: T) -> Response {
+        Self::json(hyper::StatusCode::UNAUTHORIZED, body)
+    }
+
+    /// Build a 403 response with a JSON body
+    pub fn forbidden_json<T: Into<Bytes>>(body: T) -> Response {
+        Self::json(hyper::StatusCode::FORBIDDEN, body)
+    }
+
+    /// Build a 429 response with a JSON body
+    pub fn too_many_requests_json<T: Into<Bytes>>(body: T) -> Response {
+        Self::json(hyper::StatusCode::TOO_MANY_REQUESTS, body)
+    }
+
+    /// Start an RFC 7807 `application/problem+json` response
+    ///
+    /// Add extension members with [`Problem::extension`](crate::Problem::extension)
+    /// and return it from a handler, or call `into_response`.
+    ///
+    /// ```rust
+    /// use ree::{IntoResponse, ResponseBuilder, StatusCode};
+    ///
+    /// let response = ResponseBuilder::problem(
+    ///     StatusCode::FORBIDDEN,
+    ///     "https://example.com/probs/out-of-credit",
+    ///     "You do not have enough credit.",
+    ///     "Your current balance is 30, but that costs 50.",
+    /// )
+    /// .extension("balance", 30)
+    /// .into_response();
+    /// assert_eq!(response.headers()["content-type"], "application/problem+json");
+    /// ```
+    pub fn problem(
+        status: hyper::StatusCode,
+        type_uri: &str,
+        title: &str,
+        detail: &str,
+    ) -> crate::Problem {
+        crate::Problem::new(status, type_uri, title).detail(detail)
+    }
+
+    /// Build a 400 response
+    pub fn bad_request() -> Response {
+        Self::new()
+            .status(hyper::StatusCode::BAD_REQUEST)
+            .content_type("text/plain; charset=utf-8")
+            .body("400 Bad Request")
+    }
+
+    /// Build a 403 response
+    pub fn forbidden() -> Response {
+        Self::new()
+            .status(hyper::StatusCode::FORBIDDEN)
+            .content_type("text/plain; charset=utf-8")
+            .body("403 Forbidden")
+    }
+
     /// Build a 404 response
     pub fn not_found() -> Response {
         Self::new()
@@ -85,12 +179,89 @@ impl ResponseBuilder {
             .body("500 Internal Server Error")
     }
 
+    /// Build a response streaming the file at `path`
+    ///
+    /// `Content-Type` is guessed from the extension and a directory serves its
+    /// `index.html`. Returns `404` when the file can't be opened. `path` is used
+    /// as given: to serve a path taken from the request, use
+    /// [`Engine::static_files`](crate::Engine::static_files), which keeps it
+    /// inside its root.
+    pub async fn serve_file(path: impl AsRef<std::path::Path>) -> Response {
+        files::serve_file(path.as_ref()).await
+    }
+
+    /// Build a response sending the file at `path` as an attachment named `filename`
+    ///
+    /// The file is streamed rather than read into memory. `Content-Type` is
+    /// inferred from the filename's extension, and names that aren't plain ASCII
+    /// are sent with an RFC 5987 `filename*` parameter. Returns `404` when the
+    /// file can't be opened.
+    pub async fn download(path: impl AsRef<std::path::Path>, filename: &str) -> Response {
+        let Ok(file) = tokio::fs::File::open(path.as_ref()).await else {
+            return Self::not_found();
+        };
+        let Ok(metadata) = file.metadata().await else {
+            return Self::internal_error();
+        };
+
+        Self::new()
+            .content_type(files::content_type_for(std::path::Path::new(filename)))
+            .header("Content-Length", metadata.len().to_string())
+            .header(
+                "Content-Disposition",
+                files::attachment_disposition(filename),
+            )
+            .builder
+            .body(FileBody::new(file).boxed())
+            .unwrap()
+    }
+
+    /// Build an error response in the format the client wants
+    ///
+    /// JSON (`{"error": "Not Found", "status": 404}`) when
+    /// [`RequestCtx::wants_json`](crate::RequestCtx::wants_json), a small HTML page otherwise.
+    /// With [`Engine::problem_details`](crate::Engine::problem_details) always an
+    /// `application/problem+json` body of type `about:blank`.
+    pub fn error_for(ctx: &crate::RequestCtx, status: hyper::StatusCode) -> Response {
+        let reason = status.canonical_reason().unwrap_or("Error");
+        if ctx.problem_details {
+            crate::Problem::new(status, "about:blank", reason).into_response()
+        } else if ctx.wants_json() {
+            let body = serde_json::json!({ "error": reason, "status": status.as_u16() });
+            Self::new()
+                .status(status)
+                .content_type("application/json; charset=utf-8")
+                .body(body.to_string())
+        } else {
+            let title = format!("{} {reason}", status.as_u16());
+            let mut response = Self::html(format!(
+                "<!DOCTYPE html><title>{title}</title><h1>{title}</h1>"
+            ));
+            *response.status_mut() = status;
+            response
+        }
+    }
+
     /// Build a 204 No Content response
     pub fn no_content() -> Response {
         Self::new()
             .status(hyper::StatusCode::NO_CONTENT)
             .empty_body()
     }
+
+    /// Relay a response from an upstream server without its connection headers
+    ///
+    /// Removes `Connection`, `Keep-Alive`, `Transfer-Encoding`, `TE`, `Trailer`,
+    /// `Upgrade`, every `Proxy-*` header and any header named in the upstream's
+    /// `Connection` header. Status, body and end-to-end headers pass through.
+    pub fn proxy_response<B>(upstream: hyper::Response<B>) -> Response
+    where
+        B: hyper::body::Body<Data = Bytes, Error = hyper::Error> + Send + Sync + 'static,
+    {
+        let (mut parts, body) = upstream.into_parts();
+        crate::proxy::strip_hop_by_hop(&mut parts.headers);
+        Response::from_parts(parts, body.boxed())
+    }
 }
 
 impl Default for ResponseBuilder {
@@ -188,18 +359,55 @@ impl IntoResponse for () {
     }
 }
 
+/// Answers the crate's own error types give when returned as a handler's `Err`
+///
+/// Each entry recognizes one type, also when boxed as
+/// `Box<dyn Error + Send + Sync>`, and builds its response.
+const REJECTIONS: &[fn(&dyn std::any::Any) -> Option<Response>] = &[
+    |err| rejection::<crate::Rejection>(err).map(|err| err.clone().into_response()),
+    |err| rejection(err).map(crate::JsonRejection::response),
+    |err| rejection::<crate::ValidationErrors>(err).map(|err| err.clone().into_response()),
+    |err| rejection(err).map(crate::BodyRejection::response),
+    |err| rejection::<crate::ParamError>(err).map(|err| err.clone().into_response()),
+    |err| rejection::<crate::WebSocketRejection>(err).map(|err| err.clone().into_response()),
+];
+
+/// `err` as an `R`, looking inside a `Box<dyn Error + Send + Sync>` too
+fn rejection<R: std::error::Error + 'static>(err: &dyn std::any::Any) -> Option<&R> {
+    err.downcast_ref::<R>().or_else(|| {
+        err.downcast_ref::<Box<dyn std::error::Error + Send + Sync>>()?
+            .downcast_ref()
+    })
+}
+
+/// Errors answer with `500`, or the status of a registered [`crate::ErrorMapper`]
+///
+/// The crate's own errors ([`crate::Rejection`], [`crate::JsonRejection`],
+/// [`crate::ValidationErrors`], [`crate::BodyRejection`], [`crate::ParamError`]
+/// and [`crate::WebSocketRejection`]), also when boxed as
+/// `Box<dyn Error + Send + Sync>`, are answered with their own status and body.
+/// Telling them apart needs `E: 'static`, so an error borrowing from the
+/// handler's locals has to be converted to an owned one first.
 impl<T, E> IntoResponse for Result<T, E>
 where
     T: IntoResponse,
-    E: std::fmt::Display,
+    E: std::fmt::Display + 'static,
 {
     fn into_response(self) -> Response {
         match self {
             Ok(value) => value.into_response(),
-            Err(err) => ResponseBuilder::new()
-                .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
-                .content_type("text/plain; charset=utf-8")
-                .body(format!("Error: {err}")),
+            Err(err) => REJECTIONS
+                .iter()
+                .find_map(|respond| respond(&err))
+                .unwrap_or_else(|| {
+                    ResponseBuilder::new()
+                        .status(
+                            crate::error::mapped_status(&err)
+                                .unwrap_or(hyper::StatusCode::INTERNAL_SERVER_ERROR),
+                        )
+                        .content_type("text/plain; charset=utf-8")
+                        .body(format!("Error: {err}"))
+                }),
         }
     }
 }
@@ -258,3 +466,415 @@ impl<const N: usize> IntoResponse for [u8; N] {
             .body(self.to_vec())
     }
 }
+
+/// Set a header on `response`, replacing any existing values
+///
+/// Unlike `headers_mut().insert(name.parse().unwrap(), ...)` this reports an
+/// invalid name or value as an error instead of panicking.
+pub fn response_set_header(
+    response: &mut Response,
+    name: &str,
+    value: &str,
+) -> Result<(), hyper::http::Error> {
+    let (name, value) = parse_header(name, value)?;
+    response.headers_mut().insert(name, value);
+    Ok(())
+}
+
+fn parse_header(
+    name: &str,
+    value: &str,
+) -> Result<(hyper::header::HeaderName, hyper::header::HeaderValue), hyper::http::Error> {
+    Ok((name.parse()?, hyper::header::HeaderValue::from_str(value)?))
+}
+
+/// Non-panicking helpers for middleware adjusting the response from `next`
+///
+/// ```rust
+/// use ree::{Next, RequestCtx, Response, ResponseExt};
+///
+/// async fn powered_by(ctx: RequestCtx, next: Next) -> Response {
+///     let mut response = next(ctx).await;
+///     let _ = response.set_header("X-Powered-By", "ree");
+///     response
+/// }
+/// ```
+pub trait ResponseExt {
+    /// Set a header, replacing any existing values
+    fn set_header(&mut self, name: &str, value: &str) -> Result<&mut Self, hyper::http::Error>;
+
+    /// Add a header value, keeping existing values
+    fn append_header(&mut self, name: &str, value: &str) -> Result<&mut Self, hyper::http::Error>;
+
+    /// Remove every value of a header
+    fn remove_header(&mut self, name: &str) -> &mut Self;
+
+    /// Replace the status code
+    fn set_status(&mut self, status: hyper::StatusCode) -> &mut Self;
+
+    /// A header value, if present and valid UTF-8
+    fn header_str(&self, name: &str) -> Option<&str>;
+
+    /// The directives of the response's `Cache-Control` headers
+    fn cache_control(&self) -> crate::CacheControl;
+
+    /// Add a `Set-Cookie` carrying `value` signed with `secret`, valid for `max_age`
+    ///
+    /// Read it back with [`RequestCtx::signed_cookie`](crate::RequestCtx::signed_cookie).
+    /// The cookie is `HttpOnly`, `SameSite=Lax` and scoped to `/`.
+    fn set_signed_cookie(
+        &mut self,
+        name: &str,
+        value: &str,
+        secret: &[u8],
+        max_age: std::time::Duration,
+    ) -> &mut Self;
+}
+
+impl ResponseExt for Response {
+    fn set_header(&mut self, name: &str, value: &str) -> Result<&mut Self, hyper::http::Error> {
+        response_set_header(self, name, value)?;
+        Ok(self)
+    }
+
+    fn append_header(&mut self, name: &str, value: &str) -> Result<&mut Self, hyper::http::Error> {
+        let (name, value) = parse_header(name, value)?;
+        self.headers_mut().append(name, value);
+        Ok(self)
+    }
+
+    fn remove_header(&mut self, name: &str) -> &mut Self {
+        self.headers_mut().remove(name);
+        self
+    }
+
+    fn set_status(&mut self, status: hyper::StatusCode) -> &mut Self {
+        *self.status_mut() = status;
+        self
+    }
+
+    fn header_str(&self, name: &str) -> Option<&str> {
+        self.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    fn cache_control(&self) -> crate::CacheControl {
+        crate::CacheControl::from_headers(self.headers())
+    }
+
+    fn set_signed_cookie(
+        &mut self,
+        name: &str,
+        value: &str,
+        secret: &[u8],
+        max_age: std::time::Duration,
+    ) -> &mut Self {
+        let signed = crate::cookie::sign(name, value, secret, max_age);
+        let cookie = crate::cookie::set_cookie_header(name, &signed, Some(max_age));
+        // The signed value is base64url and digits, always a valid header value
+        let cookie = hyper::header::HeaderValue::from_str(&cookie).expect("valid cookie header");
+        self.headers_mut().append(hyper::header::SET_COOKIE, cookie);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_response_strips_connection_headers() {
+        let upstream = ResponseBuilder::new()
+            .status(hyper::StatusCode::CREATED)
+            .header("Connection", "keep-alive, X-Trace")
+            .header("Keep-Alive", "timeout=5")
+            .header("Transfer-Encoding", "chunked")
+            .header("Upgrade", "h2c")
+            .header("Proxy-Authenticate", "Basic")
+            .header("Proxy-Agent", "squid")
+            .header("X-Trace", "abc")
+            .header("Cache-Control", "max-age=60")
+            .header("X-Request-Id", "42")
+            .body("created");
+
+        let response = ResponseBuilder::proxy_response(upstream);
+        assert_eq!(response.status(), hyper::StatusCode::CREATED);
+        let mut names: Vec<&str> = response
+            .headers()
+            .keys()
+            .map(|name| name.as_str())
+            .collect();
+        names.sort_unstable();
+        assert_eq!(names, ["cache-control", "x-request-id"]);
+    }
+
+    /// Download a temporary file holding `contents` under the attachment name `name`
+    async fn download(name: &str, contents: &str) -> (hyper::http::response::Parts, Bytes) {
+        // Tests run in parallel, so each call gets a file of its own
+        static CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let call = CALLS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("ree-download-{}-{call}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let (parts, body) = ResponseBuilder::download(&path, name).await.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        std::fs::remove_file(&path).unwrap();
+        (parts, body)
+    }
+
+    #[tokio::test]
+    async fn test_into_response_status_and_content_type() {
+        let existing = ResponseBuilder::new()
+            .status(hyper::StatusCode::ACCEPTED)
+            .content_type("text/csv")
+            .body("a,b");
+        let cases = [
+            (existing.into_response(), 202, "text/csv", "a,b"),
+            ("hi".into_response(), 200, "text/plain; charset=utf-8", "hi"),
+            (
+                format!("{}!", "hi").into_response(),
+                200,
+                "text/plain; charset=utf-8",
+                "hi!",
+            ),
+            (
+                serde_json::json!({"ok": true}).into_response(),
+                200,
+                "application/json; charset=utf-8",
+                r#"{"ok":true}"#,
+            ),
+            (
+                (hyper::StatusCode::CREATED, serde_json::json!({"id": 7})).into_response(),
+                201,
+                "application/json; charset=utf-8",
+                r#"{"id":7}"#,
+            ),
+        ];
+        for (response, status, content_type, body) in cases {
+            assert_eq!(response.status(), status);
+            assert_eq!(response.headers()["content-type"], content_type);
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(bytes, body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_status_helpers() {
+        let user = "bob";
+        let cases = [
+            (
+                ResponseBuilder::json(hyper::StatusCode::CREATED, r#"{"id":1}"#),
+                201,
+                r#"{"id":1}"#.to_string(),
+            ),
+            (
+                ResponseBuilder::unauthorized_json(r#"{"error":"login required"}"#),
+                401,
+                r#"{"error":"login required"}"#.to_string(),
+            ),
+            (
+                ResponseBuilder::forbidden_json(format!(r#"{{"error":"{user} may not"}}"#)),
+                403,
+                r#"{"error":"bob may not"}"#.to_string(),
+            ),
+            (
+                ResponseBuilder::too_many_requests_json(r#"{"retry_after":30}"#),
+                429,
+                r#"{"retry_after":30}"#.to_string(),
+            ),
+        ];
+        for (response, status, body) in cases {
+            assert_eq!(response.status(), status);
+            assert_eq!(
+                response.headers()["content-type"],
+                "application/json; charset=utf-8"
+            );
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(bytes, body);
+        }
+
+        let response = ResponseBuilder::with_status(hyper::StatusCode::ACCEPTED, "queued");
+        assert_eq!(response.status(), 202);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/plain; charset=utf-8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_problem_details() {
+        let response = ResponseBuilder::problem(
+            hyper::StatusCode::FORBIDDEN,
+            "https://example.com/probs/out-of-credit",
+            "You do not have enough credit.",
+            "Your current balance is 30, but that costs 50.",
+        )
+        .extension("balance", 30)
+        .extension("status", 500)
+        .into_response();
+        assert_eq!(response.status(), hyper::StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/problem+json"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "https://example.com/probs/out-of-credit",
+                "title": "You do not have enough credit.",
+                "status": 403,
+                "detail": "Your current balance is 30, but that costs 50.",
+                "balance": 30,
+            })
+        );
+
+        let mut ctx = crate::RequestCtx::new(
+            hyper::Request::get("/").body("").unwrap(),
+            Default::default(),
+        );
+        ctx.problem_details = true;
+        let response = ResponseBuilder::error_for(&ctx, hyper::StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/problem+json"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"type": "about:blank", "title": "Bad Gateway", "status": 502})
+        );
+    }
+
+    #[test]
+    fn test_html_and_text_responses() {
+        let cases = [
+            (ResponseBuilder::html("<p>hi</p>"), 200, "text/html"),
+            (ResponseBuilder::text("hi"), 200, "text/plain"),
+            (
+                ResponseBuilder::html_with_status(hyper::StatusCode::NOT_FOUND, "<p>gone</p>"),
+                404,
+                "text/html",
+            ),
+            (
+                ResponseBuilder::text_with_status(hyper::StatusCode::CREATED, "made"),
+                201,
+                "text/plain",
+            ),
+        ];
+        for (response, status, media_type) in cases {
+            assert_eq!(response.status(), status);
+            assert_eq!(
+                response.headers()["content-type"],
+                format!("{media_type}; charset=utf-8").as_str()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_ascii_filename() {
+        let (parts, body) = download("report.csv", "a,b\n1,2\n").await;
+        assert_eq!(parts.status, hyper::StatusCode::OK);
+        assert_eq!(
+            parts.headers["content-disposition"],
+            "attachment; filename=\"report.csv\""
+        );
+        assert_eq!(parts.headers["content-type"], "text/csv; charset=utf-8");
+        assert_eq!(parts.headers["content-length"], "8");
+        assert_eq!(body, "a,b\n1,2\n");
+    }
+
+    #[tokio::test]
+    async fn test_download_utf8_filename() {
+        let (parts, _) = download("报告 2024.pdf", "%PDF").await;
+        assert_eq!(
+            parts.headers["content-disposition"],
+            "attachment; filename=\"__ 2024.pdf\"; \
+             filename*=UTF-8''%E6%8A%A5%E5%91%8A%202024.pdf"
+        );
+        assert_eq!(parts.headers["content-type"], "application/pdf");
+    }
+
+    #[tokio::test]
+    async fn test_serve_file() {
+        let path = std::env::temp_dir().join(format!("ree-serve-{}.css", std::process::id()));
+        std::fs::write(&path, "body{}").unwrap();
+        let response = ResponseBuilder::serve_file(&path).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/css; charset=utf-8"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "body{}");
+        std::fs::remove_file(&path).unwrap();
+
+        let response = ResponseBuilder::serve_file(&path).await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_download_missing_file() {
+        let response = ResponseBuilder::download("/nonexistent/ree/file", "file.txt").await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_response_ext_in_middleware() {
+        use crate::{IntoNext, Next, RequestCtx};
+
+        async fn stamp(ctx: RequestCtx, next: Next) -> Response {
+            let mut response = next(ctx).await;
+            response
+                .set_status(hyper::StatusCode::ACCEPTED)
+                .set_header("X-Served-By", "ree")
+                .unwrap()
+                .append_header("Vary", "Accept")
+                .unwrap();
+            assert!(response.set_header("bad header", "x").is_err());
+            response
+        }
+
+        let next = (|_ctx: RequestCtx| async {
+            ResponseBuilder::new().header("Vary", "Origin").body("ok")
+        })
+        .into_next();
+        let ctx = RequestCtx::new(
+            hyper::Request::get("/").body("").unwrap(),
+            Default::default(),
+        );
+        let response = stamp(ctx, next).await;
+
+        assert_eq!(response.status(), hyper::StatusCode::ACCEPTED);
+        assert_eq!(response.header_str("x-served-by"), Some("ree"));
+        assert_eq!(response.headers().get_all("vary").iter().count(), 2);
+
+        let mut response = response;
+        response_set_header(&mut response, "Vary", "*").unwrap();
+        assert_eq!(response.header_str("vary"), Some("*"));
+    }
+
+    #[tokio::test]
+    async fn test_error_for_follows_accept() {
+        let ctx = |accept: &str| {
+            let request = hyper::Request::get("/missing")
+                .header("Accept", accept)
+                .body("");
+            crate::RequestCtx::new(request.unwrap(), Default::default())
+        };
+
+        let response =
+            ResponseBuilder::error_for(&ctx("application/json"), hyper::StatusCode::NOT_FOUND);
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"{"error":"Not Found","status":404}"#);
+
+        let response = ResponseBuilder::error_for(&ctx("text/html"), hyper::StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=utf-8"
+        );
+    }
+}
@@ -1,22 +1,121 @@
 //! HTTP router with trie-based pattern matching.
 
-use crate::{Handler, RequestCtx, Response, ResponseBuilder, trie::Node};
-use std::collections::HashMap;
+use crate::{
+    Handler, RequestCtx, Response, ResponseBuilder,
+    context::media_type,
+    swagger::RouteDoc,
+    trie::{Node, split_constraint},
+};
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
 
 type HandlerFunc = Box<dyn Handler>;
 
+/// Handlers of one route keyed by the request media type they accept
+type MediaTypeHandlers = Arc<RwLock<Vec<(String, Arc<dyn Handler>)>>>;
+
+/// Dispatches a route to the handler registered for the request's `Content-Type`
+struct ByContentType {
+    handlers: MediaTypeHandlers,
+}
+
+#[async_trait]
+impl Handler for ByContentType {
+    async fn handle(&self, ctx: RequestCtx) -> Response {
+        let requested = media_type(ctx.header("content-type").unwrap_or(""));
+        let handler = self
+            .handlers
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(media_type, _)| *media_type == requested)
+            .map(|(_, handler)| handler.clone());
+        match handler {
+            Some(handler) => handler.handle(ctx).await,
+            None => ResponseBuilder::error_for(&ctx, hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        }
+    }
+}
+
+/// How paths that only differ from a route in slashes are served, see
+/// [`Engine::trailing_slash`](crate::Engine::trailing_slash)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// Serve `/users/` and `//users` from a `/users` route as they are
+    #[default]
+    Match,
+    /// Answer `308 Permanent Redirect` to the path spelled like its route
+    Redirect,
+}
+
+/// `path` with runs of `/` collapsed and a trailing `/` only where `pattern` has one
+///
+/// The root path is always `/`.
+pub(crate) fn spelled_like(path: &str, pattern: &str) -> String {
+    let mut spelled = String::with_capacity(path.len() + 1);
+    for c in path.chars() {
+        if !(c == '/' && spelled.ends_with('/')) {
+            spelled.push(c);
+        }
+    }
+    if spelled.len() > 1 && spelled.ends_with('/') {
+        spelled.pop();
+    }
+    if pattern.len() > 1 && pattern.ends_with('/') && !spelled.ends_with('/') {
+        spelled.push('/');
+    }
+    spelled
+}
+
+/// A successful route lookup: the handler, its pattern and the captured parameters
+pub struct RouteMatch<'a> {
+    pub pattern: &'a str,
+    pub handler: &'a HandlerFunc,
+    pub params: HashMap<String, String>,
+}
+
+/// Marks a response the router produced because no route matched
+///
+/// Stored in the response extensions, so middleware can tell these apart from a
+/// `404` returned by a handler, e.g. to try a fallback:
+/// `response.extensions().get::<Unmatched>().is_some()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unmatched {
+    /// No route has this path
+    NotFound,
+    /// The path is routed, but not for the request method
+    MethodNotAllowed,
+}
+
 /// HTTP router for matching requests to handlers
 #[derive(Default)]
 pub struct Router {
     roots: HashMap<String, Node>,
-    handlers: HashMap<String, HandlerFunc>,
+    /// Handlers keyed by method, then by pattern
+    handlers: HashMap<String, HashMap<String, HandlerFunc>>,
+    /// OpenAPI metadata keyed by (method, pattern)
+    docs: HashMap<(String, String), RouteDoc>,
+    /// Routes added with `add_route_for_content_type`, keyed by (method, pattern)
+    media_types: HashMap<(String, String), MediaTypeHandlers>,
 }
 
 impl std::fmt::Debug for Router {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys = self
+            .handlers
+            .iter()
+            .flat_map(|(method, patterns)| {
+                patterns
+                    .keys()
+                    .map(move |pattern| format!("{method}-{pattern}"))
+            })
+            .collect::<Vec<_>>();
         f.debug_struct("Router")
             .field("roots", &self.roots)
-            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .field("handlers", &keys)
             .finish()
     }
 }
@@ -27,29 +126,167 @@ impl Router {
     }
 
     /// Parse a route pattern into parts (only one * is allowed)
+    ///
+    /// The `*` part may be followed by further parts, as in `/files/*path/meta`.
+    ///
+    /// The root route has no parts: `""`, `"/"` and `"//"` all parse to an empty
+    /// list, and routes are registered under `"/"` for all of them.
+    ///
+    /// Empty segments are dropped everywhere, so a request for `/users/` or
+    /// `//users` resolves to a `/users` route, and `/files/a/b/` hands a
+    /// `/files/*path` route `a/b`. [`Engine::trailing_slash`](crate::Engine::trailing_slash)
+    /// can redirect such requests instead.
     pub fn parse_pattern(pattern: &str) -> Vec<&str> {
-        let vs = pattern.split('/').collect::<Vec<&str>>();
-        let mut part = Vec::new();
-        for &item in vs.iter() {
-            if !item.is_empty() {
-                part.push(item);
-                if item.starts_with('*') {
-                    break;
-                }
-            }
+        pattern.split('/').filter(|item| !item.is_empty()).collect()
+    }
+
+    /// The pattern a route is stored under: `"/"` for any spelling of the root
+    fn canonical_pattern(pattern: &str) -> &str {
+        if pattern.bytes().all(|byte| byte == b'/') {
+            "/"
+        } else {
+            pattern
         }
-        part
     }
 
     /// Add a route with the specified method, pattern, and handler
+    ///
+    /// A parameter may carry a [`Constraint`](crate::trie::Constraint), as
+    /// `:id(\d+)` does to only match digits; it's captured as `id`.
+    ///
+    /// # Panics
+    ///
+    /// When `method` already has a route for `pattern`, also spelled with
+    /// another trailing slash (`/users/` for `/users`), or one that captures a
+    /// segment of it under another name, e.g. `/users/:id` for `/users/:name`.
+    /// Use [`Router::remove_route`] first to replace a route. Also when a
+    /// constraint isn't one of those recognized.
     pub fn add_route(&mut self, method: &str, pattern: &str, handler: HandlerFunc) {
+        let canonical = Self::canonical_pattern(pattern);
+        if self
+            .handlers
+            .get(method)
+            .is_some_and(|patterns| patterns.contains_key(canonical))
+        {
+            panic!("route {method} {pattern} is registered twice");
+        }
+        let parts = Self::parse_pattern(canonical);
+        if let Some(existing) = self
+            .roots
+            .get(method)
+            .and_then(|root| root.conflict(&parts, 0))
+        {
+            panic!("route {method} {pattern} conflicts with {method} {existing}");
+        }
+        self.replace_route(method, canonical, handler);
+    }
+
+    /// Add a route, replacing any registered for the same method and pattern
+    fn replace_route(&mut self, method: &str, pattern: &str, handler: HandlerFunc) {
+        let pattern = Self::canonical_pattern(pattern);
         let parts = Self::parse_pattern(pattern);
-        let key = format!("{method}-{pattern}");
         self.roots
             .entry(method.to_string())
             .or_default()
             .insert(pattern, parts, 0);
-        self.handlers.insert(key, handler);
+        self.handlers
+            .entry(method.to_string())
+            .or_default()
+            .insert(pattern.to_string(), handler);
+        self.media_types
+            .remove(&(method.to_string(), pattern.to_string()));
+    }
+
+    /// Add a handler for requests to `method` and `pattern` with this `Content-Type`
+    ///
+    /// Several handlers can share a route this way, one per media type, e.g.
+    /// `application/json` and `application/xml`. The request's `Content-Type`
+    /// is compared without parameters and case-insensitively; requests matching
+    /// none of the registered types get `415 Unsupported Media Type`. Like a
+    /// second [`Router::add_route`], adding one to a route registered without
+    /// a media type panics.
+    pub fn add_route_for_content_type(
+        &mut self,
+        method: &str,
+        pattern: &str,
+        content_type: &str,
+        handler: HandlerFunc,
+    ) {
+        let pattern = Self::canonical_pattern(pattern);
+        let key = (method.to_string(), pattern.to_string());
+        let handlers = match self.media_types.get(&key) {
+            Some(handlers) => handlers.clone(),
+            None => {
+                let handlers = MediaTypeHandlers::default();
+                let dispatch = ByContentType {
+                    handlers: handlers.clone(),
+                };
+                self.add_route(method, pattern, Box::new(dispatch));
+                self.media_types.insert(key, handlers.clone());
+                handlers
+            }
+        };
+        let content_type = media_type(content_type);
+        let mut handlers = handlers.write().unwrap();
+        handlers.retain(|(media_type, _)| *media_type != content_type);
+        handlers.push((content_type, Arc::from(handler)));
+    }
+
+    /// Remove a route previously added with [`Router::add_route`]
+    ///
+    /// Returns the removed handler, or `None` when nothing was registered
+    /// for `method` and `pattern`.
+    pub fn remove_route(&mut self, method: &str, pattern: &str) -> Option<HandlerFunc> {
+        let pattern = Self::canonical_pattern(pattern);
+        let patterns = self.handlers.get_mut(method)?;
+        let handler = patterns.remove(pattern)?;
+        if patterns.is_empty() {
+            self.handlers.remove(method);
+        }
+        let key = (method.to_string(), pattern.to_string());
+        self.docs.remove(&key);
+        self.media_types.remove(&key);
+        let parts = Self::parse_pattern(pattern);
+        if let Some(root) = self.roots.get_mut(method) {
+            root.remove(&parts, 0);
+            if root.pattern.is_empty() && root.children.is_empty() {
+                self.roots.remove(method);
+            }
+        }
+        Some(handler)
+    }
+
+    /// Move every route and its metadata from `other` into this router
+    ///
+    /// # Panics
+    ///
+    /// Like [`Router::add_route`], when a route of `other` is already
+    /// registered here or conflicts with one that is.
+    pub fn merge(&mut self, other: Router) {
+        for (method, patterns) in other.handlers {
+            for (pattern, handler) in patterns {
+                self.add_route(&method, &pattern, handler);
+            }
+        }
+        self.docs.extend(other.docs);
+        self.media_types.extend(other.media_types);
+    }
+
+    /// Attach OpenAPI metadata to a route
+    pub fn set_doc(&mut self, method: &str, pattern: &str, doc: RouteDoc) {
+        let pattern = Self::canonical_pattern(pattern);
+        self.docs
+            .insert((method.to_string(), pattern.to_string()), doc);
+    }
+
+    /// OpenAPI metadata for all documented routes
+    pub fn docs(&self) -> &HashMap<(String, String), RouteDoc> {
+        &self.docs
+    }
+
+    /// Total number of trie nodes across all methods
+    pub fn node_count(&self) -> usize {
+        self.roots.values().map(Node::node_count).sum()
     }
 
     /// Get a route handler for the given method and path
@@ -63,10 +300,20 @@ impl Router {
         if let Some(node) = root.unwrap().search(&search_parts, 0) {
             let parts = Self::parse_pattern(&node.pattern);
             for (index, ele) in parts.iter().enumerate() {
-                if let Some(param_name) = ele.strip_prefix(':') {
+                if let Some(param_name) = split_constraint(ele).0.strip_prefix(':') {
                     params.insert(param_name.to_string(), search_parts[index].to_string());
                 } else if let Some(param_name) = ele.strip_prefix('*') {
-                    params.insert(param_name.to_string(), search_parts[index..].join("/"));
+                    // Every part after the wildcard matched exactly one segment
+                    let end = search_parts.len() - (parts.len() - index - 1);
+                    params.insert(param_name.to_string(), search_parts[index..end].join("/"));
+                    for (offset, ele) in parts[index + 1..].iter().enumerate() {
+                        if let Some(param_name) = split_constraint(ele).0.strip_prefix(':') {
+                            params.insert(
+                                param_name.to_string(),
+                                search_parts[end + offset].to_string(),
+                            );
+                        }
+                    }
                     break;
                 }
             }
@@ -75,9 +322,48 @@ impl Router {
         (None, HashMap::new())
     }
 
-    /// Get a handler by key
+    /// Every route node matching `method` and `path`, not just the one that wins
+    ///
+    /// A debugging aid for overlapping routes: the nodes come in priority
+    /// order, so the first is the one [`Router::get_route`] picks.
+    pub fn find_all(&self, method: &str, path: &str) -> Vec<&Node> {
+        let mut matches = Vec::new();
+        if let Some(root) = self.roots.get(method) {
+            root.search_all(&Self::parse_pattern(path), 0, &mut matches);
+        }
+        matches
+    }
+
+    /// Resolve the handler for a request along with the route parameters
+    ///
+    /// Unlike [`Router::get_route`] followed by [`Router::handle`], this doesn't
+    /// build a `method-pattern` key string for every request.
+    pub fn find_route(&self, method: &str, path: &str) -> Option<RouteMatch<'_>> {
+        let (node, params) = self.get_route(method, path);
+        let pattern = node?.pattern.as_str();
+        let handler = self.handlers.get(method)?.get(pattern)?;
+        Some(RouteMatch {
+            pattern,
+            handler,
+            params,
+        })
+    }
+
+    /// Whether the route matching a request reads the body itself
+    pub fn streams_body(&self, method: &str, path: &str) -> bool {
+        self.find_route(method, path)
+            .is_some_and(|route| route.handler.streams_body())
+    }
+
+    /// Get a handler by `method-pattern` key
+    ///
+    /// The key is split at its first `-/`: methods never contain a `/` and
+    /// patterns start with one, so hyphens in the method (`M-SEARCH`) or the
+    /// pattern (`/user-profile`) don't confuse it.
     pub fn handle(&self, key: &str) -> Option<&HandlerFunc> {
-        self.handlers.get(key)
+        let split = key.find("-/")?;
+        let (method, pattern) = (&key[..split], &key[split + 1..]);
+        self.handlers.get(method)?.get(pattern)
     }
 
     /// Get all registered routes (method, pattern) for swagger generation
@@ -97,25 +383,114 @@ impl Router {
     }
 
     /// Handle an HTTP request
-    pub async fn handle_request(&self, mut ctx: RequestCtx) -> Response {
+    pub async fn handle_request(&self, ctx: RequestCtx) -> Response {
+        self.handle_request_stripping(ctx, None).await
+    }
+
+    /// Methods with a route for `path`, sorted
+    pub fn allowed_methods(&self, path: &str) -> Vec<&str> {
+        let mut methods: Vec<&str> = self
+            .roots
+            .keys()
+            .filter(|method| self.get_route(method, path).0.is_some())
+            .map(String::as_str)
+            .collect();
+        methods.sort_unstable();
+        methods
+    }
+
+    /// The `404`, or `405` with `Allow` when other methods are routed, for an unmatched request
+    fn unmatched(&self, ctx: &RequestCtx) -> Response {
+        let allowed = self.allowed_methods(ctx.path());
+        if allowed.is_empty() {
+            let mut response = ResponseBuilder::error_for(ctx, hyper::StatusCode::NOT_FOUND);
+            response.extensions_mut().insert(Unmatched::NotFound);
+            return response;
+        }
+
+        let mut response = ResponseBuilder::error_for(ctx, hyper::StatusCode::METHOD_NOT_ALLOWED);
+        response
+            .extensions_mut()
+            .insert(Unmatched::MethodNotAllowed);
+        if let Ok(value) = hyper::header::HeaderValue::from_str(&allowed.join(", ")) {
+            response.headers_mut().insert(hyper::header::ALLOW, value);
+        }
+        response
+    }
+
+    /// Handle a request, removing `prefix` from the path the handler sees
+    pub(crate) async fn handle_request_stripping(
+        &self,
+        mut ctx: RequestCtx,
+        prefix: Option<&str>,
+    ) -> Response {
         let method = ctx.request.method().as_str();
         let path = ctx.request.uri().path();
-        let (node, params) = self.get_route(method, path);
+        let Some(route) = self.find_route(method, path) else {
+            return self.unmatched(&ctx);
+        };
 
-        if node.is_none() {
-            return ResponseBuilder::not_found();
-        }
+        // Documented response schemas are checked in debug builds only
+        #[cfg(debug_assertions)]
+        let doc = self
+            .docs
+            .get(&(method.to_string(), route.pattern.to_string()));
 
         // Merge routing parameters and middleware parameters instead of overwriting
-        ctx.params.extend(params);
-        let node = node.unwrap();
-        let key = format!("{}-{}", method, node.pattern);
+        ctx.params.extend(route.params);
+        ctx.route_pattern = Some(route.pattern.to_string());
+        crate::panic::ErrorContext::record(&ctx);
+        if let Some(prefix) = prefix {
+            strip_path_prefix(&mut ctx, prefix);
+        }
 
-        if let Some(handler) = self.handle(&key) {
-            handler.handle(ctx).await
-        } else {
-            ResponseBuilder::not_found()
+        // Bodies held back for `Expect: 100-continue` are read only now
+        if !route.handler.streams_body()
+            && let Err(response) = ctx.read_pending_body().await
+        {
+            return response;
+        }
+
+        let log = ctx.log_context().clone();
+        let mut response = route.handler.handle(ctx).await;
+        #[cfg(debug_assertions)]
+        if let Some(doc) = doc {
+            response = crate::swagger::check_response(doc, route.pattern, &log, response).await;
         }
+        response.extensions_mut().insert(log);
+        response
+    }
+}
+
+/// Rewrite the request URI so its path no longer starts with `prefix`
+fn strip_path_prefix(ctx: &mut RequestCtx, prefix: &str) {
+    let uri = ctx.request.uri();
+    let Some(rest) = uri.path().strip_prefix(prefix) else {
+        return;
+    };
+    let path = if rest.starts_with('/') {
+        rest.to_string()
+    } else {
+        format!("/{rest}")
+    };
+    set_request_path(ctx, &path);
+}
+
+/// Replace the path of the request URI, keeping its query string
+pub(crate) fn set_request_path(ctx: &mut RequestCtx, path: &str) {
+    let uri = ctx.request.uri();
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_string(),
+    };
+
+    let mut parts = uri.clone().into_parts();
+    let Ok(path_and_query) = path_and_query.parse() else {
+        return;
+    };
+    parts.path_and_query = Some(path_and_query);
+    if let Ok(uri) = hyper::Uri::from_parts(parts) {
+        *ctx.request.uri_mut() = uri;
     }
 }
 
@@ -127,7 +502,8 @@ mod tests {
         let mut router = Router::new();
         router.add_route("GET", "/", Box::new(|_ctx| async { "Hello, World!" }));
         router.add_route("GET", "/hello", Box::new(|_ctx| async { "Hello!" }));
-        assert_eq!(router.roots.len(), 2);
+        assert_eq!(router.roots.len(), 1);
+        assert_eq!(router.handlers["GET"].len(), 2);
     }
 
     #[test]
@@ -137,6 +513,224 @@ mod tests {
         assert_eq!(parts, vec!["p", ":lang", "doc"]);
     }
 
+    #[tokio::test]
+    async fn test_root_route() {
+        for pattern in ["/", "", "//"] {
+            let mut router = Router::new();
+            router.add_route("GET", pattern, Box::new(|_ctx| async { "root" }));
+            router.add_route("GET", "/about", Box::new(|_ctx| async { "about" }));
+            assert_eq!(
+                router.get_all_routes()[0],
+                ("GET".to_string(), "/".to_string())
+            );
+
+            for path in ["/", ""] {
+                let route = router.find_route("GET", path).expect(pattern);
+                assert_eq!(route.pattern, "/");
+                assert!(route.params.is_empty());
+            }
+            assert_eq!(
+                router.find_route("GET", "/about").unwrap().pattern,
+                "/about"
+            );
+            assert!(router.find_route("POST", "/").is_none());
+
+            let request = hyper::Request::get("/").body("").unwrap();
+            let response = router
+                .handle_request(RequestCtx::new(request, Default::default()))
+                .await;
+            let body = http_body_util::BodyExt::collect(response.into_body())
+                .await
+                .unwrap()
+                .to_bytes();
+            assert_eq!(body, "root");
+
+            assert!(router.remove_route("GET", pattern).is_some());
+            assert!(router.find_route("GET", "/").is_none());
+        }
+    }
+
+    #[test]
+    fn test_get_route_wildcard_mid_path() {
+        let mut router = Router::new();
+        router.add_route(
+            "GET",
+            "/files/*path/meta",
+            Box::new(|_ctx| async { "meta" }),
+        );
+        router.add_route(
+            "GET",
+            "/repos/*path/blob/:rev",
+            Box::new(|_ctx| async { "blob" }),
+        );
+
+        let (node, params) = router.get_route("GET", "/files/a/b/meta");
+        assert_eq!(node.unwrap().pattern, "/files/*path/meta");
+        assert_eq!(params["path"], "a/b");
+
+        let (_, params) = router.get_route("GET", "/repos/org/app/blob/v1");
+        assert_eq!(params["path"], "org/app");
+        assert_eq!(params["rev"], "v1");
+
+        assert!(router.get_route("GET", "/files/a/b").0.is_none());
+        assert!(router.get_route("GET", "/files/meta").0.is_none());
+        assert!(router.get_route("GET", "/files/a/meta/b").0.is_none());
+    }
+
+    #[test]
+    fn test_find_all_overlapping_routes() {
+        let mut router = Router::new();
+        router.add_route("GET", "/users/:id", Box::new(|_ctx| async { "user" }));
+        router.add_route("GET", "/users/me", Box::new(|_ctx| async { "me" }));
+        router.add_route(
+            "GET",
+            "/users/:id/posts",
+            Box::new(|_ctx| async { "posts" }),
+        );
+
+        let patterns: Vec<_> = router
+            .find_all("GET", "/users/me")
+            .iter()
+            .map(|node| node.pattern.as_str())
+            .collect();
+        assert_eq!(patterns, ["/users/:id", "/users/me"]);
+        assert_eq!(
+            router.get_route("GET", "/users/me").0.unwrap().pattern,
+            patterns[0]
+        );
+
+        assert_eq!(router.find_all("GET", "/users/7").len(), 1);
+        assert!(router.find_all("POST", "/users/me").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_route_by_content_type() {
+        use http_body_util::BodyExt;
+
+        let mut router = Router::new();
+        router.add_route_for_content_type(
+            "POST",
+            "/orders",
+            "application/json",
+            Box::new(|_ctx| async { "json order" }),
+        );
+        router.add_route_for_content_type(
+            "POST",
+            "/orders",
+            "application/xml",
+            Box::new(|_ctx| async { "xml order" }),
+        );
+
+        let post = async |content_type: Option<&str>| {
+            let mut request = hyper::Request::post("/orders");
+            if let Some(content_type) = content_type {
+                request = request.header("Content-Type", content_type);
+            }
+            let ctx = RequestCtx::new(request.body("<order/>").unwrap(), Default::default());
+            let response = router.handle_request(ctx).await;
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (status, body)
+        };
+
+        assert_eq!(post(Some("application/json")).await.1, "json order");
+        assert_eq!(
+            post(Some("Application/XML; charset=utf-8")).await.1,
+            "xml order"
+        );
+        for content_type in [Some("text/csv"), None] {
+            let (status, _) = post(content_type).await;
+            assert_eq!(status, hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_path_404_other_method_405() {
+        let mut router = Router::new();
+        router.add_route("GET", "/users/:id", Box::new(|_ctx| async { "user" }));
+        router.add_route("PUT", "/users/:id", Box::new(|_ctx| async { "updated" }));
+        router.add_route("POST", "/users", Box::new(|_ctx| async { "created" }));
+        let send = |method: &str, path: &str| {
+            let request = hyper::Request::builder()
+                .method(method)
+                .uri(path)
+                .body("")
+                .unwrap();
+            router.handle_request(RequestCtx::new(request, Default::default()))
+        };
+
+        let response = send("GET", "/teams/1").await;
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        assert!(!response.headers().contains_key(hyper::header::ALLOW));
+
+        let response = send("DELETE", "/users/1").await;
+        assert_eq!(response.status(), hyper::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[hyper::header::ALLOW], "GET, PUT");
+
+        let response = send("GET", "/users").await;
+        assert_eq!(response.status(), hyper::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[hyper::header::ALLOW], "POST");
+    }
+
+    #[test]
+    #[should_panic(expected = "route GET /users/:id is registered twice")]
+    fn test_duplicate_route_panics() {
+        let mut router = Router::new();
+        router.add_route("GET", "/users/:id", Box::new(|_ctx| async { "first" }));
+        router.add_route(
+            "POST",
+            "/users/:id",
+            Box::new(|_ctx| async { "other method" }),
+        );
+        router.add_route("GET", "/users/:id", Box::new(|_ctx| async { "second" }));
+    }
+
+    #[test]
+    #[should_panic(expected = "route GET /users/:name/posts conflicts with GET /users/:id")]
+    fn test_conflicting_param_names_panic() {
+        let mut router = Router::new();
+        router.add_route("GET", "/users/:id", Box::new(|_ctx| async { "user" }));
+        router.add_route("GET", "/users/me", Box::new(|_ctx| async { "me" }));
+        router.add_route(
+            "GET",
+            "/users/:id/posts",
+            Box::new(|_ctx| async { "posts" }),
+        );
+        router.add_route(
+            "GET",
+            "/users/:name/posts",
+            Box::new(|_ctx| async { "posts" }),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "route GET /users/ conflicts with GET /users")]
+    fn test_trailing_slash_spelling_conflicts() {
+        let mut router = Router::new();
+        router.add_route("GET", "/users", Box::new(|_ctx| async { "users" }));
+        router.add_route("POST", "/users/", Box::new(|_ctx| async { "other method" }));
+        router.add_route("GET", "/users/", Box::new(|_ctx| async { "slashed" }));
+    }
+
+    #[test]
+    fn test_spelled_like() {
+        assert_eq!(spelled_like("/", "/"), "/");
+        assert_eq!(spelled_like("//", "/"), "/");
+        assert_eq!(spelled_like("//users/", "/users"), "/users");
+        assert_eq!(spelled_like("/docs", "/docs/"), "/docs/");
+        assert_eq!(spelled_like("/files/a//b/", "/files/*path"), "/files/a/b");
+    }
+
+    #[test]
+    fn test_route_can_be_replaced_after_removal() {
+        let mut router = Router::new();
+        router.add_route("GET", "/users/:id", Box::new(|_ctx| async { "first" }));
+        assert!(router.remove_route("GET", "/users/:id").is_some());
+        router.add_route("GET", "/users/:name", Box::new(|_ctx| async { "second" }));
+        let route = router.find_route("GET", "/users/alice").unwrap();
+        assert_eq!(route.params["name"], "alice");
+    }
+
     #[test]
     fn test_get_route() {
         let mut router = Router::new();
@@ -171,4 +765,101 @@ mod tests {
         // 验证参数是否正确提取
         assert_eq!(params.get("filepath").unwrap(), "js/app.js");
     }
+
+    #[test]
+    fn test_remove_route_reclaims_nodes() {
+        let mut router = Router::new();
+        router.add_route("GET", "/health", Box::new(|_ctx| async { "ok" }));
+        let baseline = router.node_count();
+
+        for i in 0..100 {
+            let pattern = format!("/items/{i}/detail");
+            router.add_route("GET", &pattern, Box::new(|_ctx| async { "item" }));
+            router.add_route("POST", &pattern, Box::new(|_ctx| async { "item" }));
+        }
+        router.add_route("GET", "/users/:id", Box::new(|_ctx| async { "user" }));
+        assert!(router.node_count() > baseline);
+
+        for i in 0..100 {
+            let pattern = format!("/items/{i}/detail");
+            assert!(router.remove_route("GET", &pattern).is_some());
+            assert!(router.remove_route("POST", &pattern).is_some());
+        }
+        assert!(router.remove_route("GET", "/users/:id").is_some());
+        assert!(router.remove_route("GET", "/users/:id").is_none());
+
+        assert_eq!(router.node_count(), baseline);
+        assert!(router.get_route("GET", "/users/1").0.is_none());
+        assert!(router.get_route("GET", "/health").0.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_find_route_returns_matching_handler() {
+        let mut router = Router::new();
+        router.add_route("GET", "/users/:id", Box::new(|_ctx| async { "user" }));
+        router.add_route("POST", "/users/:id", Box::new(|_ctx| async { "update" }));
+
+        let route = router.find_route("GET", "/users/42").unwrap();
+        assert_eq!(route.pattern, "/users/:id");
+        assert_eq!(route.params.get("id").unwrap(), "42");
+
+        let ctx = RequestCtx::new(
+            hyper::Request::get("/users/42").body("").unwrap(),
+            route.params,
+        );
+        let response = route.handler.handle(ctx).await;
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(body, "user");
+
+        assert!(router.find_route("DELETE", "/users/42").is_none());
+        assert!(router.handle("POST-/users/:id").is_some());
+    }
+
+    #[test]
+    fn test_digit_constrained_param() {
+        let mut router = Router::new();
+        router.add_route("GET", r"/users/:id(\d+)", Box::new(|_ctx| async { "user" }));
+
+        let (node, params) = router.get_route("GET", "/users/42");
+        assert_eq!(node.unwrap().pattern, r"/users/:id(\d+)");
+        assert_eq!(params["id"], "42");
+        assert!(router.get_route("GET", "/users/me").0.is_none());
+        assert!(router.get_route("GET", "/users/4x").0.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported constraint")]
+    fn test_unsupported_constraint() {
+        let mut router = Router::new();
+        router.add_route(
+            "GET",
+            "/users/:id([a-z]+)",
+            Box::new(|_ctx| async { "user" }),
+        );
+    }
+
+    #[test]
+    fn test_handle_key_with_hyphens() {
+        let mut router = Router::new();
+        router.add_route(
+            "GET",
+            "/user-profile/:id",
+            Box::new(|_ctx| async { "profile" }),
+        );
+        router.add_route(
+            "M-SEARCH",
+            "/device-list",
+            Box::new(|_ctx| async { "devices" }),
+        );
+
+        assert!(router.handle("GET-/user-profile/:id").is_some());
+        assert!(router.handle("M-SEARCH-/device-list").is_some());
+        assert!(router.handle("M-/device-list").is_none());
+        assert!(router.handle("M-SEARCH-/user-profile/:id").is_none());
+        assert!(router.handle("GET-/device-list").is_none());
+        assert!(router.handle("GET-/user").is_none());
+    }
 }
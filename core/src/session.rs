@@ -0,0 +1,265 @@
+//! Cookie-based sessions with pluggable storage.
+//!
+//! The [`session`] middleware keeps a session id in the `ree_session` cookie,
+//! loads the matching data from a [`SessionStore`] before the handler runs and
+//! saves it afterwards if the handler changed it. Handlers reach the data
+//! through [`RequestCtx::session`].
+//!
+//! ```rust
+//! use ree::{Engine, RequestCtx, session};
+//!
+//! let mut app = Engine::new();
+//! app.use_middleware(session());
+//! app.get("/visit", |ctx: RequestCtx| async move {
+//!     let session = ctx.session().expect("session middleware is installed");
+//!     let visits = session.get("visits").and_then(|v| v.parse::<u32>().ok()).unwrap_or(0);
+//!     session.set("visits", (visits + 1).to_string());
+//!     format!("visit #{}", visits + 1)
+//! });
+//! ```
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::RngCore;
+
+use crate::{Next, RequestCtx, Response};
+
+/// Name of the cookie holding the session id
+pub const SESSION_COOKIE: &str = "ree_session";
+
+/// The key-value data of one session
+pub type SessionData = HashMap<String, String>;
+
+/// Where session data lives between requests
+///
+/// Implement this to keep sessions in Redis, a database and so on; the
+/// built-in [`MemoryStore`] keeps them in the process.
+#[async_trait]
+pub trait SessionStore: Send + Sync + 'static {
+    /// The data stored for `id`, or `None` for unknown ids
+    async fn load(&self, id: &str) -> Option<SessionData>;
+
+    /// Store `data` under `id`, replacing what was there
+    async fn save(&self, id: &str, data: SessionData);
+
+    /// Forget the session `id`
+    async fn delete(&self, id: &str);
+}
+
+/// Sessions held in memory, lost on restart and not shared between processes
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    sessions: Arc<Mutex<HashMap<String, SessionData>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn load(&self, id: &str) -> Option<SessionData> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    async fn save(&self, id: &str, data: SessionData) {
+        self.sessions.lock().unwrap().insert(id.to_string(), data);
+    }
+
+    async fn delete(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+}
+
+#[derive(Default)]
+struct SessionState {
+    data: SessionData,
+    changed: bool,
+}
+
+/// The current request's session, see [`RequestCtx::session`]
+///
+/// Clones share the same data, so a middleware and the handler after it see
+/// each other's changes.
+#[derive(Clone, Default)]
+pub struct Session {
+    state: Arc<Mutex<SessionState>>,
+}
+
+impl Session {
+    /// The value stored under `key`
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.state.lock().unwrap().data.get(key).cloned()
+    }
+
+    /// Store `value` under `key`
+    pub fn set(&self, key: &str, value: impl Into<String>) {
+        let mut state = self.state.lock().unwrap();
+        state.data.insert(key.to_string(), value.into());
+        state.changed = true;
+    }
+
+    /// Remove `key`, returning its value
+    pub fn remove(&self, key: &str) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let removed = state.data.remove(key);
+        state.changed |= removed.is_some();
+        removed
+    }
+}
+
+impl RequestCtx {
+    /// The session loaded by the [`session`] middleware, `None` without it
+    pub fn session(&self) -> Option<Session> {
+        self.request.extensions().get::<Session>().cloned()
+    }
+}
+
+/// 256 random bits, base64url-encoded
+fn new_session_id() -> String {
+    let mut id = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut id);
+    URL_SAFE_NO_PAD.encode(id)
+}
+
+/// Sessions kept in a [`MemoryStore`]
+pub fn session()
+-> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    session_with_store(MemoryStore::new())
+}
+
+/// Sessions kept in `store`
+///
+/// A new id is only issued, and the cookie only set, once a handler stores
+/// something. Sessions left empty by the handler are deleted from the store.
+pub fn session_with_store<S: SessionStore>(
+    store: S,
+) -> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    let store = Arc::new(store);
+    move |mut ctx, next| {
+        let store = store.clone();
+        Box::pin(async move {
+            let known = match ctx.cookie(SESSION_COOKIE) {
+                Some(id) => {
+                    let id = id.to_string();
+                    store.load(&id).await.map(|data| (id, data))
+                }
+                None => None,
+            };
+            let (id, data) = known.unzip();
+
+            let session = Session::default();
+            session.state.lock().unwrap().data = data.unwrap_or_default();
+            ctx.request.extensions_mut().insert(session.clone());
+
+            let mut response = next(ctx).await;
+
+            let state = std::mem::take(&mut *session.state.lock().unwrap());
+            if !state.changed {
+                return response;
+            }
+            match id {
+                Some(id) if state.data.is_empty() => store.delete(&id).await,
+                Some(id) => store.save(&id, state.data).await,
+                None if state.data.is_empty() => {}
+                None => {
+                    let id = new_session_id();
+                    store.save(&id, state.data).await;
+                    let cookie = crate::cookie::set_cookie_header(SESSION_COOKIE, &id, None);
+                    response.headers_mut().append(
+                        hyper::header::SET_COOKIE,
+                        hyper::header::HeaderValue::from_str(&cookie).expect("valid cookie header"),
+                    );
+                }
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntoNext;
+    use http_body_util::BodyExt;
+
+    fn request(uri: &str, cookie: Option<&str>) -> RequestCtx {
+        let mut request = hyper::Request::get(uri);
+        if let Some(cookie) = cookie {
+            request = request.header("Cookie", cookie);
+        }
+        RequestCtx::new(request.body("").unwrap(), Default::default())
+    }
+
+    fn endpoint() -> Next {
+        (|ctx: RequestCtx| async move {
+            let session = ctx.session().unwrap();
+            match ctx.path() {
+                "/login" => session.set("user", "alice"),
+                "/logout" => {
+                    session.remove("user");
+                }
+                _ => {}
+            }
+            let user = session
+                .get("user")
+                .unwrap_or_else(|| "anonymous".to_string());
+            crate::ResponseBuilder::new().body(user)
+        })
+        .into_next()
+    }
+
+    async fn body_text(response: Response) -> String {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_session_survives_requests() {
+        let store = MemoryStore::new();
+        let middleware = session_with_store(store.clone());
+
+        let response = middleware(request("/me", None), endpoint()).await;
+        assert!(!response.headers().contains_key("set-cookie"));
+        assert_eq!(body_text(response).await, "anonymous");
+
+        let response = middleware(request("/login", None), endpoint()).await;
+        let set_cookie = response.headers()["set-cookie"].to_str().unwrap();
+        let cookie = set_cookie.split(';').next().unwrap().to_string();
+        assert!(cookie.starts_with("ree_session="));
+
+        let response = middleware(request("/me", Some(&cookie)), endpoint()).await;
+        assert!(!response.headers().contains_key("set-cookie"));
+        assert_eq!(body_text(response).await, "alice");
+
+        let response = middleware(request("/logout", Some(&cookie)), endpoint()).await;
+        assert_eq!(body_text(response).await, "anonymous");
+        assert!(store.sessions.lock().unwrap().is_empty());
+        let response = middleware(request("/me", Some(&cookie)), endpoint()).await;
+        assert_eq!(body_text(response).await, "anonymous");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_session_id_gets_a_fresh_session() {
+        let middleware = session();
+        let response = middleware(request("/login", Some("ree_session=forged")), endpoint()).await;
+        let set_cookie = response.headers()["set-cookie"].to_str().unwrap();
+        assert!(!set_cookie.starts_with("ree_session=forged"));
+    }
+
+    #[test]
+    fn test_session_without_middleware() {
+        assert!(request("/", None).session().is_none());
+    }
+}
@@ -0,0 +1,204 @@
+//! Server-sent event responses.
+//!
+//! Handlers push [`SseEvent`]s into a channel and return [`ResponseBuilder::sse`]
+//! with the receiving end; the response stays open until every sender is dropped.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use ree::{Engine, RequestCtx, ResponseBuilder, SseEvent};
+//!
+//! let mut app = Engine::new();
+//! app.get("/ticks", |_ctx: RequestCtx| async {
+//!     let (events, receiver) = tokio::sync::mpsc::channel(16);
+//!     tokio::spawn(async move {
+//!         for tick in 0.. {
+//!             let event = SseEvent::new(tick.to_string()).event("tick");
+//!             if events.send(event).await.is_err() {
+//!                 break;
+//!             }
+//!             tokio::time::sleep(Duration::from_secs(1)).await;
+//!         }
+//!     });
+//!     ResponseBuilder::sse(receiver).keepalive(Duration::from_secs(15))
+//! });
+//! ```
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http_body_util::BodyExt;
+use hyper::body::{Body, Bytes, Frame};
+use tokio::{sync::mpsc, time::Sleep};
+
+use crate::{IntoResponse, Response, ResponseBuilder};
+
+/// The comment sent on idle streams, see [`Sse::keepalive`]
+const KEEPALIVE: &[u8] = b":keepalive\n\n";
+
+/// One server-sent event
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SseEvent {
+    event: Option<String>,
+    id: Option<String>,
+    data: String,
+}
+
+impl SseEvent {
+    /// An unnamed event carrying `data`; multi-line data is sent as several `data:` lines
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the event name clients listen for with `addEventListener`
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Set the id clients send back in `Last-Event-ID` when reconnecting
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// The event in wire format, ending with the blank line that dispatches it
+    pub fn to_bytes(&self) -> Bytes {
+        let mut out = String::new();
+        if let Some(event) = &self.event {
+            out.push_str(&format!("event: {event}\n"));
+        }
+        if let Some(id) = &self.id {
+            out.push_str(&format!("id: {id}\n"));
+        }
+        for line in self.data.split('\n') {
+            out.push_str(&format!("data: {line}\n"));
+        }
+        out.push('\n');
+        Bytes::from(out)
+    }
+}
+
+/// An event stream response, see [`ResponseBuilder::sse`]
+pub struct Sse {
+    events: mpsc::Receiver<SseEvent>,
+    keepalive: Option<Duration>,
+}
+
+impl ResponseBuilder {
+    /// Build a `text/event-stream` response sending every event from `events`
+    pub fn sse(events: mpsc::Receiver<SseEvent>) -> Sse {
+        Sse {
+            events,
+            keepalive: None,
+        }
+    }
+}
+
+impl Sse {
+    /// Send a `:keepalive` comment after every `every` without an event
+    ///
+    /// Keeps proxies from closing idle connections. Clients ignore comments, and
+    /// the timer restarts with each real event, so events are never delayed.
+    pub fn keepalive(mut self, every: Duration) -> Self {
+        self.keepalive = Some(every);
+        self
+    }
+}
+
+impl IntoResponse for Sse {
+    fn into_response(self) -> Response {
+        let body = SseBody {
+            events: self.events,
+            keepalive: self
+                .keepalive
+                .map(|every| (every, Box::pin(tokio::time::sleep(every)))),
+        };
+        hyper::Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+            .header(hyper::header::CACHE_CONTROL, "no-cache")
+            .body(body.boxed())
+            .unwrap()
+    }
+}
+
+struct SseBody {
+    events: mpsc::Receiver<SseEvent>,
+    keepalive: Option<(Duration, Pin<Box<Sleep>>)>,
+}
+
+impl Body for SseBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, hyper::Error>>> {
+        let this = self.get_mut();
+        match this.events.poll_recv(cx) {
+            Poll::Ready(Some(event)) => {
+                if let Some((every, timer)) = &mut this.keepalive {
+                    timer.as_mut().reset(tokio::time::Instant::now() + *every);
+                }
+                return Poll::Ready(Some(Ok(Frame::data(event.to_bytes()))));
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {}
+        }
+
+        let Some((every, timer)) = &mut this.keepalive else {
+            return Poll::Pending;
+        };
+        match timer.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                timer.as_mut().reset(tokio::time::Instant::now() + *every);
+                Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(KEEPALIVE)))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_format() {
+        let event = SseEvent::new("line one\nline two").event("update").id("7");
+        assert_eq!(
+            event.to_bytes(),
+            "event: update\nid: 7\ndata: line one\ndata: line two\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_during_idle_period() {
+        let (events, receiver) = mpsc::channel(4);
+        let response = ResponseBuilder::sse(receiver)
+            .keepalive(Duration::from_millis(20))
+            .into_response();
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let mut body = response.into_body();
+        let mut next_chunk = async || {
+            let frame = body.frame().await?.unwrap();
+            Some(frame.into_data().unwrap())
+        };
+
+        // Nothing is sent for a while: keepalives fill the gap
+        assert_eq!(next_chunk().await.unwrap(), KEEPALIVE);
+        assert_eq!(next_chunk().await.unwrap(), KEEPALIVE);
+
+        events.send(SseEvent::new("hello")).await.unwrap();
+        assert_eq!(next_chunk().await.unwrap(), "data: hello\n\n");
+
+        drop(events);
+        assert_eq!(next_chunk().await, None);
+    }
+}
@@ -1,26 +1,277 @@
 //! Simple Swagger generation for Ree framework
 
-use serde_json::json;
+use std::collections::HashMap;
 
-/// Generate OpenAPI JSON from routes
-pub fn generate_swagger_json(routes: &[(String, String)]) -> String {
+use serde_json::{Value, json};
+
+use crate::trie::{Constraint, split_constraint};
+
+/// OpenAPI metadata attached to a route at registration time
+///
+/// ```rust
+/// use ree::{Engine, RouteDoc};
+/// use serde_json::json;
+///
+/// let mut app = Engine::new();
+/// app.get_documented(
+///     "/users/:id",
+///     RouteDoc::new()
+///         .summary("Fetch a user")
+///         .tag("users")
+///         .response_schema(200, "The user", json!({"type": "object"}))
+///         .response(404, "No such user"),
+///     |_ctx| async { "user" },
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RouteDoc {
+    summary: Option<String>,
+    description: Option<String>,
+    tags: Vec<String>,
+    responses: Vec<(u16, String, Option<Value>)>,
+    /// Schemas of path parameters, by name
+    params: Vec<(String, Value)>,
+    request_body: Option<(String, Value)>,
+}
+
+impl RouteDoc {
+    /// Start an empty route description
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// One-line summary shown next to the operation
+    pub fn summary(mut self, summary: &str) -> Self {
+        self.summary = Some(summary.to_string());
+        self
+    }
+
+    /// Longer, free-form description of the operation
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Add a tag used to group operations in Swagger UI
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Document a response status without a body schema
+    pub fn response(mut self, status: u16, description: &str) -> Self {
+        self.responses.push((status, description.to_string(), None));
+        self
+    }
+
+    /// Document a response status with a JSON schema for its body
+    pub fn response_schema(mut self, status: u16, description: &str, schema: Value) -> Self {
+        self.responses
+            .push((status, description.to_string(), Some(schema)));
+        self
+    }
+
+    /// Give the path parameter `name` a schema other than the one inferred
+    ///
+    /// Parameters are documented as integers when their pattern part is
+    /// constrained to digits (`:id(\d+)`) and as strings otherwise; this
+    /// overrides that, e.g. `.param_schema("id", json!({"type": "integer", "format": "int64"}))`.
+    pub fn param_schema(mut self, name: &str, schema: Value) -> Self {
+        self.params.retain(|(documented, _)| documented != name);
+        self.params.push((name.to_string(), schema));
+        self
+    }
+
+    /// Document the JSON request body with a schema
+    ///
+    /// Replaces the placeholder body listed for undocumented `POST`, `PUT` and
+    /// `PATCH` routes. Any JSON Schema works, e.g. one derived with `schemars`
+    /// and converted with `serde_json::to_value`.
+    pub fn request_schema(mut self, description: &str, schema: Value) -> Self {
+        self.request_body = Some((description.to_string(), schema));
+        self
+    }
+
+    /// The schema documented for responses with `status`
+    #[cfg(debug_assertions)]
+    fn schema_for(&self, status: u16) -> Option<&Value> {
+        self.responses
+            .iter()
+            .find(|(documented, _, _)| *documented == status)
+            .and_then(|(_, _, schema)| schema.as_ref())
+    }
+}
+
+/// Where a response body doesn't match its documented schema, logged in debug builds
+///
+/// Recorded in the request's [`LogContext`](crate::LogContext) so tests can see it.
+#[cfg(debug_assertions)]
+#[derive(Clone, Debug)]
+pub(crate) struct SchemaMismatch(pub(crate) Vec<String>);
+
+/// Check a JSON response against the schema `doc` gives for its status
+///
+/// Debug builds only. Mismatches are printed as warnings and the response is
+/// passed on unchanged; bodies that aren't fully buffered JSON are skipped, so
+/// streams are never consumed.
+#[cfg(debug_assertions)]
+pub(crate) async fn check_response(
+    doc: &RouteDoc,
+    route: &str,
+    log: &crate::LogContext,
+    response: crate::Response,
+) -> crate::Response {
+    use http_body_util::BodyExt;
+
+    let Some(schema) = doc.schema_for(response.status().as_u16()) else {
+        return response;
+    };
+    let is_json = response
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| crate::context::media_type(value) == "application/json");
+    if !is_json
+        || hyper::body::Body::size_hint(response.body())
+            .exact()
+            .is_none()
+    {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(err) => {
+            eprintln!("Failed to read response of {route} for schema check: {err}");
+            hyper::body::Bytes::new()
+        }
+    };
+    let mismatches = match serde_json::from_slice::<Value>(&body) {
+        Ok(value) => schema_mismatches(schema, &value, "$"),
+        Err(err) => vec![format!("$: not valid JSON ({err})")],
+    };
+    if !mismatches.is_empty() {
+        let mismatch = SchemaMismatch(mismatches);
+        eprintln!(
+            "⚠️  Response of {route} doesn't match its documented schema: {}",
+            mismatch.0.join("; ")
+        );
+        log.insert(mismatch);
+    }
+    let body = http_body_util::Full::new(body)
+        .map_err(|never| match never {})
+        .boxed();
+    crate::Response::from_parts(parts, body)
+}
+
+/// Where `value` breaks `schema`, one message per problem
+///
+/// Understands the common subset of JSON Schema used in route docs: `type`
+/// (a name or a list of names), `enum`, `properties`, `required` and `items`.
+/// Other keywords are ignored.
+#[cfg(debug_assertions)]
+pub(crate) fn schema_mismatches(schema: &Value, value: &Value, at: &str) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    let type_name = |value: &Value| match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    };
+    let matches_type = |expected: &str| {
+        let actual = type_name(value);
+        expected == actual || (expected == "number" && actual == "integer")
+    };
+
+    let expected: Vec<&str> = match schema.get("type") {
+        Some(Value::String(name)) => vec![name.as_str()],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !expected.is_empty() && !expected.iter().any(|name| matches_type(name)) {
+        mismatches.push(format!(
+            "{at}: expected {}, found {}",
+            expected.join(" or "),
+            type_name(value)
+        ));
+        return mismatches;
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum")
+        && !allowed.contains(value)
+    {
+        mismatches.push(format!(
+            "{at}: {value} is not one of {}",
+            Value::Array(allowed.clone())
+        ));
+    }
+
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    mismatches.push(format!("{at}: missing required field `{name}`"));
+                }
+            }
+        }
+        if let Some(Value::Object(properties)) = schema.get("properties") {
+            for (name, property) in properties {
+                if let Some(field) = object.get(name) {
+                    let at = format!("{at}.{name}");
+                    mismatches.extend(schema_mismatches(property, field, &at));
+                }
+            }
+        }
+    }
+    if let (Value::Array(elements), Some(items)) = (value, schema.get("items")) {
+        for (index, element) in elements.iter().enumerate() {
+            mismatches.extend(schema_mismatches(items, element, &format!("{at}[{index}]")));
+        }
+    }
+    mismatches
+}
+
+/// Generate OpenAPI JSON from routes, enriched with any documented metadata
+pub fn generate_swagger_json(
+    routes: &[(String, String)],
+    docs: &HashMap<(String, String), RouteDoc>,
+) -> String {
+    serde_json::to_string_pretty(&generate_openapi(routes, docs)).unwrap()
+}
+
+/// The schema of a path parameter captured under `constraint`
+fn inferred_schema(constraint: Option<Constraint>) -> Value {
+    match constraint {
+        Some(Constraint::Digits) => json!({ "type": "integer" }),
+        None => json!({ "type": "string" }),
+    }
+}
+
+/// Build the OpenAPI 3.0 document for routes given as (method, pattern) pairs
+///
+/// Each pattern becomes a `paths` key with `:name` segments written as
+/// `{name}`, and each method an operation listing those segments as required
+/// path parameters. A `:name(\d+)` segment is documented as an integer.
+pub fn generate_openapi(
+    routes: &[(String, String)],
+    docs: &HashMap<(String, String), RouteDoc>,
+) -> Value {
     let mut paths = serde_json::Map::new();
     
     for (method, path) in routes {
-        // Convert Ree path format (:id) to OpenAPI format ({id})
-        let openapi_path = path.replace(":", "{").replace("}", "");
-        let openapi_path = if openapi_path.contains("{") && !openapi_path.contains("}") {
-            // Add closing braces for parameters
-            openapi_path.split('/').map(|part| {
-                if part.starts_with("{") && !part.ends_with("}") {
-                    format!("{part}}}")
-                } else {
-                    part.to_string()
-                }
-            }).collect::<Vec<_>>().join("/")
-        } else {
-            openapi_path
-        };
+        // Convert Ree path format (:id, :id(\d+)) to OpenAPI format ({id})
+        let openapi_path = path
+            .split('/')
+            .map(|part| match split_constraint(part).0.strip_prefix(':') {
+                Some(param_name) => format!("{{{param_name}}}"),
+                None => part.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let doc = docs.get(&(method.clone(), path.clone()));
         
         let path_item = paths.entry(openapi_path.clone()).or_insert_with(|| json!({}));
         
@@ -34,17 +285,44 @@ pub fn generate_swagger_json(routes: &[(String, String)]) -> String {
                 }
             });
 
+            if let Some(doc) = doc {
+                if let Some(summary) = &doc.summary {
+                    operation["summary"] = json!(summary);
+                }
+                if let Some(description) = &doc.description {
+                    operation["description"] = json!(description);
+                }
+                if !doc.tags.is_empty() {
+                    operation["tags"] = json!(doc.tags);
+                }
+                if !doc.responses.is_empty() {
+                    let mut responses = serde_json::Map::new();
+                    for (status, description, schema) in &doc.responses {
+                        let mut response = json!({ "description": description });
+                        if let Some(schema) = schema {
+                            response["content"] = json!({
+                                "application/json": { "schema": schema }
+                            });
+                        }
+                        responses.insert(status.to_string(), response);
+                    }
+                    operation["responses"] = Value::Object(responses);
+                }
+            }
+
             // Extract path parameters
             let mut parameters = Vec::new();
             for part in path.split('/') {
+                let (part, constraint) = split_constraint(part);
                 if let Some(param_name) = part.strip_prefix(':') {
+                    let schema = doc
+                        .and_then(|doc| doc.params.iter().find(|(name, _)| name == param_name))
+                        .map_or_else(|| inferred_schema(constraint), |(_, schema)| schema.clone());
                     parameters.push(json!({
                         "name": param_name,
                         "in": "path",
                         "required": true,
-                        "schema": {
-                            "type": "string"
-                        },
+                        "schema": schema,
                         "description": format!("The {param_name} parameter")
                     }));
                 }
@@ -66,7 +344,15 @@ pub fn generate_swagger_json(routes: &[(String, String)]) -> String {
             }
 
             // Add request body for POST, PUT, PATCH methods
-            if matches!(method.as_str(), "POST" | "PUT" | "PATCH") {
+            if let Some((description, schema)) = doc.and_then(|doc| doc.request_body.as_ref()) {
+                operation["requestBody"] = json!({
+                    "description": description,
+                    "required": true,
+                    "content": {
+                        "application/json": { "schema": schema }
+                    }
+                });
+            } else if matches!(method.as_str(), "POST" | "PUT" | "PATCH") {
                 operation["requestBody"] = json!({
                     "required": true,
                     "content": {
@@ -103,16 +389,14 @@ pub fn generate_swagger_json(routes: &[(String, String)]) -> String {
         }
     }
     
-    let swagger_doc = json!({
+    json!({
         "openapi": "3.0.0",
         "info": {
             "title": "Ree API",
             "version": "1.0.0"
         },
         "paths": paths
-    });
-    
-    serde_json::to_string_pretty(&swagger_doc).unwrap()
+    })
 }
 
 /// Generate Swagger UI HTML
@@ -140,3 +424,167 @@ pub fn generate_swagger_ui(json_url: &str) -> String {
 </html>
     "#)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_documented_route_metadata() {
+        let routes = vec![
+            ("GET".to_string(), "/users/:id".to_string()),
+            ("GET".to_string(), "/health".to_string()),
+        ];
+        let mut docs = HashMap::new();
+        docs.insert(
+            ("GET".to_string(), "/users/:id".to_string()),
+            RouteDoc::new()
+                .summary("Fetch a user")
+                .tag("users")
+                .response_schema(200, "The user", json!({"type": "object"}))
+                .response(404, "No such user"),
+        );
+
+        let spec: Value = serde_json::from_str(&generate_swagger_json(&routes, &docs)).unwrap();
+        let operation = &spec["paths"]["/users/{id}"]["get"];
+        assert_eq!(operation["summary"], "Fetch a user");
+        assert_eq!(operation["tags"], json!(["users"]));
+        assert_eq!(
+            operation["responses"]["200"]["content"]["application/json"]["schema"],
+            json!({"type": "object"})
+        );
+        assert_eq!(operation["responses"]["404"]["description"], "No such user");
+
+        let undocumented = &spec["paths"]["/health"]["get"];
+        assert_eq!(undocumented["summary"], "GET /health");
+        assert!(undocumented.get("tags").is_none());
+    }
+
+    #[test]
+    fn test_param_and_request_schemas() {
+        let routes = vec![
+            ("PUT".to_string(), "/users/:id/posts/:slug".to_string()),
+            ("POST".to_string(), "/users".to_string()),
+        ];
+        let mut docs = HashMap::new();
+        let post = json!({"type": "object", "required": ["title"]});
+        docs.insert(
+            routes[0].clone(),
+            RouteDoc::new()
+                .param_schema("id", json!({"type": "integer", "format": "int64"}))
+                .request_schema("The post", post.clone()),
+        );
+
+        let spec: Value = serde_json::from_str(&generate_swagger_json(&routes, &docs)).unwrap();
+        let operation = &spec["paths"]["/users/{id}/posts/{slug}"]["put"];
+        assert_eq!(operation["parameters"][0]["name"], "id");
+        assert_eq!(
+            operation["parameters"][0]["schema"],
+            json!({"type": "integer", "format": "int64"})
+        );
+        assert_eq!(
+            operation["parameters"][1]["schema"],
+            json!({"type": "string"})
+        );
+        assert_eq!(operation["requestBody"]["description"], "The post");
+        assert_eq!(
+            operation["requestBody"]["content"]["application/json"]["schema"],
+            post
+        );
+
+        // Undocumented bodies keep the placeholder
+        let create = &spec["paths"]["/users"]["post"];
+        assert!(create["requestBody"].get("description").is_none());
+        assert!(create["requestBody"]["content"]["application/json"]["example"].is_object());
+    }
+
+    #[test]
+    fn test_param_schema_inferred_from_constraint() {
+        let routes = vec![(
+            "GET".to_string(),
+            r"/users/:id(\d+)/posts/:slug".to_string(),
+        )];
+
+        let spec: Value =
+            serde_json::from_str(&generate_swagger_json(&routes, &HashMap::new())).unwrap();
+        let operation = &spec["paths"]["/users/{id}/posts/{slug}"]["get"];
+        assert_eq!(operation["parameters"][0]["name"], "id");
+        assert_eq!(
+            operation["parameters"][0]["schema"],
+            json!({"type": "integer"})
+        );
+        assert_eq!(operation["parameters"][1]["name"], "slug");
+        assert_eq!(
+            operation["parameters"][1]["schema"],
+            json!({"type": "string"})
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_schema_mismatches() {
+        let schema = json!({
+            "type": "object",
+            "required": ["id", "name"],
+            "properties": {
+                "id": { "type": "integer" },
+                "score": { "type": "number" },
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "status": { "enum": ["active", "banned"] }
+            }
+        });
+        let valid = json!({
+            "id": 1, "name": "ada", "score": 3, "tags": ["x"], "status": "active"
+        });
+        assert!(schema_mismatches(&schema, &valid, "$").is_empty());
+
+        let invalid = json!({ "id": "1", "tags": ["x", 2], "status": "gone" });
+        assert_eq!(
+            schema_mismatches(&schema, &invalid, "$"),
+            [
+                "$: missing required field `name`",
+                "$.id: expected integer, found string",
+                "$.status: \"gone\" is not one of [\"active\",\"banned\"]",
+                "$.tags[1]: expected string, found integer",
+            ]
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    async fn test_mismatched_response_is_reported() {
+        let mut router = crate::Router::new();
+        let schema = json!({
+            "type": "object",
+            "properties": { "id": { "type": "integer" } }
+        });
+        router.set_doc(
+            "GET",
+            "/users/:id",
+            RouteDoc::new().response_schema(200, "A user", schema),
+        );
+        router.add_route(
+            "GET",
+            "/users/:id",
+            Box::new(|ctx: crate::RequestCtx| async move {
+                json!({ "id": ctx.get_param("id").cloned() })
+            }),
+        );
+
+        let request = hyper::Request::get("/users/7").body("").unwrap();
+        let ctx = crate::RequestCtx::new(request, Default::default());
+        let response = router.handle_request(ctx).await;
+        let log = response
+            .extensions()
+            .get::<crate::LogContext>()
+            .unwrap()
+            .clone();
+        let mismatch = log.get::<SchemaMismatch>().unwrap();
+        assert_eq!(mismatch.0, ["$.id: expected integer, found string"]);
+
+        // The body still reaches the client
+        use http_body_util::BodyExt;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"{"id":"7"}"#);
+    }
+}
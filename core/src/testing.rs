@@ -0,0 +1,332 @@
+//! Running requests against an engine in-process, for tests.
+//!
+//! [`TestClient`] sends requests straight into an [`Engine`]'s routing, with
+//! its middleware and settings, without binding a socket. The answer comes
+//! back as a [`RecordedResponse`] whose body is already read, so assertions
+//! don't have to await anything. Requests can be written out as a
+//! [`hyper::Request`] or put together with a [`TestRequest`]:
+//!
+//! ```rust
+//! use ree::{Engine, RequestCtx};
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let mut app = Engine::new();
+//! app.get("/users/:id", |ctx: RequestCtx| async move {
+//!     format!("user {}", ctx.param_str("id").unwrap())
+//! });
+//! let client = app.test();
+//!
+//! let response = client.get("/users/1").header("Accept", "text/plain").send().await;
+//! assert_eq!(response.text(), "user 1");
+//! # });
+//! ```
+
+use std::{
+    future::{Future, IntoFuture},
+    pin::Pin,
+};
+
+use http_body_util::BodyExt;
+use hyper::{
+    HeaderMap, Method,
+    body::Bytes,
+    header::{HeaderName, HeaderValue},
+};
+
+use crate::{Engine, RequestCtx, engine::App};
+
+/// An engine handling requests in-process, see [`TestClient::oneshot`]
+pub struct TestClient {
+    app: App,
+}
+
+/// A request being put together for a [`TestClient`]
+///
+/// Sent with [`TestRequest::send`], or by awaiting it directly. Invalid header
+/// names or values panic, as a test would fail on them anyway.
+#[must_use = "a test request does nothing until sent"]
+pub struct TestRequest<'a> {
+    client: &'a TestClient,
+    method: Method,
+    uri: String,
+    query: Vec<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+/// A response with its body fully buffered
+#[derive(Clone, Debug)]
+pub struct RecordedResponse {
+    status: hyper::StatusCode,
+    headers: hyper::HeaderMap,
+    body: Bytes,
+}
+
+impl TestClient {
+    /// Serve `engine`'s routes to this client only
+    pub fn new(engine: Engine) -> Self {
+        Self {
+            app: App::new(engine),
+        }
+    }
+
+    /// Handle `request` as if it arrived on a connection and record the response
+    ///
+    /// ```rust
+    /// use ree::{Engine, RequestCtx, TestClient};
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let mut app = Engine::new();
+    /// app.post("/echo", |ctx: RequestCtx| async move {
+    ///     ctx.body_string().unwrap().unwrap_or_default()
+    /// });
+    /// let client = TestClient::new(app);
+    ///
+    /// let request = hyper::Request::post("/echo").body("hi").unwrap();
+    /// let response = client.oneshot(request).await;
+    /// assert_eq!(response.status(), 200);
+    /// assert_eq!(response.text(), "hi");
+    /// # });
+    /// ```
+    pub async fn oneshot<B: Into<Bytes>>(&self, request: hyper::Request<B>) -> RecordedResponse {
+        let (parts, body) = request.into_parts();
+        let response = self
+            .app
+            .handle(RequestCtx::from_parts(parts, body.into()))
+            .await;
+        let (parts, body) = response.into_parts();
+        let body = match body.collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(err) => panic!("failed to read response body: {err}"),
+        };
+        RecordedResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        }
+    }
+
+    /// Start a `method` request for `uri`
+    pub fn request(&self, method: Method, uri: &str) -> TestRequest<'_> {
+        TestRequest {
+            client: self,
+            method,
+            uri: uri.to_string(),
+            query: Vec::new(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    /// Start a `GET` for `uri`
+    pub fn get(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::GET, uri)
+    }
+
+    /// Start a `POST` to `uri`
+    pub fn post(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::POST, uri)
+    }
+
+    /// Start a `PUT` to `uri`
+    pub fn put(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::PUT, uri)
+    }
+
+    /// Start a `PATCH` to `uri`
+    pub fn patch(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::PATCH, uri)
+    }
+
+    /// Start a `DELETE` for `uri`
+    pub fn delete(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::DELETE, uri)
+    }
+}
+
+impl TestRequest<'_> {
+    /// Add a request header, keeping earlier ones of the same name
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let name = HeaderName::try_from(name).expect("invalid header name");
+        let value = HeaderValue::try_from(value).expect("invalid header value");
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Add a query parameter, percent-encoded after any query already in the URI
+    pub fn query(mut self, name: &str, value: &str) -> Self {
+        self.query.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Send `body` as is
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Send `value` serialized as JSON, with `Content-Type: application/json`
+    pub fn json<T: serde::Serialize>(mut self, value: &T) -> Self {
+        let body = serde_json::to_vec(value).expect("failed to serialize JSON body");
+        self.headers.insert(
+            hyper::header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        self.body = body.into();
+        self
+    }
+
+    /// Send the request and record the response
+    pub async fn send(self) -> RecordedResponse {
+        let mut uri = self.uri;
+        if !self.query.is_empty() {
+            let query = form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(&self.query)
+                .finish();
+            uri.push(if uri.contains('?') { '&' } else { '?' });
+            uri.push_str(&query);
+        }
+        let mut request = hyper::Request::builder()
+            .method(self.method)
+            .uri(uri)
+            .body(self.body)
+            .expect("invalid request URI");
+        *request.headers_mut() = self.headers;
+        self.client.oneshot(request).await
+    }
+}
+
+impl Engine {
+    /// Serve the routes to a [`TestClient`] instead of a socket
+    pub fn test(self) -> TestClient {
+        TestClient::new(self)
+    }
+}
+
+impl<'a> IntoFuture for TestRequest<'a> {
+    type Output = RecordedResponse;
+    type IntoFuture = Pin<Box<dyn Future<Output = RecordedResponse> + Send + 'a>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.send())
+    }
+}
+
+impl RecordedResponse {
+    pub fn status(&self) -> hyper::StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &hyper::HeaderMap {
+        &self.headers
+    }
+
+    /// A header value, `None` when missing or not valid text
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// The raw body
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The body as text, with invalid UTF-8 replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// The body deserialized from JSON
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntoResponse;
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct User {
+        id: u32,
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn test_recorded_json_response() {
+        let mut app = Engine::new();
+        app.get("/users/:id", |ctx: RequestCtx| async move {
+            let id: u32 = ctx.get_param("id").unwrap().parse().unwrap();
+            serde_json::json!({ "id": id, "name": "Ada" })
+        });
+        let client = TestClient::new(app);
+
+        let recorded = client.get("/users/7").await;
+        assert_eq!(recorded.status(), hyper::StatusCode::OK);
+        assert!(
+            recorded
+                .header("content-type")
+                .unwrap()
+                .starts_with("application/json")
+        );
+        assert_eq!(
+            recorded.json::<User>().unwrap(),
+            User {
+                id: 7,
+                name: "Ada".to_string()
+            }
+        );
+
+        let missing = client.get("/nope").await;
+        assert_eq!(missing.status(), hyper::StatusCode::NOT_FOUND);
+        assert!(missing.json::<User>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_builder() {
+        let mut app = Engine::new();
+        app.get("/users/:id", |ctx: RequestCtx| async move {
+            format!(
+                "{} {} {:?}",
+                ctx.param_str("id").unwrap(),
+                ctx.header("accept").unwrap_or_default(),
+                ctx.query_all("tag"),
+            )
+        });
+        app.post("/users", |ctx: RequestCtx| async move {
+            let user: User = ctx.json().unwrap();
+            let mut response =
+                serde_json::json!({ "id": user.id + 1, "name": user.name }).into_response();
+            *response.status_mut() = hyper::StatusCode::CREATED;
+            response
+        });
+        let client = app.test();
+
+        let recorded = client
+            .get("/users/1?tag=a")
+            .header("Accept", "application/json")
+            .query("tag", "b c")
+            .send()
+            .await;
+        assert_eq!(recorded.text(), r#"1 application/json ["a", "b c"]"#);
+
+        let created = client
+            .post("/users")
+            .json(&serde_json::json!({ "id": 1, "name": "Ada" }))
+            .await;
+        assert_eq!(created.status(), hyper::StatusCode::CREATED);
+        assert_eq!(
+            created.json::<User>().unwrap(),
+            User {
+                id: 2,
+                name: "Ada".to_string()
+            }
+        );
+
+        let wrong_method = client.delete("/users/1").await;
+        assert_eq!(wrong_method.status(), hyper::StatusCode::METHOD_NOT_ALLOWED);
+        let raw = client.put("/users").body("not json").send().await;
+        assert_eq!(raw.status(), hyper::StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
@@ -0,0 +1,94 @@
+//! `Server-Timing` headers for browser devtools.
+//!
+//! The [`server_timing`] middleware measures how long the rest of the chain
+//! takes and reports it as a `total` metric. Handlers and later middleware can
+//! add their own metrics with [`RequestCtx::record_timing`], e.g. the time spent
+//! on a database query.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{Next, RequestCtx, Response};
+
+/// Metrics recorded for the current request, shared with the middleware
+#[derive(Clone, Default)]
+struct Timings(Arc<Mutex<Vec<(String, Duration)>>>);
+
+/// One `Server-Timing` metric, durations in milliseconds
+fn metric(name: &str, duration: Duration) -> String {
+    format!("{name};dur={:.1}", duration.as_secs_f64() * 1000.0)
+}
+
+impl RequestCtx {
+    /// Report `duration` under `name` in the `Server-Timing` header
+    ///
+    /// `name` must be a header token such as `db` or `cache-read`. Does nothing
+    /// unless the [`server_timing`] middleware runs for this request.
+    pub fn record_timing(&self, name: &str, duration: Duration) {
+        if let Some(timings) = self.request.extensions().get::<Timings>() {
+            timings.0.lock().unwrap().push((name.to_string(), duration));
+        }
+    }
+}
+
+/// Add a `Server-Timing` header with the time taken by the rest of the chain
+///
+/// The header lists the metrics recorded with [`RequestCtx::record_timing`],
+/// in recording order, followed by `total`:
+/// `Server-Timing: db;dur=12.5, total;dur=20.3`.
+pub fn server_timing()
+-> impl Fn(RequestCtx, Next) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync + 'static
+{
+    |mut ctx, next| {
+        Box::pin(async move {
+            let timings = Timings::default();
+            ctx.request.extensions_mut().insert(timings.clone());
+
+            let started = Instant::now();
+            let mut response = next(ctx).await;
+            let total = started.elapsed();
+
+            let mut metrics: Vec<String> = timings
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, duration)| metric(name, *duration))
+                .collect();
+            metrics.push(metric("total", total));
+            if let Ok(value) = hyper::header::HeaderValue::from_str(&metrics.join(", ")) {
+                response.headers_mut().append("server-timing", value);
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntoNext;
+
+    #[tokio::test]
+    async fn test_server_timing_header() {
+        let endpoint = (|ctx: RequestCtx| async move {
+            ctx.record_timing("db", Duration::from_micros(12_500));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            crate::ResponseBuilder::new().body("ok")
+        })
+        .into_next();
+
+        let request = hyper::Request::get("/").body("").unwrap();
+        let response =
+            server_timing()(RequestCtx::new(request, Default::default()), endpoint).await;
+        let header = response.headers()["server-timing"].to_str().unwrap();
+        let (db, total) = header.split_once(", ").unwrap();
+        assert_eq!(db, "db;dur=12.5");
+        let total: f64 = total.strip_prefix("total;dur=").unwrap().parse().unwrap();
+        assert!(total >= 5.0, "{header}");
+    }
+}
@@ -0,0 +1,215 @@
+//! W3C Trace Context (`traceparent` / `tracestate`) propagation.
+//!
+//! [`RequestCtx::trace_context`] reads the trace a request belongs to, or
+//! starts a new one when the caller sent none, so log lines and outgoing calls
+//! can be correlated across services. Tasks started with [`RequestCtx::spawn`]
+//! see the same trace through [`current_trace_context`].
+
+use std::fmt::Write;
+
+use rand::RngCore;
+
+use crate::RequestCtx;
+
+/// Header carrying the trace id, parent span id and flags
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Header carrying vendor-specific trace data
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// The trace a request is part of, see [`RequestCtx::trace_context`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits, shared by every span of the trace
+    pub trace_id: String,
+    /// 16 lowercase hex digits identifying the caller's span
+    pub parent_id: String,
+    /// Trace flags, bit `0x01` meaning sampled
+    pub flags: u8,
+    /// The `tracestate` header, passed on unchanged
+    pub tracestate: Option<String>,
+}
+
+tokio::task_local! {
+    static CURRENT: TraceContext;
+}
+
+/// The trace of the request the current task was spawned for
+///
+/// Set inside tasks started with [`RequestCtx::spawn`]; `None` anywhere else.
+pub fn current_trace_context() -> Option<TraceContext> {
+    CURRENT.try_with(Clone::clone).ok()
+}
+
+/// `bytes` random bytes as lowercase hex
+fn random_hex(bytes: usize) -> String {
+    let mut id = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut id);
+    id.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Whether `value` is `len` lowercase hex digits, not all zero
+fn valid_id(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+        && value.bytes().any(|byte| byte != b'0')
+}
+
+impl TraceContext {
+    /// Start a new sampled trace, for requests that arrive without one
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: random_hex(16),
+            parent_id: random_hex(8),
+            flags: 0x01,
+            tracestate: None,
+        }
+    }
+
+    /// Parse a `traceparent` header value, with the request's `tracestate` if any
+    ///
+    /// Returns `None` for invalid values, including the forbidden version `ff`
+    /// and all-zero ids. Versions after `00` may carry extra fields, which are
+    /// ignored.
+    pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<Self> {
+        let mut fields = traceparent.trim().split('-');
+        let version = fields.next()?;
+        let (trace_id, parent_id, flags) = (fields.next()?, fields.next()?, fields.next()?);
+        let extra = fields.next().is_some();
+
+        let version = u8::from_str_radix(version, 16)
+            .ok()
+            .filter(|_| version.len() == 2)?;
+        if version == 0xff || (version == 0 && extra) {
+            return None;
+        }
+        if !valid_id(trace_id, 32) || !valid_id(parent_id, 16) || flags.len() != 2 {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+            flags: u8::from_str_radix(flags, 16).ok()?,
+            tracestate: tracestate
+                .map(str::trim)
+                .filter(|state| !state.is_empty())
+                .map(str::to_string),
+        })
+    }
+
+    /// Whether the caller decided to record this trace
+    pub fn sampled(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    /// The same trace with a new span id, to send on an outgoing request
+    pub fn child(&self) -> Self {
+        Self {
+            parent_id: random_hex(8),
+            ..self.clone()
+        }
+    }
+
+    /// The `traceparent` header value, e.g.
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.parent_id, self.flags)
+    }
+
+    /// Run `future` with this as its [`current_trace_context`]
+    pub(crate) fn scope<F: std::future::Future>(
+        self,
+        future: F,
+    ) -> impl std::future::Future<Output = F::Output> {
+        CURRENT.scope(self, future)
+    }
+}
+
+impl RequestCtx {
+    /// The trace this request belongs to
+    ///
+    /// Parsed from `traceparent` and `tracestate`; when the header is missing or
+    /// invalid a new trace is started. The result is the same for every call on
+    /// one request.
+    pub fn trace_context(&self) -> &TraceContext {
+        self.trace.get_or_init(|| {
+            self.header(TRACEPARENT_HEADER)
+                .and_then(|traceparent| {
+                    TraceContext::parse(traceparent, self.header(TRACESTATE_HEADER))
+                })
+                .unwrap_or_else(TraceContext::new_root)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_headers(headers: &[(&str, &str)]) -> RequestCtx {
+        let mut request = hyper::Request::get("/");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        RequestCtx::new(request.body("").unwrap(), Default::default())
+    }
+
+    #[test]
+    fn test_parse_valid_traceparent() {
+        let ctx = with_headers(&[
+            (
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ),
+            ("tracestate", "congo=t61rcWkgMzE"),
+        ]);
+        let trace = ctx.trace_context();
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(trace.parent_id, "00f067aa0ba902b7");
+        assert!(trace.sampled());
+        assert_eq!(trace.tracestate.as_deref(), Some("congo=t61rcWkgMzE"));
+        assert_eq!(
+            trace.traceparent(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+
+        let child = trace.child();
+        assert_eq!(child.trace_id, trace.trace_id);
+        assert_ne!(child.parent_id, trace.parent_id);
+    }
+
+    #[test]
+    fn test_invalid_or_missing_traceparent_starts_new_trace() {
+        for invalid in [
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736",
+        ] {
+            assert_eq!(TraceContext::parse(invalid, None), None, "{invalid}");
+        }
+
+        let ctx = with_headers(&[]);
+        let trace = ctx.trace_context().clone();
+        assert!(valid_id(&trace.trace_id, 32) && valid_id(&trace.parent_id, 16));
+        assert_eq!(ctx.trace_context(), &trace);
+        assert_ne!(with_headers(&[]).trace_context().trace_id, trace.trace_id);
+    }
+
+    #[tokio::test]
+    async fn test_spawned_task_sees_trace_context() {
+        let ctx = with_headers(&[(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+        )]);
+        let trace = ctx.spawn(async { current_trace_context() }).await.unwrap();
+        assert_eq!(trace.as_ref(), Some(ctx.trace_context()));
+        assert_eq!(current_trace_context(), None);
+    }
+}
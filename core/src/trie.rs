@@ -1,24 +1,129 @@
 //! Trie data structure for efficient route matching.
+//!
+//! This is the tree behind [`Engine`](crate::Engine) routing, usable on its own
+//! for custom dispatch. A pattern such as `/p/:lang/doc` is split on `/` into
+//! parts (`["p", ":lang", "doc"]`, empty segments dropped), and each part
+//! becomes one level of the tree:
+//!
+//! - a plain part (`doc`) only matches the identical path segment;
+//! - a `:name` part matches any single segment, and `:name(\d+)` only one
+//!   made of ASCII digits (see [`Constraint`]);
+//! - a `*name` part matches one or more segments. It usually ends the pattern,
+//!   but more parts may follow (`/files/*path/meta`): the wildcard then takes
+//!   as many segments as it can while leaving enough for the rest to match.
+//!
+//! At each level children are tried in insertion order, so a static part
+//! registered after a wildcard sibling is shadowed by it.
+//!
+//! ```
+//! use ree::trie::Node;
+//!
+//! let mut root = Node::new();
+//! root.insert("/p/:lang/doc", vec!["p", ":lang", "doc"], 0);
+//! root.insert("/static/*filepath", vec!["static", "*filepath"], 0);
+//!
+//! let node = root.search(&["p", "rust", "doc"], 0).unwrap();
+//! assert_eq!(node.pattern, "/p/:lang/doc");
+//!
+//! let node = root.search(&["static", "js", "app.js"], 0).unwrap();
+//! assert_eq!(node.pattern, "/static/*filepath");
+//!
+//! // Prefixes of a pattern aren't matches on their own
+//! assert!(root.search(&["p", "rust"], 0).is_none());
+//!
+//! root.insert("/files/*path/meta", vec!["files", "*path", "meta"], 0);
+//! let node = root.search(&["files", "a", "b", "meta"], 0).unwrap();
+//! assert_eq!(node.pattern, "/files/*path/meta");
+//! assert!(root.search(&["files", "meta"], 0).is_none());
+//! ```
 
+/// What a `:name(...)` part requires of the segment it captures
+///
+/// Only the forms listed here are recognized; they are spelled like the
+/// regular expressions they stand for, but no regex engine is involved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Constraint {
+    /// `(\d+)`: one or more ASCII digits
+    Digits,
+}
+
+impl Constraint {
+    fn parse(source: &str) -> Option<Self> {
+        match source {
+            r"\d+" => Some(Constraint::Digits),
+            _ => None,
+        }
+    }
+
+    /// Whether `segment` satisfies the constraint
+    pub fn matches(self, segment: &str) -> bool {
+        match self {
+            Constraint::Digits => {
+                !segment.is_empty() && segment.bytes().all(|byte| byte.is_ascii_digit())
+            }
+        }
+    }
+}
+
+/// Split a pattern part such as `:id(\d+)` into its parameter name and constraint
+///
+/// Parts without a `(...)` suffix are returned unchanged.
+///
+/// # Panics
+///
+/// When the suffix isn't a [`Constraint`] this module recognizes.
+///
+/// ```
+/// use ree::trie::{Constraint, split_constraint};
+///
+/// assert_eq!(split_constraint(r":id(\d+)"), (":id", Some(Constraint::Digits)));
+/// assert_eq!(split_constraint(":slug"), (":slug", None));
+/// ```
+pub fn split_constraint(part: &str) -> (&str, Option<Constraint>) {
+    let Some((name, source)) = part.strip_suffix(')').and_then(|rest| rest.split_once('(')) else {
+        return (part, None);
+    };
+    match Constraint::parse(source) {
+        Some(constraint) => (name, Some(constraint)),
+        None => {
+            panic!("route part {part} has an unsupported constraint; only (\\d+) is recognized")
+        }
+    }
+}
+
+/// A node in the routing trie
 #[derive(Default, Debug)]
 pub struct Node {
+    /// Full pattern registered at this node, empty if none ends here
     pub pattern: String,
+    /// Pattern part matched at this level (`doc`, `:lang` or `*filepath`)
     pub part: String,
+    /// Child nodes, tried in insertion order
     pub children: Vec<Node>,
+    /// Whether `part` is a `:` or `*` wildcard
     pub iswild: bool,
+    /// What a `:name(...)` part requires of the segment it matches
+    pub constraint: Option<Constraint>,
 }
 
 impl Node {
-    #[allow(dead_code)]
+    /// Create an empty root node
     pub fn new() -> Self {
         Node::default()
     }
 
+    /// Whether this node's part accepts the path segment `path`
+    fn accepts(&self, path: &str) -> bool {
+        self.part == path
+            || (self.iswild
+                && self
+                    .constraint
+                    .is_none_or(|constraint| constraint.matches(path)))
+    }
+
     #[allow(dead_code)]
     fn match_child(&self, path: &str) -> Option<&Node> {
-        self.children
-            .iter()
-            .find(|child| child.part == path || child.iswild)
+        self.children.iter().find(|child| child.accepts(path))
     }
 
     fn match_child_mut(&mut self, path: &str) -> Option<&mut Node> {
@@ -28,10 +133,22 @@ impl Node {
     fn match_children(&self, path: &str) -> Vec<&Node> {
         self.children
             .iter()
-            .filter(|&child| child.part == path || child.iswild)
+            .filter(|&child| child.accepts(path))
             .collect()
     }
 
+    /// Register `pattern`, whose split `parts` are inserted from depth `height`
+    ///
+    /// Pass `0` as `height` when inserting into a root.
+    ///
+    /// ```
+    /// use ree::trie::Node;
+    ///
+    /// let mut root = Node::new();
+    /// root.insert("/users/:id", vec!["users", ":id"], 0);
+    /// assert_eq!(root.children[0].part, "users");
+    /// assert!(root.children[0].children[0].iswild);
+    /// ```
     pub fn insert(&mut self, pattern: &str, parts: Vec<&str>, height: usize) {
         if height == parts.len() {
             self.pattern = pattern.to_string();
@@ -47,14 +164,75 @@ impl Node {
                 part: part.to_string(),
                 children: Vec::new(),
                 iswild: part.starts_with(':') || part.starts_with('*'),
+                constraint: split_constraint(part).1,
             };
             new_node.insert(pattern, parts, height + 1);
             self.children.push(new_node);
         }
     }
 
+    /// An already registered pattern that `parts` would be ambiguous with
+    ///
+    /// That's one with a differently named wildcard of the same kind at the
+    /// same position, as `/users/:name` has with `/users/:id`: both capture the
+    /// same segment, and the one inserted later could never match. A pattern
+    /// with the very same parts, like `/users/` for `/users`, is one too.
+    ///
+    /// ```
+    /// use ree::trie::Node;
+    ///
+    /// let mut root = Node::new();
+    /// root.insert("/users/:id", vec!["users", ":id"], 0);
+    /// assert_eq!(root.conflict(&["users", ":name"], 0), Some("/users/:id"));
+    /// assert_eq!(root.conflict(&["users", ":id"], 0), Some("/users/:id"));
+    /// assert_eq!(root.conflict(&["users", ":id", "posts"], 0), None);
+    /// assert_eq!(root.conflict(&["users", "me"], 0), None);
+    /// ```
+    pub fn conflict(&self, parts: &[&str], height: usize) -> Option<&str> {
+        let Some(part) = parts.get(height) else {
+            return (!self.pattern.is_empty()).then_some(self.pattern.as_str());
+        };
+        if part.starts_with(':') || part.starts_with('*') {
+            let rival = self
+                .children
+                .iter()
+                .find(|child| child.iswild && child.part != *part && child.part[..1] == part[..1]);
+            if let Some(rival) = rival {
+                return rival.any_pattern();
+            }
+        }
+        self.children
+            .iter()
+            .find(|child| child.part == *part)
+            .and_then(|child| child.conflict(parts, height + 1))
+    }
+
+    /// A pattern registered at or below this node, the shallowest first
+    fn any_pattern(&self) -> Option<&str> {
+        if !self.pattern.is_empty() {
+            return Some(&self.pattern);
+        }
+        self.children.iter().find_map(Node::any_pattern)
+    }
+
+    /// Find the node whose pattern matches the path segments `parts`
+    ///
+    /// Returns `None` when no registered pattern matches. The captured values
+    /// aren't returned; compare the returned node's `pattern` with `parts` to
+    /// extract them.
+    ///
+    /// ```
+    /// use ree::trie::Node;
+    ///
+    /// let mut root = Node::new();
+    /// root.insert("/users/:id", vec!["users", ":id"], 0);
+    ///
+    /// let node = root.search(&["users", "42"], 0).unwrap();
+    /// assert_eq!(node.pattern, "/users/:id");
+    /// assert!(root.search(&["users", "42", "posts"], 0).is_none());
+    /// ```
     pub fn search(&self, parts: &[&str], height: usize) -> Option<&Node> {
-        if height == parts.len() || self.part.starts_with('*') {
+        if height == parts.len() {
             return if self.pattern.is_empty() {
                 None
             } else {
@@ -64,14 +242,102 @@ impl Node {
 
         let part = &parts[height];
         for child in self.match_children(part) {
-            if let Some(result) = child.search(parts, height + 1) {
-                return Some(result);
+            // A `*` child takes one or more segments, as many as possible first
+            let ends = if child.part.starts_with('*') {
+                height + 1..parts.len() + 1
+            } else {
+                height + 1..height + 2
+            };
+            for end in ends.rev() {
+                if let Some(result) = child.search(parts, end) {
+                    return Some(result);
+                }
             }
         }
         None
     }
 
+    /// Collect every node whose pattern matches `parts`, in the order
+    /// [`Node::search`] tries them, so the first one is the node it returns
+    ///
+    /// ```
+    /// use ree::trie::Node;
+    ///
+    /// let mut root = Node::new();
+    /// root.insert("/users/:id", vec!["users", ":id"], 0);
+    /// root.insert("/users/me", vec!["users", "me"], 0);
+    ///
+    /// let mut matches = Vec::new();
+    /// root.search_all(&["users", "me"], 0, &mut matches);
+    /// let patterns: Vec<_> = matches.iter().map(|node| node.pattern.as_str()).collect();
+    /// assert_eq!(patterns, ["/users/:id", "/users/me"]);
+    /// ```
+    pub fn search_all<'a>(&'a self, parts: &[&str], height: usize, matches: &mut Vec<&'a Node>) {
+        if height == parts.len() {
+            if !self.pattern.is_empty() && !matches.iter().any(|node| std::ptr::eq(*node, self)) {
+                matches.push(self);
+            }
+            return;
+        }
+
+        let part = &parts[height];
+        for child in self.match_children(part) {
+            let ends = if child.part.starts_with('*') {
+                height + 1..parts.len() + 1
+            } else {
+                height + 1..height + 2
+            };
+            for end in ends.rev() {
+                child.search_all(parts, end, matches);
+            }
+        }
+    }
+
+    /// Remove the pattern registered under `parts`, pruning nodes left without
+    /// a pattern or children. Returns whether a pattern was removed.
+    pub fn remove(&mut self, parts: &[&str], height: usize) -> bool {
+        if height == parts.len() {
+            if self.pattern.is_empty() {
+                return false;
+            }
+            self.pattern.clear();
+            return true;
+        }
+
+        let Some(index) = self
+            .children
+            .iter()
+            .position(|child| child.part == parts[height])
+        else {
+            return false;
+        };
+
+        let removed = self.children[index].remove(parts, height + 1);
+        let child = &self.children[index];
+        if removed && child.pattern.is_empty() && child.children.is_empty() {
+            self.children.remove(index);
+        }
+        removed
+    }
+
+    /// Count this node and all of its descendants
+    pub fn node_count(&self) -> usize {
+        1 + self.children.iter().map(Node::node_count).sum::<usize>()
+    }
+
     /// Collect all patterns from this node and its children
+    ///
+    /// ```
+    /// use ree::trie::Node;
+    ///
+    /// let mut root = Node::new();
+    /// root.insert("/a", vec!["a"], 0);
+    /// root.insert("/a/:b", vec!["a", ":b"], 0);
+    ///
+    /// let mut patterns = Vec::new();
+    /// root.collect_patterns(&mut patterns);
+    /// assert_eq!(patterns, ["/a", "/a/:b"]);
+    /// ```
     pub fn collect_patterns(&self, patterns: &mut Vec<String>) {
         if !self.pattern.is_empty() {
             patterns.push(self.pattern.clone());
@@ -123,6 +389,23 @@ mod tests {
         assert!(patterns.contains(&"/p/:lang/doc".to_string()));
         assert!(patterns.contains(&"/p/go/doc".to_string()));
     }
+    #[test]
+    fn test_remove_prunes_dead_nodes() {
+        let mut root = Node::new();
+        root.insert("/p/:lang/doc", vec!["p", ":lang", "doc"], 0);
+        root.insert("/p/:lang/doc/intro", vec!["p", ":lang", "doc", "intro"], 0);
+        assert_eq!(root.node_count(), 5);
+
+        assert!(root.remove(&["p", ":lang", "doc", "intro"], 0));
+        assert_eq!(root.node_count(), 4);
+        assert!(root.search(&["p", "rust", "doc"], 0).is_some());
+
+        // Removing an unknown pattern or one that's already gone is a no-op
+        assert!(!root.remove(&["p", ":lang", "doc", "intro"], 0));
+        assert!(root.remove(&["p", ":lang", "doc"], 0));
+        assert_eq!(root.node_count(), 1);
+    }
+
     #[test]
     fn test_wildcard_search() {
         let mut root = Node::new();
@@ -132,4 +415,36 @@ mod tests {
         assert!(result.is_some());
         assert_eq!(result.unwrap().pattern, "/static/*filepath");
     }
+
+    #[test]
+    fn test_wildcard_followed_by_parts() {
+        let mut root = Node::new();
+        root.insert("/files/*path/meta", vec!["files", "*path", "meta"], 0);
+        root.insert(
+            "/repos/*path/blob/:rev",
+            vec!["repos", "*path", "blob", ":rev"],
+            0,
+        );
+
+        let matches = |parts: &[&str]| root.search(parts, 0).map(|node| node.pattern.as_str());
+        assert_eq!(matches(&["files", "a", "meta"]), Some("/files/*path/meta"));
+        assert_eq!(
+            matches(&["files", "a", "b", "meta"]),
+            Some("/files/*path/meta")
+        );
+        // The wildcard backtracks past a `meta` segment inside the captured part
+        assert_eq!(
+            matches(&["files", "meta", "b", "meta"]),
+            Some("/files/*path/meta")
+        );
+        assert_eq!(
+            matches(&["repos", "org", "app", "blob", "v1"]),
+            Some("/repos/*path/blob/:rev")
+        );
+
+        assert_eq!(matches(&["files", "meta"]), None);
+        assert_eq!(matches(&["files", "a", "b"]), None);
+        assert_eq!(matches(&["files", "a", "meta", "extra"]), None);
+        assert_eq!(matches(&["repos", "org", "blob"]), None);
+    }
 }
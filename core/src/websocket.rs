@@ -0,0 +1,317 @@
+//! WebSocket opening handshakes.
+//!
+//! [`RequestCtx::upgrade_websocket`] validates an upgrade request, picks a
+//! subprotocol and answers `101 Switching Protocols`. The connection is then
+//! handed to a callback as a raw byte stream; framing is left to the
+//! application or a WebSocket library.
+
+use std::future::Future;
+
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use hyper::{StatusCode, header, upgrade::Upgraded};
+use hyper_util::rt::TokioIo;
+use sha1::{Digest, Sha1};
+
+use crate::{RequestCtx, Response, ResponseBuilder};
+
+/// Appended to the client's key before hashing it (RFC 6455, 1.3)
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Why [`RequestCtx::upgrade_websocket`] refused the handshake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketRejection {
+    /// Not a WebSocket upgrade request, answered with `426 Upgrade Required`
+    NotUpgrade,
+    /// A `Sec-WebSocket-Version` other than 13, answered with `426 Upgrade Required`
+    UnsupportedVersion,
+    /// None of the client's subprotocols is supported though one is required,
+    /// answered with `400 Bad Request`
+    NoCommonProtocol(Vec<String>),
+}
+
+impl WebSocketRejection {
+    /// The status the rejection is answered with
+    pub fn status(&self) -> StatusCode {
+        match self {
+            WebSocketRejection::NotUpgrade | WebSocketRejection::UnsupportedVersion => {
+                StatusCode::UPGRADE_REQUIRED
+            }
+            WebSocketRejection::NoCommonProtocol(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl std::fmt::Display for WebSocketRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebSocketRejection::NotUpgrade => write!(f, "Expected a WebSocket upgrade request"),
+            WebSocketRejection::UnsupportedVersion => {
+                write!(f, "Unsupported WebSocket version, expected 13")
+            }
+            WebSocketRejection::NoCommonProtocol(offered) if offered.is_empty() => {
+                write!(f, "A WebSocket subprotocol is required")
+            }
+            WebSocketRejection::NoCommonProtocol(offered) => write!(
+                f,
+                "None of the WebSocket subprotocols {} is supported",
+                offered.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WebSocketRejection {}
+
+impl crate::IntoResponse for WebSocketRejection {
+    fn into_response(self) -> Response {
+        let rejection = crate::Rejection::new(self.status(), self.to_string());
+        let mut response = crate::IntoResponse::into_response(rejection);
+        if self.status() == StatusCode::UPGRADE_REQUIRED {
+            let headers = response.headers_mut();
+            headers.insert(
+                header::UPGRADE,
+                header::HeaderValue::from_static("websocket"),
+            );
+            headers.insert(
+                header::CONNECTION,
+                header::HeaderValue::from_static("upgrade"),
+            );
+            headers.insert(
+                header::SEC_WEBSOCKET_VERSION,
+                header::HeaderValue::from_static("13"),
+            );
+        }
+        response
+    }
+}
+
+/// An accepted WebSocket handshake, see [`RequestCtx::upgrade_websocket`]
+pub struct WebSocketUpgrade {
+    accept: String,
+    protocol: Option<String>,
+    on_upgrade: Option<hyper::upgrade::OnUpgrade>,
+}
+
+impl WebSocketUpgrade {
+    /// The negotiated subprotocol, echoed in `Sec-WebSocket-Protocol`
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Answer `101 Switching Protocols` and run `callback` on the connection
+    ///
+    /// `callback` is spawned once the response has been sent, with the
+    /// connection as a [`TokioIo`] stream speaking WebSocket frames. It isn't
+    /// called when the request didn't come over a connection that can be
+    /// upgraded, e.g. in a [`TestClient`](crate::TestClient).
+    pub fn on_upgrade<F, Fut>(self, callback: F) -> Response
+    where
+        F: FnOnce(TokioIo<Upgraded>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut response = ResponseBuilder::new()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::UPGRADE.as_str(), "websocket")
+            .header(header::CONNECTION.as_str(), "upgrade")
+            .header(header::SEC_WEBSOCKET_ACCEPT.as_str(), self.accept.as_str())
+            .empty_body();
+        if let Some(protocol) = &self.protocol
+            && let Ok(value) = header::HeaderValue::from_str(protocol)
+        {
+            response
+                .headers_mut()
+                .insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+        }
+        if let Some(on_upgrade) = self.on_upgrade {
+            tokio::spawn(async move {
+                match on_upgrade.await {
+                    Ok(upgraded) => callback(TokioIo::new(upgraded)).await,
+                    Err(err) => eprintln!("WebSocket upgrade failed: {err}"),
+                }
+            });
+        }
+        response
+    }
+}
+
+/// The `Sec-WebSocket-Accept` value for a client's `Sec-WebSocket-Key`
+fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(ACCEPT_GUID.as_bytes());
+    STANDARD.encode(sha1.finalize())
+}
+
+impl RequestCtx {
+    /// Accept a WebSocket upgrade, negotiating one of the `supported` subprotocols
+    ///
+    /// The first protocol in the client's `Sec-WebSocket-Protocol` that is also
+    /// in `supported` is picked. When there's none, the handshake goes ahead
+    /// without a protocol unless `require_protocol` is set, in which case it
+    /// fails with [`WebSocketRejection::NoCommonProtocol`]. Returned from a
+    /// handler with `?`, a rejection is answered with its status.
+    ///
+    /// ```rust
+    /// use ree::{Engine, RequestCtx, WebSocketRejection};
+    /// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    ///
+    /// let mut app = Engine::new();
+    /// app.get("/ws", |mut ctx: RequestCtx| async move {
+    ///     let upgrade = ctx.upgrade_websocket(&["chat.v2", "chat.v1"], true)?;
+    ///     Ok::<_, WebSocketRejection>(upgrade.on_upgrade(|mut socket| async move {
+    ///         let mut frame = [0; 1024];
+    ///         while let Ok(read @ 1..) = socket.read(&mut frame).await {
+    ///             let _ = socket.write_all(&frame[..read]).await;
+    ///         }
+    ///     }))
+    /// });
+    /// ```
+    pub fn upgrade_websocket(
+        &mut self,
+        supported: &[&str],
+        require_protocol: bool,
+    ) -> Result<WebSocketUpgrade, WebSocketRejection> {
+        if !self.is_websocket_upgrade() {
+            return Err(WebSocketRejection::NotUpgrade);
+        }
+        if self.header(header::SEC_WEBSOCKET_VERSION.as_str()) != Some("13") {
+            return Err(WebSocketRejection::UnsupportedVersion);
+        }
+        let offered = self.websocket_protocols();
+        let protocol = offered
+            .iter()
+            .find(|protocol| supported.contains(&protocol.as_str()))
+            .cloned();
+        if protocol.is_none() && require_protocol {
+            return Err(WebSocketRejection::NoCommonProtocol(offered));
+        }
+
+        let key = self
+            .header(header::SEC_WEBSOCKET_KEY.as_str())
+            .unwrap_or_default();
+        Ok(WebSocketUpgrade {
+            accept: accept_key(key.trim()),
+            protocol,
+            on_upgrade: self
+                .request
+                .extensions_mut()
+                .remove::<hyper::upgrade::OnUpgrade>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn upgrade_request(protocols: Option<&str>) -> RequestCtx {
+        let mut request = hyper::Request::get("/ws")
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .header("Sec-WebSocket-Version", "13");
+        if let Some(protocols) = protocols {
+            request = request.header("Sec-WebSocket-Protocol", protocols);
+        }
+        RequestCtx::new(request.body("").unwrap(), Default::default())
+    }
+
+    #[test]
+    fn test_negotiates_first_offered_supported_protocol() {
+        let mut ctx = upgrade_request(Some("chat.v3, chat.v1, chat.v2"));
+        let upgrade = ctx
+            .upgrade_websocket(&["chat.v2", "chat.v1"], true)
+            .unwrap();
+        assert_eq!(upgrade.protocol(), Some("chat.v1"));
+
+        let response = upgrade.on_upgrade(|_| async {});
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        // The example handshake of RFC 6455, 1.3
+        assert_eq!(
+            response.headers()["sec-websocket-accept"],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(response.headers()["sec-websocket-protocol"], "chat.v1");
+    }
+
+    #[test]
+    fn test_no_common_protocol() {
+        let mut ctx = upgrade_request(Some("mqtt"));
+        let rejection = ctx.upgrade_websocket(&["chat.v1"], true).err().unwrap();
+        assert_eq!(
+            rejection,
+            WebSocketRejection::NoCommonProtocol(vec!["mqtt".into()])
+        );
+        let response = crate::IntoResponse::into_response(rejection);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Without `require_protocol` the handshake goes ahead without one
+        let upgrade = ctx.upgrade_websocket(&["chat.v1"], false).unwrap();
+        assert_eq!(upgrade.protocol(), None);
+        let response = upgrade.on_upgrade(|_| async {});
+        assert!(!response.headers().contains_key("sec-websocket-protocol"));
+    }
+
+    #[test]
+    fn test_no_protocol_offered() {
+        let mut ctx = upgrade_request(None);
+        let upgrade = ctx.upgrade_websocket(&[], false).unwrap();
+        assert_eq!(upgrade.protocol(), None);
+        let response = upgrade.on_upgrade(|_| async {});
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert!(!response.headers().contains_key("sec-websocket-protocol"));
+
+        let rejection = ctx.upgrade_websocket(&["chat.v1"], true).err().unwrap();
+        assert_eq!(rejection, WebSocketRejection::NoCommonProtocol(Vec::new()));
+
+        let mut plain = RequestCtx::new(
+            hyper::Request::get("/ws").body("").unwrap(),
+            Default::default(),
+        );
+        let rejection = plain.upgrade_websocket(&[], false).err().unwrap();
+        let response = crate::IntoResponse::into_response(rejection);
+        assert_eq!(response.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(response.headers()["upgrade"], "websocket");
+    }
+
+    #[tokio::test]
+    async fn test_upgraded_connection_reaches_callback() {
+        let mut app = crate::Engine::new();
+        app.get("/ws", |mut ctx: RequestCtx| async move {
+            let upgrade = ctx.upgrade_websocket(&["echo"], true)?;
+            Ok::<_, WebSocketRejection>(upgrade.on_upgrade(|mut socket| async move {
+                let mut buffer = [0; 5];
+                socket.read_exact(&mut buffer).await.unwrap();
+                socket.write_all(&buffer).await.unwrap();
+            }))
+        });
+        let bound = app.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = bound.serve().await;
+        });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                b"GET /ws HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\n\
+                  Upgrade: websocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: echo\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+        assert!(head.starts_with("http/1.1 101"), "{head}");
+        assert!(head.contains("sec-websocket-protocol: echo"), "{head}");
+
+        stream.write_all(b"hello").await.unwrap();
+        let mut echoed = [0; 5];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello");
+    }
+}
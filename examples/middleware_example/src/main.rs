@@ -1,4 +1,4 @@
-use ree::{Engine, IntoResponse, Next, RequestCtx, Response, ResponseBuilder};
+use ree::{Engine, IntoResponse, Next, RequestCtx, Response, ResponseBuilder, RouterGroup};
 use serde_json::json;
 use std::{future::Future, pin::Pin, sync::Arc, time::Instant};
 
@@ -73,10 +73,10 @@ async fn timer(name: &'static str, ctx: RequestCtx, next: Next) -> Response {
 #[allow(dead_code)]
 /// 🚀 认证中间件
 async fn auth(token: &'static str, ctx: RequestCtx, next: Next) -> Response {
-    if let Some(auth) = ctx.request.headers().get("Authorization") {
-        if auth.to_str().unwrap_or("") == token {
-            return next(ctx).await;
-        }
+    if let Some(auth) = ctx.request.headers().get("Authorization")
+        && auth.to_str().unwrap_or("") == token
+    {
+        return next(ctx).await;
     }
     (
         ree::StatusCode::UNAUTHORIZED,
@@ -193,17 +193,16 @@ fn extract_user_from_token(token: &str) -> String {
 async fn jwt_require_role(required_role: &'static str, ctx: RequestCtx, next: Next) -> Response {
     // 这个中间件应该在 jwt_auth 之后使用
     // 从 Authorization header 获取并解析角色
-    if let Some(auth_header) = ctx.request.headers().get("Authorization") {
-        if let Ok(auth_str) = auth_header.to_str() {
-            if let Some(token) = auth_str.strip_prefix("Bearer ") {
-                let parts: Vec<&str> = token.split('.').collect();
-                if parts.len() == 3 {
-                    let role = parts[1];
-                    if role == required_role || role == "admin" {
-                        // admin有所有权限
-                        return next(ctx).await;
-                    }
-                }
+    if let Some(auth_header) = ctx.request.headers().get("Authorization")
+        && let Ok(auth_str) = auth_header.to_str()
+        && let Some(token) = auth_str.strip_prefix("Bearer ")
+    {
+        let parts: Vec<&str> = token.split('.').collect();
+        if parts.len() == 3 {
+            let role = parts[1];
+            if role == required_role || role == "admin" {
+                // admin有所有权限
+                return next(ctx).await;
             }
         }
     }
@@ -381,10 +380,10 @@ async fn request_id(ctx: RequestCtx, next: Next) -> Response {
 
 /// 🚀 API密钥验证中间件 - 使用简洁的函数式风格
 async fn api_key_auth(valid_key: &'static str, ctx: RequestCtx, next: Next) -> Response {
-    if let Some(api_key) = ctx.request.headers().get("X-API-Key") {
-        if api_key.to_str().unwrap_or("") == valid_key {
-            return next(ctx).await;
-        }
+    if let Some(api_key) = ctx.request.headers().get("X-API-Key")
+        && api_key.to_str().unwrap_or("") == valid_key
+    {
+        return next(ctx).await;
     }
 
     (
@@ -397,14 +396,13 @@ async fn api_key_auth(valid_key: &'static str, ctx: RequestCtx, next: Next) -> R
 /// 🚀 内容类型验证中间件 - 无参数版本，不需要宏
 #[allow(dead_code)]
 async fn require_json(ctx: RequestCtx, next: Next) -> Response {
-    if let Some(content_type) = ctx.request.headers().get("Content-Type") {
-        if content_type
+    if let Some(content_type) = ctx.request.headers().get("Content-Type")
+        && content_type
             .to_str()
             .unwrap_or("")
             .starts_with("application/json")
-        {
-            return next(ctx).await;
-        }
+    {
+        return next(ctx).await;
     }
 
     (
@@ -712,7 +710,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 5. JWT 认证路由组 - 展示带参数中间件的使用
     println!("5️⃣ JWT authentication route group - Function-based middleware");
     {
-        let jwt_group = app.group("/jwt");
+        // 单独构建路由组，最后再挂载到 app 上，避免长期可变借用 app
+        let mut jwt_group = RouterGroup::new("/jwt");
 
         // 使用函数式中间件写法，更加简洁直观
         jwt_group.use_middleware(|ctx, next| jwt_auth("my-secret-key", ctx, next));
@@ -735,6 +734,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 "note": "使用简洁的函数式中间件实现"
             })
         });
+
+        app.mount_group(jwt_group);
     }
 
     // 6. JWT + 角色权限路由组演示 - 使用新的函数式中间件